@@ -0,0 +1,197 @@
+//! Descriptions of the pulsed CW beacons in a synthetic capture
+
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use iq_converter::units::{format_frequency, parse_duration, parse_frequency};
+
+/// The default rise and fall time of shaped pulse edges
+pub const DEFAULT_RISE: f64 = 0.001;
+
+/// The shape of the rising and falling edges of each pulse
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Envelope {
+    /// The pulse switches on and off instantly
+    Rectangular,
+
+    /// The pulse ramps up and down linearly over the given time (in seconds)
+    Linear(f64),
+
+    /// The pulse ramps up and down along half a cosine over the given time (in seconds)
+    Cosine(f64),
+}
+
+impl Envelope {
+    /// The gain of a pulse lasting `width` seconds, `t` seconds after it started
+    pub fn gain(&self, t: f64, width: f64) -> f64 {
+        if t < 0.0 || t >= width {
+            return 0.0;
+        }
+
+        let rise = match *self {
+            Envelope::Rectangular => return 1.0,
+            Envelope::Linear(rise) | Envelope::Cosine(rise) => rise.min(width / 2.0),
+        };
+        if rise <= 0.0 {
+            return 1.0;
+        }
+
+        let edge = (t.min(width - t) / rise).min(1.0);
+        match *self {
+            Envelope::Cosine(_) => 0.5 - 0.5 * (PI * edge).cos(),
+            _ => edge,
+        }
+    }
+}
+
+impl fmt::Display for Envelope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Envelope::Rectangular => write!(f, "rectangular"),
+            Envelope::Linear(rise) => write!(f, "linear:{}s", rise),
+            Envelope::Cosine(rise) => write!(f, "cosine:{}s", rise),
+        }
+    }
+}
+
+impl FromStr for Envelope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Envelope, String> {
+        let lower = s.trim().to_lowercase();
+        let mut parts = lower.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let rise = match parts.next() {
+            Some(rise) => try!(parse_duration(rise)),
+            None => DEFAULT_RISE,
+        };
+
+        match name {
+            "rectangular" | "rect" => Ok(Envelope::Rectangular),
+            "linear" | "trapezoid" => Ok(Envelope::Linear(rise)),
+            "cosine" | "cos" => Ok(Envelope::Cosine(rise)),
+            _ => Err(format!("Unknown envelope `{}` (expected rectangular, linear[:<rise>] or \
+                cosine[:<rise>])", s)),
+        }
+    }
+}
+
+/// A collar transmitting pulses of a carrier at a fixed interval
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Beacon {
+    /// The frequency of the carrier (in Hz) as an offset from the centre frequency
+    pub freq: f64,
+
+    /// The peak amplitude of the carrier, where 1.0 is the full scale of the capture
+    pub amplitude: f64,
+
+    /// The length of each pulse in seconds
+    pub width: f64,
+
+    /// The time between the start of consecutive pulses in seconds
+    pub interval: f64,
+
+    /// The time of the start of the first pulse in seconds
+    pub start: f64,
+
+    /// The shape of each pulse
+    pub envelope: Envelope,
+
+    /// The rate (in Hz per second) at which the carrier frequency drifts
+    pub drift: f64,
+}
+
+impl Beacon {
+    /// Creates a beacon at `freq` sending 20 ms pulses once a second, similar to a typical collar
+    pub fn new(freq: f64) -> Beacon {
+        Beacon {
+            freq: freq,
+            amplitude: 0.5,
+            width: 0.02,
+            interval: 1.0,
+            start: 0.0,
+            envelope: Envelope::Cosine(DEFAULT_RISE),
+            drift: 0.0,
+        }
+    }
+
+    /// Checks that the beacon describes a usable pulse train
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.width > 0.0) {
+            return Err("Pulse width must be greater than zero".into());
+        }
+        if !(self.interval >= self.width) {
+            return Err("Pulse interval must be at least the pulse width".into());
+        }
+        if !(self.amplitude >= 0.0 && self.amplitude.is_finite()) {
+            return Err("Amplitude must be finite and non-negative".into());
+        }
+        Ok(())
+    }
+
+    /// The carrier frequency `t` seconds into the capture
+    pub fn freq_at(&self, t: f64) -> f64 {
+        self.freq + self.drift * t
+    }
+
+    /// The amplitude of the carrier `t` seconds into the capture
+    pub fn amplitude_at(&self, t: f64) -> f64 {
+        let since_start = t - self.start;
+        if since_start < 0.0 {
+            return 0.0;
+        }
+        let offset = since_start - (since_start / self.interval).floor() * self.interval;
+        self.amplitude * self.envelope.gain(offset, self.width)
+    }
+}
+
+impl fmt::Display for Beacon {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "{}, {}s pulses every {}s, amplitude {}, {} edges",
+            format_frequency(self.freq), self.width, self.interval, self.amplitude, self.envelope));
+        if self.drift != 0.0 {
+            try!(write!(f, ", drifting {}/s", format_frequency(self.drift)));
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Beacon {
+    type Err = String;
+
+    /// Parses a beacon from its frequency followed by optional comma separated settings, e.g.
+    /// `130k,width=20ms,interval=1s,amplitude=0.5,envelope=cosine:2ms,drift=10Hz,start=0.5s`
+    fn from_str(s: &str) -> Result<Beacon, String> {
+        let mut parts = s.split(',');
+        let freq = try!(parse_frequency(parts.next().unwrap_or("")));
+        let mut beacon = Beacon::new(freq);
+
+        for part in parts {
+            let mut pair = part.splitn(2, '=');
+            let (key, value) = match (pair.next(), pair.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => return Err(format!("Invalid beacon setting `{}`: expected key=value", part)),
+            };
+
+            match key {
+                "width" => beacon.width = try!(parse_duration(value)),
+                "interval" => beacon.interval = try!(parse_duration(value)),
+                "start" => beacon.start = try!(parse_duration(value)),
+                "envelope" => beacon.envelope = try!(value.parse()),
+                "drift" => beacon.drift = try!(parse_frequency(value.trim_right_matches("/s"))),
+                "amplitude" => {
+                    beacon.amplitude = try!(value.parse::<f64>()
+                        .map_err(|_| format!("Invalid amplitude `{}`", value)));
+                }
+                _ => {
+                    return Err(format!("Unknown beacon setting `{}` (expected width, interval, \
+                        start, amplitude, envelope or drift)", key));
+                }
+            }
+        }
+
+        try!(beacon.validate());
+        Ok(beacon)
+    }
+}
@@ -0,0 +1,332 @@
+//! Generation of synthetic HackRF captures containing pulsed CW beacons, so that the other tools
+//! can be tested against captures with a known ground truth.
+//!
+//! Captures are written in the HackRF's cs8 format: interleaved signed 8-bit I and Q samples, where
+//! a sample of `x` in the range [-1, 1) is stored as `x * 128`. This is the format read by
+//! `IqConverter`.
+
+extern crate iq_converter;
+extern crate rand;
+
+pub mod beacon;
+
+use std::f64::consts::PI;
+use std::io;
+use std::io::prelude::*;
+
+use rand::{SeedableRng, XorShiftRng};
+use rand::distributions::{IndependentSample, Normal};
+
+use beacon::Beacon;
+
+/// The default seed for the noise generator
+pub const DEFAULT_SEED: u32 = 0x5eed;
+
+/// Settings describing everything in a capture other than the beacons
+#[derive(Copy, Clone, Debug)]
+pub struct Settings {
+    /// The sample rate of the capture
+    pub samp_rate: f64,
+
+    /// The length of the capture in seconds
+    pub duration: f64,
+
+    /// The RMS amplitude of the complex white noise added to the capture
+    pub noise: f64,
+
+    /// The DC offset added to the I and Q samples, as produced by the HackRF's LO leakage
+    pub dc: (f64, f64),
+
+    /// The seed for the noise, so that the same settings always give the same capture
+    pub seed: u32,
+}
+
+impl Settings {
+    /// Creates settings for a noiseless capture
+    pub fn new(samp_rate: f64, duration: f64) -> Settings {
+        Settings {
+            samp_rate: samp_rate,
+            duration: duration,
+            noise: 0.0,
+            dc: (0.0, 0.0),
+            seed: DEFAULT_SEED,
+        }
+    }
+
+    /// The number of I/Q samples in the capture
+    pub fn samples(&self) -> usize {
+        (self.duration * self.samp_rate).round() as usize
+    }
+}
+
+/// An iterator over the I/Q samples of a synthetic capture
+pub struct Generator {
+    beacons: Vec<Beacon>,
+    phases: Vec<f64>,
+    samp_rate: f64,
+    dc: (f64, f64),
+    noise: Normal,
+    rng: XorShiftRng,
+    index: usize,
+    len: usize,
+}
+
+impl Generator {
+    pub fn new(beacons: &[Beacon], settings: &Settings) -> Generator {
+        Generator {
+            beacons: beacons.to_vec(),
+            phases: vec![0.0; beacons.len()],
+            samp_rate: settings.samp_rate,
+            dc: settings.dc,
+            // The noise power is split evenly between I and Q
+            noise: Normal::new(0.0, settings.noise / 2.0f64.sqrt()),
+            rng: XorShiftRng::from_seed([settings.seed, 0x193a6754, 0xa8a7d469, 0x97830e05]),
+            index: 0,
+            len: settings.samples(),
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = (f32, f32);
+
+    fn next(&mut self) -> Option<(f32, f32)> {
+        if self.index >= self.len {
+            return None;
+        }
+
+        let t = self.index as f64 / self.samp_rate;
+        self.index += 1;
+
+        let (mut i, mut q) = self.dc;
+        for (beacon, phase) in self.beacons.iter().zip(&mut self.phases) {
+            let amplitude = beacon.amplitude_at(t);
+            i += amplitude * phase.cos();
+            q += amplitude * phase.sin();
+
+            // The carrier keeps running between pulses, like a real transmitter's oscillator
+            *phase = (*phase + 2.0 * PI * beacon.freq_at(t) / self.samp_rate) % (2.0 * PI);
+        }
+
+        i += self.noise.ind_sample(&mut self.rng);
+        q += self.noise.ind_sample(&mut self.rng);
+        Some((i as f32, q as f32))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.len - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+/// A pulse sent by one of the beacons in a capture
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TruePulse {
+    /// The index of the beacon that sent the pulse
+    pub beacon: usize,
+
+    /// The time the pulse started in seconds
+    pub start: f64,
+
+    /// The time the pulse ended in seconds
+    pub end: f64,
+
+    /// The carrier frequency at the middle of the pulse
+    pub freq: f64,
+
+    /// The peak amplitude of the pulse
+    pub amplitude: f64,
+}
+
+/// Lists every pulse that starts within a capture, ordered by start time
+pub fn pulses(beacons: &[Beacon], settings: &Settings) -> Vec<TruePulse> {
+    let mut pulses = vec![];
+    for (index, beacon) in beacons.iter().enumerate() {
+        let mut start = beacon.start;
+        while start < settings.duration {
+            let centre = start + beacon.width / 2.0;
+            pulses.push(TruePulse {
+                beacon: index,
+                start: start,
+                end: (start + beacon.width).min(settings.duration),
+                freq: beacon.freq_at(centre),
+                amplitude: beacon.amplitude,
+            });
+            start += beacon.interval;
+        }
+    }
+
+    pulses.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+    pulses
+}
+
+/// Converts a sample in the range [-1, 1] to the HackRF's signed 8-bit format, clipping it if it is
+/// out of range
+pub fn to_cs8(sample: f32) -> u8 {
+    (sample * 128.0).round().max(-128.0).min(127.0) as i8 as u8
+}
+
+/// Whether a sample is out of the range that `to_cs8` can represent, and so has to be clipped
+fn is_clipped(sample: f32) -> bool {
+    let value = (sample * 128.0).round();
+    value > 127.0 || value < -128.0
+}
+
+/// Writes I/Q samples to `output` in the HackRF's cs8 format, returning the number of samples that
+/// had to be clipped
+pub fn write_cs8<I, W>(samples: I, output: &mut W) -> io::Result<usize>
+    where I: Iterator<Item=(f32, f32)>, W: Write
+{
+    let mut clipped = 0;
+    let mut buffer = Vec::with_capacity(2 * 4096);
+
+    for (i, q) in samples {
+        if is_clipped(i) || is_clipped(q) {
+            clipped += 1;
+        }
+        buffer.push(to_cs8(i));
+        buffer.push(to_cs8(q));
+
+        if buffer.len() == buffer.capacity() {
+            try!(output.write_all(&buffer));
+            buffer.clear();
+        }
+    }
+
+    try!(output.write_all(&buffer));
+    Ok(clipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use iq_converter::IqConverter;
+    use iq_converter::goertzel::{FilterSettings, Magnitudes};
+    use iq_converter::pulse::{PulseDetector, DEFAULT_THRESHOLD};
+
+    use beacon::{Beacon, Envelope};
+
+    use super::*;
+
+    const SAMP_RATE: f64 = 2e6;
+
+    fn capture(beacons: &[Beacon], settings: &Settings) -> Vec<u8> {
+        let mut data = vec![];
+        write_cs8(Generator::new(beacons, settings), &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn parses_beacon_settings() {
+        let beacon: Beacon = "130k,width=30ms,interval=1.5s,amplitude=0.25,envelope=linear:2ms,\
+            drift=-10Hz/s,start=0.2s".parse().unwrap();
+
+        assert_eq!(beacon, Beacon {
+            freq: 130e3,
+            amplitude: 0.25,
+            width: 0.03,
+            interval: 1.5,
+            start: 0.2,
+            envelope: Envelope::Linear(0.002),
+            drift: -10.0,
+        });
+        assert!("130k,width".parse::<Beacon>().is_err());
+        assert!("130k,colour=red".parse::<Beacon>().is_err());
+        assert!("130k,width=2s,interval=1s".parse::<Beacon>().is_err());
+        assert!("130k,amplitude=inf".parse::<Beacon>().is_err());
+    }
+
+    #[test]
+    fn envelopes_ramp_up_and_down() {
+        let linear = Envelope::Linear(0.002);
+        let expected = [(-0.001, 0.0), (0.0005, 0.25), (0.001, 0.5), (0.01, 1.0), (0.019, 0.5),
+            (0.02, 0.0)];
+        for &(t, gain) in &expected {
+            assert!((linear.gain(t, 0.02) - gain).abs() < 1e-9, "gain at {}s", t);
+        }
+
+        assert!((Envelope::Cosine(0.002).gain(0.001, 0.02) - 0.5).abs() < 1e-9);
+        assert_eq!(Envelope::Rectangular.gain(0.0, 0.02), 1.0);
+    }
+
+    #[test]
+    fn decodes_through_iq_converter() {
+        let mut beacon = Beacon::new(100e3);
+        beacon.envelope = Envelope::Rectangular;
+        let mut settings = Settings::new(SAMP_RATE, 0.001);
+        settings.dc = (0.05, -0.05);
+
+        let data = capture(&[beacon], &settings);
+        assert_eq!(data.len(), 2 * settings.samples());
+
+        let expected = Generator::new(&[beacon], &settings);
+        for ((i, q), (x, y)) in IqConverter::new(data).zip(expected) {
+            assert!((i - x).abs() <= 0.5 / 128.0 && (q - y).abs() <= 0.5 / 128.0);
+        }
+    }
+
+    #[test]
+    fn noise_is_repeatable() {
+        let mut settings = Settings::new(SAMP_RATE, 0.01);
+        settings.noise = 0.1;
+
+        let first = capture(&[], &settings);
+        assert_eq!(first, capture(&[], &settings));
+
+        settings.seed += 1;
+        assert!(first != capture(&[], &settings));
+
+        // The noise has the requested RMS amplitude
+        let power = IqConverter::new(first).map(|(i, q)| (i * i + q * q) as f64).sum::<f64>()
+            / settings.samples() as f64;
+        assert!((power.sqrt() - 0.1).abs() < 0.005, "RMS {}", power.sqrt());
+    }
+
+    #[test]
+    fn pulses_match_ground_truth() {
+        let mut beacon = Beacon::new(130e3);
+        beacon.start = 0.25;
+        beacon.interval = 0.5;
+        let mut settings = Settings::new(SAMP_RATE, 2.0);
+        settings.noise = 0.05;
+
+        let truth = pulses(&[beacon], &settings);
+        assert_eq!(truth.len(), 4);
+
+        let filter = FilterSettings::new(100);
+        let rate = filter.output_rate(SAMP_RATE);
+        let mut detector = PulseDetector::new(rate, DEFAULT_THRESHOLD);
+        let magnitudes = Magnitudes::new(IqConverter::new(capture(&[beacon], &settings)),
+            SAMP_RATE as f32, 130e3, filter);
+        let found: Vec<_> = magnitudes.filter_map(|x| detector.process(x)).collect();
+
+        // The noise floor is still settling during the first pulse, so it may be missed
+        assert!(found.len() >= 3);
+        for pulse in &found {
+            let start = pulse.start as f64 / rate;
+            let end = pulse.end as f64 / rate;
+            assert!(truth.iter().any(|x| (x.start - start).abs() < 0.002 &&
+                (x.end - end).abs() < 0.002), "unexpected pulse at {}s to {}s", start, end);
+        }
+    }
+
+    #[test]
+    fn drift_moves_the_carrier() {
+        let mut beacon = Beacon::new(130e3);
+        beacon.drift = 100.0;
+        let settings = Settings::new(SAMP_RATE, 10.0);
+
+        let truth = pulses(&[beacon], &settings);
+        assert!((truth[9].freq - (130e3 + 100.0 * 9.01)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn counts_samples_that_round_out_of_range() {
+        // 127.4 / 128 rounds to 127, so fits, while 127.5 / 128 rounds to 128 and is clipped, even
+        // though both are under 1. -1 is exactly -128 and fits.
+        let samples = vec![(127.4 / 128.0, 0.0), (127.5 / 128.0, 0.0), (-1.0, -1.0),
+            (0.0, -128.5 / 128.0), (1.0, 1.0)];
+        let mut data = vec![];
+        assert_eq!(write_cs8(samples.into_iter(), &mut data).unwrap(), 3);
+        assert_eq!(data, vec![127, 0, 127, 0, 128, 128, 0, 128, 127, 127]);
+    }
+}
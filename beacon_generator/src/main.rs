@@ -0,0 +1,140 @@
+//! A tool for generating synthetic HackRF captures of pulsed collar beacons
+
+extern crate clap;
+extern crate beacon_generator;
+extern crate iq_converter;
+
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::fs::File;
+use std::path::Path;
+
+use clap::{App, Arg};
+
+use iq_converter::cli::{exit_with_error, parse_arg, status};
+use iq_converter::units::{parse_duration, parse_rate};
+
+use beacon_generator::{pulses, write_cs8, Generator, Settings, TruePulse, DEFAULT_SEED};
+use beacon_generator::beacon::Beacon;
+
+/// The file name used to write the capture to stdout
+const STDOUT: &'static str = "-";
+
+fn main() {
+    let matches = App::new("Beacon Capture Generator")
+        .arg_from_usage("<output> 'Name of the cs8 file to write, or - to write to stdout'")
+        .arg(Arg::from_usage("-b, --beacon [beacon]... 'Add a beacon, given as its frequency \
+            offset followed by optional settings, e.g. \
+            130k,width=20ms,interval=1s,amplitude=0.5,envelope=cosine:1ms,drift=10Hz/s,start=0.2s'")
+            .use_delimiter(false).number_of_values(1).allow_hyphen_values(true))
+        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set the sample rate, e.g. 2e6, 2M or 2MS/s \
+            (default: 2MS/s)'")
+        .arg_from_usage("-d, --duration [duration] 'Set the length of the capture, e.g. 10s or \
+            1.5min (default: 10s)'")
+        .arg_from_usage("-n, --noise [noise] 'Set the RMS amplitude of the added noise, where 1.0 \
+            is full scale (default: 0.01)'")
+        .arg(Arg::from_usage("--dc [dc] 'Set the DC offset added to the I and Q samples, e.g. \
+            0.02,-0.01 (default: 0,0)'").allow_hyphen_values(true))
+        .arg_from_usage("--seed [seed] 'Set the seed for the noise (default: 24301)'")
+        .arg_from_usage("--truth [truth] 'Name of the CSV file to write the pulses to. \
+            (default: <output>.truth.csv)'")
+        .get_matches();
+
+    let output = matches.value_of("output").unwrap();
+
+    let beacons: Vec<Beacon> = matches.values_of("beacon").map_or(vec![], |values| {
+        values.map(|x| x.parse::<Beacon>().unwrap_or_else(|e| exit_with_error(&e))).collect()
+    });
+
+    let parse_amplitude = |x: &str| {
+        match x.trim().parse::<f64>() {
+            Ok(amplitude) if amplitude >= 0.0 && amplitude.is_finite() => Ok(amplitude),
+            _ => Err(format!("Invalid amplitude `{}`: expected a finite non-negative number", x)),
+        }
+    };
+    let parse_dc = |x: &str| {
+        let mut parts = x.split(',').map(|x| x.trim().parse::<f64>());
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(i)), Some(Ok(q)), None) if i.is_finite() && q.is_finite() => Ok((i, q)),
+            _ => Err(format!("Invalid DC offset `{}`: expected two finite numbers, e.g. \
+                0.02,-0.01", x)),
+        }
+    };
+
+    let parse_seed = |x: &str| {
+        x.trim().parse::<u32>()
+            .map_err(|_| format!("Invalid seed `{}`: expected a whole number below 2^32", x))
+    };
+
+    let settings = Settings {
+        samp_rate: parse_arg(&matches, "samp_rate", parse_rate).unwrap_or(2e6),
+        duration: parse_arg(&matches, "duration", parse_duration).unwrap_or(10.0),
+        noise: parse_arg(&matches, "noise", parse_amplitude).unwrap_or(0.01),
+        dc: parse_arg(&matches, "dc", parse_dc).unwrap_or((0.0, 0.0)),
+        seed: parse_arg(&matches, "seed", parse_seed).unwrap_or(DEFAULT_SEED),
+    };
+
+    // Status messages go to stderr if stdout is being used for the capture
+    let to_stderr = output == STDOUT;
+    if beacons.is_empty() {
+        status(to_stderr, "No beacons given, the capture will only contain noise");
+    }
+    for beacon in &beacons {
+        status(to_stderr, &format!("Beacon at {}", beacon));
+    }
+
+    let truth_path = matches.value_of("truth").map(String::from).unwrap_or_else(|| {
+        if output == STDOUT { "capture.truth.csv".into() } else { format!("{}.truth.csv", output) }
+    });
+
+    match generate(output, Path::new(&truth_path), &beacons, &settings) {
+        Ok(clipped) => {
+            status(to_stderr, &format!("Wrote {} samples ({} s at {} samples/s)",
+                settings.samples(), settings.duration, settings.samp_rate));
+            if clipped > 0 {
+                status(to_stderr, &format!("Warning: {} samples were clipped, reduce the beacon \
+                    amplitudes or noise", clipped));
+            }
+        }
+        Err(e) => exit_with_error(&format!("Unable to generate {}: {}", output, e)),
+    }
+}
+
+/// Writes the capture and its ground truth, returning the number of samples that were clipped
+fn generate(output: &str, truth_path: &Path, beacons: &[Beacon], settings: &Settings)
+    -> Result<usize, Box<Error>>
+{
+    try!(write_truth(truth_path, &pulses(beacons, settings)));
+
+    let samples = Generator::new(beacons, settings);
+    let clipped = if output == STDOUT {
+        let stdout = io::stdout();
+        let mut writer = BufWriter::new(stdout.lock());
+        let clipped = try!(write_cs8(samples, &mut writer));
+        try!(writer.flush());
+        clipped
+    }
+    else {
+        let mut writer = BufWriter::new(try!(File::create(output)));
+        let clipped = try!(write_cs8(samples, &mut writer));
+        try!(writer.flush());
+        clipped
+    };
+
+    Ok(clipped)
+}
+
+/// Writes the start, end, frequency and amplitude of each pulse to a CSV file
+fn write_truth(path: &Path, pulses: &[TruePulse]) -> io::Result<()> {
+    let mut output = BufWriter::new(try!(File::create(path)));
+    try!(writeln!(output, "beacon,start,end,frequency,amplitude"));
+
+    for pulse in pulses {
+        try!(writeln!(output, "{},{},{},{},{}", pulse.beacon, pulse.start, pulse.end, pulse.freq,
+            pulse.amplitude));
+    }
+
+    output.flush()
+}
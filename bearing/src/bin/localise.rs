@@ -0,0 +1,130 @@
+//! A tool for estimating the position of a collar from bearings taken at several places
+
+extern crate clap;
+extern crate bearing;
+extern crate iq_converter;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use clap::App;
+
+use iq_converter::cli::exit_with_error;
+
+use bearing::localise::{locate, read_observations, Ellipse, LocalFrame, Location, Observation,
+    DEFAULT_CONFIDENCE};
+
+/// The number of points used to draw the uncertainty ellipse
+const OUTLINE_POINTS: usize = 72;
+
+fn main() {
+    let matches = App::new("Collar Localiser")
+        .arg_from_usage("<observations> 'The path to a CSV file of latitude, longitude, bearing \
+            and bearing uncertainty (standard deviation), all in degrees'")
+        .arg_from_usage("-o, --output [output] 'Name of the GeoJSON file to write the estimate, \
+            its uncertainty ellipse and the bearings to. (default: <observations>.geojson)'")
+        .arg_from_usage("--csv [csv] 'Name of the CSV file to write the estimate to. \
+            (default: <observations>.location.csv)'")
+        .arg_from_usage("-c, --confidence [confidence] 'Set the probability that the collar lies \
+            within the uncertainty ellipse (default: 0.95)'")
+        .get_matches();
+
+    let input = Path::new(matches.value_of("observations").unwrap());
+    let geojson_path = matches.value_of("output").map(PathBuf::from)
+        .unwrap_or_else(|| input.with_extension("geojson"));
+    let csv_path = matches.value_of("csv").map(PathBuf::from)
+        .unwrap_or_else(|| input.with_extension("location.csv"));
+
+    let confidence = matches.value_of("confidence").map_or(DEFAULT_CONFIDENCE, |x| {
+        x.trim().parse::<f64>().unwrap_or_else(|_| {
+            exit_with_error(&format!("Invalid confidence `{}`: expected a number", x))
+        })
+    });
+    if !(confidence > 0.0 && confidence < 1.0) {
+        exit_with_error("Confidence must be between 0 and 1");
+    }
+
+    let observations = read_observations(input).unwrap_or_else(|e| exit_with_error(&e));
+    let location = locate(&observations).unwrap_or_else(|| {
+        exit_with_error(&format!("Unable to locate the collar from {} observations: at least two \
+            bearings that aren't parallel are needed", observations.len()))
+    });
+    let ellipse = location.ellipse(confidence);
+
+    println!("Location: {:.6}, {:.6} from {} bearings", location.lat, location.lon,
+        observations.len());
+    println!("{:.0}% ellipse: {:.1} m x {:.1} m, major axis at {:.0}°", confidence * 100.0,
+        ellipse.semi_major, ellipse.semi_minor, ellipse.orientation);
+    if location.behind() > 0 {
+        println!("Warning: {} bearings point away from the estimate", location.behind());
+    }
+    if location.rms_error > 3.0 {
+        println!("Warning: the bearings disagree by {:.1} times their uncertainty on average",
+            location.rms_error);
+    }
+
+    if let Err(e) = write_geojson(&geojson_path, &observations, &location, &ellipse) {
+        exit_with_error(&format!("Unable to write {}: {}", geojson_path.display(), e));
+    }
+    if let Err(e) = write_csv(&csv_path, &observations, &location, &ellipse) {
+        exit_with_error(&format!("Unable to write {}: {}", csv_path.display(), e));
+    }
+}
+
+/// Writes the estimate, its uncertainty ellipse and a line along each bearing as GeoJSON
+fn write_geojson(path: &Path, observations: &[Observation], location: &Location,
+    ellipse: &Ellipse) -> io::Result<()>
+{
+    let mut features = vec![];
+
+    features.push(format!("{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"Point\",\
+        \"coordinates\":[{},{}]}},\"properties\":{{\"kind\":\"estimate\",\"semi_major\":{},\
+        \"semi_minor\":{},\"orientation\":{},\"confidence\":{},\"rms_error\":{}}}}}",
+        location.lon, location.lat, ellipse.semi_major, ellipse.semi_minor, ellipse.orientation,
+        ellipse.confidence, location.rms_error));
+
+    let mut outline = ellipse.outline(location.lat, location.lon, OUTLINE_POINTS);
+    let first = outline[0];
+    outline.push(first);
+    features.push(format!("{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"Polygon\",\
+        \"coordinates\":[[{}]]}},\"properties\":{{\"kind\":\"ellipse\",\"confidence\":{}}}}}",
+        coordinates(&outline), ellipse.confidence));
+
+    // Draw each bearing far enough to pass through the ellipse
+    for (x, range) in observations.iter().zip(&location.ranges) {
+        let length = range.max(0.0) + ellipse.semi_major;
+        let bearing = x.bearing.to_radians();
+        let end = LocalFrame::new(x.lat, x.lon)
+            .to_global(length * bearing.sin(), length * bearing.cos());
+        features.push(format!("{{\"type\":\"Feature\",\"geometry\":{{\"type\":\"LineString\",\
+            \"coordinates\":[{}]}},\"properties\":{{\"kind\":\"bearing\",\"bearing\":{},\
+            \"uncertainty\":{}}}}}", coordinates(&[(x.lat, x.lon), end]), x.bearing,
+            x.uncertainty));
+    }
+
+    let mut output = BufWriter::new(try!(File::create(path)));
+    try!(writeln!(output, "{{\"type\":\"FeatureCollection\",\"features\":[\n{}\n]}}",
+        features.join(",\n")));
+    output.flush()
+}
+
+/// Formats latitude and longitude pairs as GeoJSON positions, which are longitude first
+fn coordinates(points: &[(f64, f64)]) -> String {
+    points.iter().map(|&(lat, lon)| format!("[{},{}]", lon, lat)).collect::<Vec<_>>().join(",")
+}
+
+/// Writes the estimate and its uncertainty ellipse to a CSV file
+fn write_csv(path: &Path, observations: &[Observation], location: &Location, ellipse: &Ellipse)
+    -> io::Result<()>
+{
+    let mut output = BufWriter::new(try!(File::create(path)));
+    try!(writeln!(output, "latitude,longitude,semi_major,semi_minor,orientation,confidence,\
+        observations,rms_error"));
+    try!(writeln!(output, "{},{},{},{},{},{},{},{}", location.lat, location.lon,
+        ellipse.semi_major, ellipse.semi_minor, ellipse.orientation, ellipse.confidence,
+        observations.len(), location.rms_error));
+    output.flush()
+}
@@ -0,0 +1,129 @@
+//! Time-stamped heading logs recorded while the antenna rotates
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+/// The headings of the antenna over time
+pub struct HeadingLog {
+    /// The time (in seconds from the start of the capture) and heading (in degrees clockwise from
+    /// north, in the range [0, 360)) of each entry, ordered by time
+    entries: Vec<(f64, f64)>,
+}
+
+impl HeadingLog {
+    /// Creates a log from time and heading pairs (in seconds and degrees), which must be finite.
+    /// The entries are sorted by time and the headings wrapped to [0, 360).
+    pub fn new(mut entries: Vec<(f64, f64)>) -> HeadingLog {
+        for entry in &mut entries {
+            entry.1 = wrap_degrees(entry.1);
+        }
+        entries.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        HeadingLog { entries: entries }
+    }
+
+    /// Reads a CSV file of time (in seconds) and yaw. Lines that don't start with two numbers, such
+    /// as a header, are skipped, while lines with a time or yaw that isn't finite (e.g. `nan`) are
+    /// rejected. `offset` is subtracted from each time to give the time from the start of the
+    /// capture, and yaw is converted from radians if `radians` is set.
+    pub fn read(path: &Path, offset: f64, radians: bool) -> Result<HeadingLog, String> {
+        let file = try!(File::open(path)
+            .map_err(|e| format!("Unable to open {}: {}", path.display(), e)));
+
+        let mut entries = vec![];
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = try!(line.map_err(|e| format!("Unable to read {}: {}", path.display(), e)));
+            let mut fields = line.split(',').map(|x| x.trim().parse::<f64>());
+            if let (Some(Ok(time)), Some(Ok(yaw))) = (fields.next(), fields.next()) {
+                if !time.is_finite() || !yaw.is_finite() {
+                    return Err(format!("Invalid entry on line {} of {}: the time and yaw must be \
+                        finite", number + 1, path.display()));
+                }
+                let heading = if radians { yaw.to_degrees() } else { yaw };
+                entries.push((time - offset, heading));
+            }
+        }
+
+        if entries.len() < 2 {
+            return Err(format!("{} must contain at least two time,yaw entries", path.display()));
+        }
+        Ok(HeadingLog::new(entries))
+    }
+
+    /// The time range covered by the log
+    pub fn span(&self) -> (f64, f64) {
+        (self.entries[0].0, self.entries[self.entries.len() - 1].0)
+    }
+
+    /// Interpolates the heading at time `t`, taking the shorter way around the circle between
+    /// entries. Returns `None` if `t` is outside of the log.
+    pub fn heading_at(&self, t: f64) -> Option<f64> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let (first, last) = self.span();
+        if t < first || t > last {
+            return None;
+        }
+
+        let index = match self.entries.binary_search_by(|x| x.0.partial_cmp(&t).unwrap()) {
+            Ok(index) => return Some(self.entries[index].1),
+            Err(index) => index,
+        };
+
+        let (t0, h0) = self.entries[index - 1];
+        let (t1, h1) = self.entries[index];
+        let fraction = if t1 > t0 { (t - t0) / (t1 - t0) } else { 0.0 };
+        Some(wrap_degrees(h0 + angle_difference(h1, h0) * fraction))
+    }
+}
+
+/// Wraps an angle in degrees to the range [0, 360)
+pub fn wrap_degrees(angle: f64) -> f64 {
+    let wrapped = angle % 360.0;
+    if wrapped < 0.0 { wrapped + 360.0 } else { wrapped }
+}
+
+/// The signed difference `a - b` between two angles in degrees, in the range [-180, 180)
+pub fn angle_difference(a: f64, b: f64) -> f64 {
+    wrap_degrees(a - b + 180.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::read_temp_file;
+
+    use super::*;
+
+    fn read(name: &str, contents: &str) -> Result<HeadingLog, String> {
+        read_temp_file(&format!("heading_{}", name), contents, |path| {
+            HeadingLog::read(path, 10.0, false)
+        })
+    }
+
+    #[test]
+    fn interpolates_headings_across_north() {
+        let log = HeadingLog::new(vec![(0.0, 350.0), (1.0, 10.0), (2.0, 20.0)]);
+        assert_eq!(log.heading_at(-0.1), None);
+        assert!((log.heading_at(0.25).unwrap() - 355.0).abs() < 1e-9);
+        assert!(log.heading_at(0.5).unwrap() < 1e-9 || log.heading_at(0.5).unwrap() > 359.9);
+        assert!((log.heading_at(1.5).unwrap() - 15.0).abs() < 1e-9);
+        assert_eq!(log.heading_at(2.5), None);
+    }
+
+    #[test]
+    fn reads_logs_skipping_headers() {
+        let log = read("headings", "time,yaw\n12,-90\n10,0\n11,90\n").unwrap();
+        assert_eq!(log.span(), (0.0, 2.0));
+        assert_eq!(log.heading_at(1.0), Some(90.0));
+        assert_eq!(log.heading_at(2.0), Some(270.0));
+    }
+
+    #[test]
+    fn rejects_entries_that_are_not_finite() {
+        let error = read("nan_time", "time,yaw\n10,0\nnan,90\n12,180\n").err().unwrap();
+        assert!(error.contains("line 3"), "{}", error);
+        assert!(read("inf_yaw", "10,0\n11,inf\n12,180\n").is_err());
+    }
+}
@@ -0,0 +1,292 @@
+//! Estimation of the bearing to a collar from a capture recorded while a directional antenna
+//! rotates.
+//!
+//! The strength of each pulse is measured with the Goertzel filter at the collar frequency and
+//! paired with the heading of the antenna when the pulse arrived. The antenna's gain pattern is
+//! approximated by `a + b cos(heading - bearing)`, which is fitted to the pulses by least squares
+//! to find the heading of maximum gain.
+
+extern crate iq_converter;
+
+pub mod heading;
+pub mod localise;
+pub mod telemetry;
+pub mod timestamp;
+
+#[cfg(test)]
+mod test_util;
+
+use std::f64;
+
+use iq_converter::goertzel::{FilterSettings, Magnitudes};
+use iq_converter::pulse::PulseDetector;
+
+use heading::{angle_difference, wrap_degrees, HeadingLog};
+
+/// The strength of a single pulse
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PulseStrength {
+    /// The time of the middle of the pulse in seconds from the start of the capture
+    pub time: f64,
+
+    /// The mean Goertzel magnitude over the pulse
+    pub magnitude: f64,
+}
+
+/// Finds the pulses in an I/Q stream sampled at `samp_rate` and measures their strength at `freq`.
+/// Pulses are found where the magnitude is at least `threshold` dB above the noise floor.
+pub fn measure_pulses<I>(input: I, samp_rate: f64, freq: f64, settings: FilterSettings,
+    threshold: f32) -> Vec<PulseStrength>
+    where I: Iterator<Item=(f32, f32)>
+{
+    let rate = settings.output_rate(samp_rate);
+    let magnitudes: Vec<_> = Magnitudes::new(input, samp_rate as f32, freq as f32, settings)
+        .collect();
+
+    let mut detector = PulseDetector::new(rate, threshold);
+    let mut pulses: Vec<_> = magnitudes.iter().filter_map(|&x| detector.process(x)).collect();
+    pulses.extend(detector.finish());
+
+    // Magnitudes are centred on their block, so shift the times to match
+    let block_centre = settings.block_size as f64 / 2.0 / samp_rate;
+    pulses.iter().map(|pulse| {
+        let magnitudes = &magnitudes[pulse.start..pulse.end];
+        PulseStrength {
+            time: (pulse.start + pulse.end) as f64 / 2.0 / rate + block_centre,
+            magnitude: magnitudes.iter().map(|&x| x as f64).sum::<f64>() / magnitudes.len() as f64,
+        }
+    }).collect()
+}
+
+/// A pulse paired with the heading of the antenna when it arrived
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Observation {
+    pub time: f64,
+    pub magnitude: f64,
+
+    /// The heading of the antenna in degrees clockwise from north
+    pub heading: f64,
+}
+
+/// Pairs each pulse with the heading at the time it arrived, dropping pulses outside of the log
+pub fn align(pulses: &[PulseStrength], log: &HeadingLog) -> Vec<Observation> {
+    pulses.iter().filter_map(|pulse| {
+        log.heading_at(pulse.time).map(|heading| {
+            Observation {
+                time: pulse.time,
+                magnitude: pulse.magnitude,
+                heading: heading,
+            }
+        })
+    }).collect()
+}
+
+/// A bearing fitted to a set of observations
+#[derive(Copy, Clone, Debug)]
+pub struct Bearing {
+    /// The heading of maximum gain in degrees clockwise from north
+    pub bearing: f64,
+
+    /// The standard error of the bearing in degrees, used as its confidence. This assumes the
+    /// antenna pattern is well described by the fitted cosine.
+    pub std_error: f64,
+
+    /// The fraction of the variation in pulse magnitude explained by the fit
+    pub r_squared: f64,
+
+    /// The ratio of the strongest to the weakest magnitude predicted by the fit
+    pub front_to_back: f64,
+
+    /// The size (in degrees) of the arc of headings covered by the observations. Less than a full
+    /// rotation makes the fit less reliable, and under 180 degrees it should not be trusted.
+    pub coverage: f64,
+
+    /// The number of observations used
+    pub count: usize,
+}
+
+/// Fits the heading of maximum gain to a set of observations. At least four observations are
+/// needed, and `None` is returned if the magnitude doesn't vary with heading.
+pub fn fit_bearing(observations: &[Observation]) -> Option<Bearing> {
+    let n = observations.len();
+    if n < 4 {
+        return None;
+    }
+
+    // Linear least squares for magnitude = a + b cos(heading) + c sin(heading)
+    let rows: Vec<_> = observations.iter().map(|x| {
+        let theta = x.heading.to_radians();
+        ([1.0, theta.cos(), theta.sin()], x.magnitude)
+    }).collect();
+
+    let mut normal = [[0.0; 3]; 3];
+    let mut rhs = [0.0; 3];
+    for &(ref row, y) in &rows {
+        for i in 0..3 {
+            for j in 0..3 {
+                normal[i][j] += row[i] * row[j];
+            }
+            rhs[i] += row[i] * y;
+        }
+    }
+
+    let inverse = match invert3(&normal) {
+        Some(inverse) => inverse,
+        None => return None,
+    };
+    let mut coeffs = [0.0; 3];
+    for i in 0..3 {
+        coeffs[i] = (0..3).map(|j| inverse[i][j] * rhs[j]).sum();
+    }
+    let (a, b, c) = (coeffs[0], coeffs[1], coeffs[2]);
+
+    // Allow for rounding error when the magnitudes don't vary
+    let amplitude = b.hypot(c);
+    if !(amplitude > 1e-9 * a.abs()) {
+        return None;
+    }
+
+    let mean = rows.iter().map(|x| x.1).sum::<f64>() / n as f64;
+    let sse: f64 = rows.iter().map(|&(ref row, y)| {
+        let fitted = a + b * row[1] + c * row[2];
+        (y - fitted) * (y - fitted)
+    }).sum();
+    let sst: f64 = rows.iter().map(|x| (x.1 - mean) * (x.1 - mean)).sum();
+
+    // Propagate the covariance of (b, c) through atan2 to get the variance of the bearing
+    let variance = sse / (n - 3).max(1) as f64;
+    let (var_b, var_c, cov_bc) = (variance * inverse[1][1], variance * inverse[2][2],
+        variance * inverse[1][2]);
+    let var_bearing = (c * c * var_b + b * b * var_c - 2.0 * b * c * cov_bc) / amplitude.powi(4);
+
+    Some(Bearing {
+        bearing: wrap_degrees(c.atan2(b).to_degrees()),
+        std_error: var_bearing.max(0.0).sqrt().to_degrees(),
+        r_squared: if sst > 0.0 { 1.0 - sse / sst } else { 0.0 },
+        front_to_back: match a > amplitude {
+            true => (a + amplitude) / (a - amplitude),
+            false => f64::INFINITY,
+        },
+        coverage: coverage(observations),
+        count: n,
+    })
+}
+
+/// The size of the arc of headings covered by the observations, i.e. 360 degrees minus the largest
+/// gap between consecutive headings
+fn coverage(observations: &[Observation]) -> f64 {
+    let mut headings: Vec<_> = observations.iter().map(|x| x.heading).collect();
+    headings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let wrap_gap = headings[0] + 360.0 - headings[headings.len() - 1];
+    let largest_gap = headings.windows(2).map(|x| x[1] - x[0]).fold(wrap_gap, f64::max);
+    360.0 - largest_gap
+}
+
+/// Inverts a 3x3 matrix, returning `None` if it is singular
+fn invert3(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+
+    let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2)
+        + m[0][2] * cofactor(1, 2, 0, 1);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    let adjugate = [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ];
+
+    let mut inverse = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            inverse[i][j] = adjugate[i][j] / det;
+        }
+    }
+    Some(inverse)
+}
+
+/// The smallest angle (in degrees) between two bearings
+pub fn bearing_error(a: f64, b: f64) -> f64 {
+    angle_difference(a, b).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate beacon_generator;
+
+    use self::beacon_generator::{Generator, Settings};
+    use self::beacon_generator::beacon::Beacon;
+
+    use iq_converter::pulse::DEFAULT_THRESHOLD;
+
+    use super::*;
+
+    /// Observations from an antenna with a cardioid pattern pointing at `bearing`, rotating once
+    /// every `count` pulses
+    fn cardioid(bearing: f64, count: usize) -> Vec<Observation> {
+        (0..count).map(|i| {
+            let heading = i as f64 * 360.0 / count as f64;
+            let gain = 0.5 + 0.5 * (heading - bearing).to_radians().cos();
+            Observation { time: i as f64, magnitude: 0.1 + gain, heading: heading }
+        }).collect()
+    }
+
+    #[test]
+    fn fits_bearing_of_maximum_gain() {
+        for &bearing in &[0.0, 45.0, 200.0, 359.0] {
+            let fit = fit_bearing(&cardioid(bearing, 36)).unwrap();
+            assert!(bearing_error(fit.bearing, bearing) < 1e-6, "{} vs {}", fit.bearing, bearing);
+            assert!(fit.std_error < 1e-3);
+            assert!((fit.r_squared - 1.0).abs() < 1e-9);
+            assert!((fit.coverage - 350.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn noisy_fit_has_larger_error() {
+        let mut observations = cardioid(120.0, 36);
+        for (i, x) in observations.iter_mut().enumerate() {
+            x.magnitude += if i % 2 == 0 { 0.1 } else { -0.1 } * (i % 3) as f64;
+        }
+
+        let fit = fit_bearing(&observations).unwrap();
+        assert!(bearing_error(fit.bearing, 120.0) < 10.0);
+        assert!(fit.std_error > 0.1 && fit.std_error < 10.0);
+        assert!(fit.r_squared < 1.0);
+    }
+
+    #[test]
+    fn rejects_flat_or_sparse_observations() {
+        assert!(fit_bearing(&cardioid(10.0, 3)).is_none());
+
+        let mut flat = cardioid(10.0, 12);
+        for x in &mut flat {
+            x.magnitude = 1.0;
+        }
+        assert!(fit_bearing(&flat).is_none());
+    }
+
+    #[test]
+    fn measures_generated_pulses() {
+        let mut beacon = Beacon::new(130e3);
+        beacon.start = 0.3;
+        beacon.interval = 0.25;
+        let mut settings = Settings::new(2e6, 2.0);
+        settings.noise = 0.02;
+
+        let pulses = measure_pulses(Generator::new(&[beacon], &settings), 2e6, 130e3,
+            FilterSettings::new(100), DEFAULT_THRESHOLD);
+
+        assert!(pulses.len() >= 6);
+        for pulse in &pulses {
+            let offset = (pulse.time - beacon.start - beacon.width / 2.0) % beacon.interval;
+            assert!(offset.abs() < 0.002, "pulse at {}s", pulse.time);
+            assert!((pulse.magnitude - beacon.amplitude).abs() < 0.05);
+        }
+    }
+}
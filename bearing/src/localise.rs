@@ -0,0 +1,374 @@
+//! Estimation of a collar's position from bearings taken at several places.
+//!
+//! Observations are projected onto a plane tangent to the earth at their mean position, which is
+//! accurate to well within the bearing uncertainty over the few kilometres a drone covers. The
+//! position is the weighted least squares intersection of the bearing lines, where each line is
+//! weighted by the inverse of its positional variance at the estimated range. The ranges depend on
+//! the position, so the fit is repeated a few times with updated weights.
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+use heading::wrap_degrees;
+
+/// The mean radius of the earth in metres
+const EARTH_RADIUS: f64 = 6371008.8;
+
+/// The number of times the weights are updated from the estimated ranges
+const ITERATIONS: usize = 10;
+
+/// The smallest range (in metres) used when weighting an observation, so that an observation
+/// taken right on top of the estimate doesn't outweigh all of the others
+const MIN_RANGE: f64 = 1.0;
+
+/// The default probability that the collar lies within the uncertainty ellipse
+pub const DEFAULT_CONFIDENCE: f64 = 0.95;
+
+/// A bearing to the collar taken from a known position
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Observation {
+    /// The latitude and longitude of the antenna in degrees
+    pub lat: f64,
+    pub lon: f64,
+
+    /// The bearing in degrees clockwise from north
+    pub bearing: f64,
+
+    /// The standard deviation of the bearing in degrees
+    pub uncertainty: f64,
+}
+
+/// Reads a CSV file of latitude, longitude, bearing and bearing uncertainty (all in degrees). The
+/// first line is skipped if it doesn't start with a number, since it's a header, and blank lines
+/// are skipped, but any other line that isn't four finite numbers is an error.
+pub fn read_observations(path: &Path) -> Result<Vec<Observation>, String> {
+    let file = try!(File::open(path)
+        .map_err(|e| format!("Unable to open {}: {}", path.display(), e)));
+
+    let mut observations = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = try!(line.map_err(|e| format!("Unable to read {}: {}", path.display(), e)));
+        let is_header = index == 0 && line.split(',').next()
+            .map_or(true, |x| x.trim().parse::<f64>().is_err());
+        if is_header || line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = line.split(',').take(4).map(|x| x.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>().unwrap_or(vec![]);
+        if fields.len() != 4 || !fields.iter().all(|x| x.is_finite()) {
+            return Err(format!("Invalid observation on line {} of {}: expected a latitude, \
+                longitude, bearing and uncertainty", index + 1, path.display()));
+        }
+
+        let (lat, lon, bearing, uncertainty) = (fields[0], fields[1], fields[2], fields[3]);
+        if !(uncertainty > 0.0) {
+            return Err(format!("Invalid uncertainty `{}` on line {} of {}: expected a positive \
+                number of degrees", uncertainty, index + 1, path.display()));
+        }
+        observations.push(Observation {
+            lat: lat,
+            lon: lon,
+            bearing: wrap_degrees(bearing),
+            uncertainty: uncertainty,
+        });
+    }
+
+    Ok(observations)
+}
+
+/// A local east/north coordinate system in metres, centred on a reference position
+#[derive(Copy, Clone, Debug)]
+pub struct LocalFrame {
+    lat: f64,
+    lon: f64,
+}
+
+impl LocalFrame {
+    pub fn new(lat: f64, lon: f64) -> LocalFrame {
+        LocalFrame { lat: lat, lon: lon }
+    }
+
+    /// Converts a latitude and longitude to metres east and north of the reference position
+    pub fn to_local(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let east = (lon - self.lon).to_radians() * EARTH_RADIUS * self.lat.to_radians().cos();
+        let north = (lat - self.lat).to_radians() * EARTH_RADIUS;
+        (east, north)
+    }
+
+    /// Converts metres east and north of the reference position to a latitude and longitude
+    pub fn to_global(&self, east: f64, north: f64) -> (f64, f64) {
+        let lat = self.lat + (north / EARTH_RADIUS).to_degrees();
+        let lon = self.lon + (east / (EARTH_RADIUS * self.lat.to_radians().cos())).to_degrees();
+        (lat, lon)
+    }
+}
+
+/// An estimated position of the collar
+#[derive(Clone, Debug)]
+pub struct Location {
+    /// The latitude and longitude of the estimate in degrees
+    pub lat: f64,
+    pub lon: f64,
+
+    /// The covariance of the east and north position in square metres, as [ee, en, nn]
+    pub covariance: [f64; 3],
+
+    /// The distance (in metres) from each observation to the estimate along its bearing. This is
+    /// negative if the collar is behind the observation, which suggests a bad bearing.
+    pub ranges: Vec<f64>,
+
+    /// The root mean square of each observation's bearing error, in standard deviations
+    pub rms_error: f64,
+}
+
+impl Location {
+    /// The uncertainty ellipse containing the collar with probability `confidence`
+    pub fn ellipse(&self, confidence: f64) -> Ellipse {
+        let (ee, en, nn) = (self.covariance[0], self.covariance[1], self.covariance[2]);
+
+        // Eigenvalues of the 2x2 covariance matrix
+        let mean = (ee + nn) / 2.0;
+        let spread = (((ee - nn) / 2.0).powi(2) + en * en).sqrt();
+        let (major, minor) = (mean + spread, (mean - spread).max(0.0));
+
+        // Chi-squared quantile with two degrees of freedom
+        let scale = (-2.0 * (1.0 - confidence).ln()).sqrt();
+
+        // The direction of the major axis, clockwise from north
+        let angle = 0.5 * (2.0 * en).atan2(nn - ee);
+
+        Ellipse {
+            semi_major: scale * major.sqrt(),
+            semi_minor: scale * minor.sqrt(),
+            orientation: wrap_degrees(angle.to_degrees()) % 180.0,
+            confidence: confidence,
+        }
+    }
+
+    /// The number of observations that point away from the estimate
+    pub fn behind(&self) -> usize {
+        self.ranges.iter().filter(|&&x| x < 0.0).count()
+    }
+}
+
+/// An uncertainty ellipse around a position
+#[derive(Copy, Clone, Debug)]
+pub struct Ellipse {
+    /// The lengths of the semi-axes in metres
+    pub semi_major: f64,
+    pub semi_minor: f64,
+
+    /// The direction of the major axis in degrees clockwise from north, in the range [0, 180)
+    pub orientation: f64,
+
+    /// The probability that the collar lies within the ellipse
+    pub confidence: f64,
+}
+
+impl Ellipse {
+    /// Lists `count` points (as latitude and longitude) around the ellipse centred on `lat`, `lon`,
+    /// going counter-clockwise as GeoJSON expects for the outside of a polygon
+    pub fn outline(&self, lat: f64, lon: f64, count: usize) -> Vec<(f64, f64)> {
+        let frame = LocalFrame::new(lat, lon);
+        let orientation = self.orientation.to_radians();
+        let (major_east, major_north) = (orientation.sin(), orientation.cos());
+
+        // The minor axis is a quarter turn counter-clockwise from the major axis
+        (0..count).map(|i| {
+            let t = 2.0 * PI * i as f64 / count as f64;
+            let (a, b) = (self.semi_major * t.cos(), self.semi_minor * t.sin());
+            frame.to_global(a * major_east - b * major_north, a * major_north + b * major_east)
+        }).collect()
+    }
+}
+
+/// Estimates the position of the collar from bearings taken at two or more places. Returns `None`
+/// if there are too few observations or they are all parallel.
+pub fn locate(observations: &[Observation]) -> Option<Location> {
+    if observations.len() < 2 {
+        return None;
+    }
+
+    let count = observations.len() as f64;
+    let frame = LocalFrame::new(observations.iter().map(|x| x.lat).sum::<f64>() / count,
+        observations.iter().map(|x| x.lon).sum::<f64>() / count);
+
+    // The position, the unit vector along the bearing and the bearing standard deviation (in
+    // radians) of each observation
+    let lines: Vec<_> = observations.iter().map(|x| {
+        let bearing = x.bearing.to_radians();
+        (frame.to_local(x.lat, x.lon), (bearing.sin(), bearing.cos()), x.uncertainty.to_radians())
+    }).collect();
+
+    // Start by weighting every line as if the collar were the same distance from each observation
+    let mut ranges = vec![1.0f64; lines.len()];
+    let mut estimate = None;
+
+    for _ in 0..ITERATIONS {
+        let mut information = [0.0; 3];
+        let mut rhs = [0.0; 2];
+        for (&((px, py), (dx, dy), sigma), &range) in lines.iter().zip(&ranges) {
+            // Normal to the bearing line, so that the distance from it is normal . (x - p)
+            let (nx, ny) = (dy, -dx);
+            let weight = 1.0 / (sigma * range.abs().max(MIN_RANGE)).powi(2);
+            let offset = nx * px + ny * py;
+            information[0] += weight * nx * nx;
+            information[1] += weight * nx * ny;
+            information[2] += weight * ny * ny;
+            rhs[0] += weight * nx * offset;
+            rhs[1] += weight * ny * offset;
+        }
+
+        let (a, b, c) = (information[0], information[1], information[2]);
+        let det = a * c - b * b;
+        if !(det > 1e-12 * (a + c) * (a + c)) {
+            return None;
+        }
+
+        let covariance = [c / det, -b / det, a / det];
+        let position = (covariance[0] * rhs[0] + covariance[1] * rhs[1],
+            covariance[1] * rhs[0] + covariance[2] * rhs[1]);
+
+        for (&((px, py), (dx, dy), _), range) in lines.iter().zip(&mut ranges) {
+            *range = dx * (position.0 - px) + dy * (position.1 - py);
+        }
+        estimate = Some((position, covariance));
+    }
+
+    estimate.map(|((east, north), covariance)| {
+        let sum_squares: f64 = lines.iter().map(|&((px, py), (dx, dy), sigma)| {
+            let (x, y) = (east - px, north - py);
+            let error = (dy * x - dx * y).atan2(dx * x + dy * y);
+            (error / sigma).powi(2)
+        }).sum();
+
+        let (lat, lon) = frame.to_global(east, north);
+        Location {
+            lat: lat,
+            lon: lon,
+            covariance: covariance,
+            ranges: ranges,
+            rms_error: (sum_squares / count).sqrt(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::read_temp_file;
+
+    use super::*;
+
+    /// A bearing to `target` taken from `east`, `north` metres from it, with an error of `error`
+    /// degrees
+    fn fix(frame: &LocalFrame, east: f64, north: f64, error: f64) -> Observation {
+        let (lat, lon) = frame.to_global(east, north);
+        Observation {
+            lat: lat,
+            lon: lon,
+            bearing: wrap_degrees((-east).atan2(-north).to_degrees() + error),
+            uncertainty: 2.0,
+        }
+    }
+
+    fn read(name: &str, contents: &str) -> Result<Vec<Observation>, String> {
+        read_temp_file(&format!("localise_{}", name), contents, read_observations)
+    }
+
+    #[test]
+    fn reads_observations() {
+        let observations = read("header", "lat,lon,bearing,uncertainty\n-27.5,153,370,2\n\n\
+            -27.4,153.1,-10,3.5\n").unwrap();
+        assert_eq!(observations, vec![
+            Observation { lat: -27.5, lon: 153.0, bearing: 10.0, uncertainty: 2.0 },
+            Observation { lat: -27.4, lon: 153.1, bearing: 350.0, uncertainty: 3.5 },
+        ]);
+
+        // Without a header, the first line is an observation
+        assert_eq!(read("no_header", "-27.5,153,10,2\n").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_observations() {
+        let error = read("short", "-27.5,153,10,2\n-27.4,153.1,20\n").unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        let error = read("text", "lat,lon,bearing,uncertainty\n-27.5,153,10,2\nend\n").unwrap_err();
+        assert!(error.contains("line 3"), "{}", error);
+        assert!(read("numeric_first", "-27.5,153,ten,2\n").is_err());
+        assert!(read("nan", "-27.5,nan,10,2\n").is_err());
+        assert!(read("uncertainty", "-27.5,153,10,0\n").unwrap_err().contains("uncertainty"));
+    }
+
+    #[test]
+    fn local_frame_round_trips() {
+        let frame = LocalFrame::new(-27.5, 153.0);
+        let (east, north) = frame.to_local(-27.49, 153.01);
+        assert!((north - 1111.95).abs() < 0.1 && (east - 986.3).abs() < 0.1, "{} {}", east, north);
+
+        let (lat, lon) = frame.to_global(east, north);
+        assert!((lat + 27.49).abs() < 1e-9 && (lon - 153.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn locates_intersection_of_bearings() {
+        let target = LocalFrame::new(-27.5, 153.0);
+        let fixes = [fix(&target, -500.0, 0.0, 0.0), fix(&target, 0.0, -400.0, 0.0),
+            fix(&target, 300.0, 300.0, 0.0)];
+
+        let location = locate(&fixes).unwrap();
+        let (east, north) = target.to_local(location.lat, location.lon);
+        assert!(east.hypot(north) < 0.01, "{} m east, {} m north", east, north);
+        assert!(location.rms_error < 1e-3);
+        assert_eq!(location.behind(), 0);
+
+        let ellipse = location.ellipse(0.95);
+        assert!(ellipse.semi_major >= ellipse.semi_minor && ellipse.semi_minor > 0.0);
+        assert!(ellipse.semi_major < 50.0);
+    }
+
+    #[test]
+    fn ellipse_is_long_along_the_bearings() {
+        // Two nearly parallel bearings from the south pin down east-west far better than
+        // north-south
+        let target = LocalFrame::new(45.0, 10.0);
+        let fixes = [fix(&target, -50.0, -1000.0, 0.5), fix(&target, 50.0, -1000.0, -0.5)];
+
+        let location = locate(&fixes).unwrap();
+        let ellipse = location.ellipse(0.95);
+        assert!(ellipse.semi_major > 5.0 * ellipse.semi_minor);
+        assert!(ellipse.orientation < 5.0 || ellipse.orientation > 175.0,
+            "oriented at {}", ellipse.orientation);
+
+        let outline = ellipse.outline(location.lat, location.lon, 36);
+        let (east, north) = LocalFrame::new(location.lat, location.lon)
+            .to_local(outline[0].0, outline[0].1);
+        assert!((east.hypot(north) - ellipse.semi_major).abs() < 0.01);
+    }
+
+    #[test]
+    fn outline_is_counter_clockwise() {
+        let ellipse = Ellipse { semi_major: 100.0, semi_minor: 50.0, orientation: 30.0,
+            confidence: 0.95 };
+        let outline = ellipse.outline(-27.5, 153.0, 36);
+
+        // The shoelace formula gives a positive area for a counter-clockwise ring of (x, y) points,
+        // here longitude and latitude
+        let area: f64 = (0..outline.len()).map(|i| {
+            let ((lat0, lon0), (lat1, lon1)) = (outline[i], outline[(i + 1) % outline.len()]);
+            lon0 * lat1 - lon1 * lat0
+        }).sum();
+        assert!(area > 0.0);
+    }
+
+    #[test]
+    fn rejects_parallel_bearings() {
+        let target = LocalFrame::new(0.0, 0.0);
+        assert!(locate(&[fix(&target, 0.0, -100.0, 0.0)]).is_none());
+        let fixes = [fix(&target, 0.0, -100.0, 0.0), fix(&target, 0.0, -200.0, 0.0)];
+        assert!(locate(&fixes).is_none());
+    }
+}
@@ -0,0 +1,184 @@
+//! A tool for estimating the bearing to a collar from a capture recorded with a rotating antenna
+
+extern crate clap;
+extern crate bearing;
+extern crate iq_converter;
+
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+
+use clap::{App, Arg};
+
+use iq_converter::IqConverter;
+use iq_converter::cli::{exit_with_error, parse_arg, read_capture};
+use iq_converter::goertzel::FilterSettings;
+use iq_converter::pulse::DEFAULT_THRESHOLD;
+use iq_converter::units::{format_frequency, parse_count, parse_frequency, parse_level};
+use iq_converter::units::{parse_rate, parse_signed_duration};
+use iq_converter::window::Window;
+
+use bearing::{align, fit_bearing, measure_pulses, Observation};
+use bearing::heading::HeadingLog;
+use bearing::telemetry::{Alignment, Telemetry};
+use bearing::timestamp::{capture_start, format_timestamp};
+
+/// The smallest arc of headings that gives a trustworthy bearing
+const MIN_COVERAGE: f64 = 180.0;
+
+fn main() {
+    let matches = App::new("Bearing Estimator")
+        .arg_from_usage("<input> 'The path to the capture'")
+        .arg_from_usage("<headings> 'The path to a CSV file of time (in seconds) and antenna yaw \
+            (in degrees clockwise from north), or a flight telemetry log with --telemetry'")
+        .arg(Arg::from_usage("-f, --filter <freq> 'Set the collar frequency as an offset from the \
+            centre frequency, e.g. 130000, 130k or -0.25MHz'").allow_hyphen_values(true))
+        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set input file sample rate, e.g. 2e6, 2M or \
+            2MS/s (default: 2MS/s)'")
+        .arg_from_usage("-w, --window [window] 'Set the filter window: rectangular, hann, hamming, \
+            blackman-harris, flat-top or kaiser[:<beta>] (default: blackman-harris)'")
+        .arg_from_usage("-n, --block_size [block_size] 'Set the number of samples in each filter \
+            block (default: 100)'")
+        .arg_from_usage("--pulse_threshold [threshold] 'Detect pulses where the filtered signal \
+            is at least this many dB above the noise floor (default: 10)'")
+        .arg(Arg::from_usage("--offset [offset] 'Set the time in the heading log at which the \
+            capture started, e.g. 12.5s (default: 0s)'").allow_hyphen_values(true))
+        .arg_from_usage("--radians 'Read the yaw in the heading log as radians'")
+        .arg(Arg::from_usage("--telemetry 'Read the headings from a flight telemetry log with \
+            absolute times'").conflicts_with("offset"))
+        .arg(Arg::from_usage("--start [start] 'Set the time the capture started, e.g. \
+            2016-05-04T10:30:00Z or seconds since the Unix epoch (default: from a SigMF metadata \
+            file or the capture file name)'").requires("telemetry"))
+        .arg(Arg::from_usage("--observations [observations] 'Name of a CSV file to add the \
+            bearing and the position of the drone to, for use with localise'")
+            .requires("telemetry"))
+        .arg_from_usage("-o, --output [output] 'Name of a CSV file to write the time, heading and \
+            magnitude of each pulse to'")
+        .get_matches();
+
+    let input = matches.value_of("input").unwrap();
+    let headings = matches.value_of("headings").unwrap();
+
+    let mut filter_settings = FilterSettings::new(parse_arg(&matches, "block_size", parse_count)
+        .unwrap_or(100));
+    if let Some(window) = matches.value_of("window") {
+        filter_settings.window = window.parse::<Window>()
+            .unwrap_or_else(|e| exit_with_error(&e));
+    }
+    if let Err(e) = filter_settings.validate() {
+        exit_with_error(&e);
+    }
+
+
+    let samp_rate = parse_arg(&matches, "samp_rate", parse_rate).unwrap_or(2e6);
+    let freq = parse_arg(&matches, "filter", parse_frequency).unwrap();
+    let threshold = parse_arg(&matches, "pulse_threshold", parse_level)
+        .unwrap_or(DEFAULT_THRESHOLD);
+    let offset = parse_arg(&matches, "offset", parse_signed_duration).unwrap_or(0.0);
+
+    let radians = matches.is_present("radians");
+    let telemetry = if matches.is_present("telemetry") {
+        let start = capture_start(Path::new(input), matches.value_of("start"))
+            .unwrap_or_else(|e| exit_with_error(&e));
+        let telemetry = Telemetry::read(Path::new(headings), radians)
+            .unwrap_or_else(|e| exit_with_error(&e));
+        println!("Capture started at {}", format_timestamp(start));
+        Some((telemetry, Alignment::new(start, samp_rate)))
+    }
+    else {
+        None
+    };
+
+    let log = match telemetry {
+        Some((ref telemetry, ref alignment)) => telemetry.heading_log(alignment),
+        None => HeadingLog::read(Path::new(headings), offset, radians)
+            .unwrap_or_else(|e| exit_with_error(&e)),
+    };
+
+    let data = read_capture(Path::new(input))
+        .unwrap_or_else(|e| exit_with_error(&format!("Unable to read {}: {}", input, e)));
+    let pulses = measure_pulses(IqConverter::new(data), samp_rate, freq, filter_settings,
+        threshold);
+    let observations = align(&pulses, &log);
+
+    println!("Found {} pulses at {}, {} within the heading log", pulses.len(),
+        format_frequency(freq), observations.len());
+
+    if let Some(output) = matches.value_of("output") {
+        let telemetry = telemetry.as_ref().map(|&(ref telemetry, ref alignment)| {
+            (telemetry, alignment)
+        });
+        if let Err(e) = write_observations(Path::new(output), &observations, telemetry) {
+            exit_with_error(&format!("Unable to write {}: {}", output, e));
+        }
+    }
+
+    let fit = fit_bearing(&observations).unwrap_or_else(|| {
+        exit_with_error("Unable to estimate a bearing: at least 4 pulses with differing strengths \
+            are needed")
+    });
+
+    println!("Bearing: {:.1}° ± {:.1}° (R² {:.2}, front to back {:.1} dB, coverage {:.0}°)",
+        fit.bearing, fit.std_error, fit.r_squared, 20.0 * fit.front_to_back.log10(), fit.coverage);
+    if fit.coverage < MIN_COVERAGE {
+        println!("Warning: the pulses only cover {:.0}° of headings, so the bearing may be \
+            unreliable", fit.coverage);
+    }
+
+    if let Some((ref telemetry, ref alignment)) = telemetry {
+        // The drone should stay in one place while it rotates, so use its mean position
+        let states: Vec<_> = observations.iter()
+            .filter_map(|x| telemetry.at(alignment.absolute(x.time))).collect();
+        let count = states.len() as f64;
+        let (lat, lon) = (states.iter().map(|x| x.lat).sum::<f64>() / count,
+            states.iter().map(|x| x.lon).sum::<f64>() / count);
+        println!("Position: {:.6}, {:.6}", lat, lon);
+
+        if let Some(path) = matches.value_of("observations") {
+            if let Err(e) = add_observation(Path::new(path), lat, lon, fit.bearing, fit.std_error) {
+                exit_with_error(&format!("Unable to write {}: {}", path, e));
+            }
+        }
+    }
+}
+
+/// Adds a bearing to a file of observations for localise, writing a header if the file is new
+fn add_observation(path: &Path, lat: f64, lon: f64, bearing: f64, uncertainty: f64)
+    -> io::Result<()>
+{
+    let mut output = try!(OpenOptions::new().append(true).create(true).open(path));
+    if try!(output.metadata()).len() == 0 {
+        try!(writeln!(output, "lat,lon,bearing,uncertainty"));
+    }
+    writeln!(output, "{},{},{},{}", lat, lon, bearing, uncertainty)
+}
+
+/// Writes the time, heading and magnitude of each pulse to a CSV file. If there is telemetry, the
+/// UTC time and the position of the drone are also written.
+fn write_observations(path: &Path, observations: &[Observation],
+    telemetry: Option<(&Telemetry, &Alignment)>) -> Result<(), Box<Error>>
+{
+    let mut output = BufWriter::new(try!(File::create(path)));
+    match telemetry {
+        Some(_) => try!(writeln!(output, "time,heading,magnitude,utc,lat,lon,alt")),
+        None => try!(writeln!(output, "time,heading,magnitude")),
+    }
+
+    for x in observations {
+        try!(write!(output, "{},{},{}", x.time, x.heading, x.magnitude));
+        if let Some((telemetry, alignment)) = telemetry {
+            let time = alignment.absolute(x.time);
+            if let Some(state) = telemetry.at(time) {
+                try!(write!(output, ",{},{},{},{}", format_timestamp(time), state.lat, state.lon,
+                    state.alt));
+            }
+        }
+        try!(writeln!(output, ""));
+    }
+
+    try!(output.flush());
+    Ok(())
+}
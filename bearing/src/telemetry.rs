@@ -0,0 +1,256 @@
+//! Flight telemetry logged by the drone, and its alignment with captures.
+//!
+//! Telemetry is a CSV file with a header naming its columns, of which `time`, `lat`, `lon`, `alt`,
+//! `roll`, `pitch` and `yaw` are used (longer names such as `latitude` are also recognised). Times
+//! are absolute, given either as seconds since the Unix epoch or as ISO 8601 dates and times.
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+use heading::{angle_difference, wrap_degrees, HeadingLog};
+use timestamp::parse_timestamp;
+
+/// The names each column may be given in the header, in the order they are stored in `State`
+const COLUMNS: [&'static [&'static str]; 7] = [
+    &["time", "timestamp", "t"],
+    &["lat", "latitude"],
+    &["lon", "lng", "long", "longitude"],
+    &["alt", "altitude", "height"],
+    &["roll"],
+    &["pitch"],
+    &["yaw", "heading"],
+];
+
+/// The state of the drone at one point in time
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct State {
+    /// The time in seconds since the Unix epoch
+    pub time: f64,
+
+    /// The latitude and longitude in degrees
+    pub lat: f64,
+    pub lon: f64,
+
+    /// The altitude in metres
+    pub alt: f64,
+
+    /// The attitude in degrees. Yaw is clockwise from north in the range [0, 360).
+    pub roll: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+}
+
+/// A telemetry log, ordered by time
+pub struct Telemetry {
+    states: Vec<State>,
+}
+
+impl Telemetry {
+    /// Creates a log from a list of states with finite times, sorting them by time and wrapping the
+    /// yaw to [0, 360)
+    pub fn new(mut states: Vec<State>) -> Telemetry {
+        for state in &mut states {
+            state.yaw = wrap_degrees(state.yaw);
+        }
+        states.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+        Telemetry { states: states }
+    }
+
+    /// Reads a telemetry CSV file, converting the attitude from radians if `radians` is set. Values
+    /// that aren't finite, such as `nan`, are rejected.
+    pub fn read(path: &Path, radians: bool) -> Result<Telemetry, String> {
+        let file = try!(File::open(path)
+            .map_err(|e| format!("Unable to open {}: {}", path.display(), e)));
+        let mut lines = BufReader::new(file).lines().enumerate();
+
+        let header = match lines.next() {
+            Some((_, Ok(header))) => header,
+            Some((_, Err(e))) => return Err(format!("Unable to read {}: {}", path.display(), e)),
+            None => return Err(format!("{} is empty", path.display())),
+        };
+        let names: Vec<_> = header.split(',').map(|x| x.trim().to_lowercase()).collect();
+
+        let mut columns = [0; 7];
+        for (column, aliases) in columns.iter_mut().zip(&COLUMNS) {
+            *column = try!(names.iter().position(|x| aliases.contains(&&x[..])).ok_or_else(|| {
+                format!("{} doesn't have a {} column", path.display(), aliases[0])
+            }));
+        }
+
+        let mut states = vec![];
+        for (number, line) in lines {
+            let line = try!(line.map_err(|e| format!("Unable to read {}: {}", path.display(), e)));
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<_> = line.split(',').map(|x| x.trim()).collect();
+            let field = |index: usize| {
+                let column = columns[index];
+                let value = try!(fields.get(column).ok_or_else(|| {
+                    format!("Line {} of {} is missing the {} column", number + 1, path.display(),
+                        COLUMNS[index][0])
+                }));
+                if index == 0 {
+                    parse_timestamp(value)
+                        .map_err(|e| format!("{} on line {} of {}", e, number + 1, path.display()))
+                }
+                else {
+                    match value.parse::<f64>() {
+                        Ok(x) if x.is_finite() => Ok(x),
+                        _ => Err(format!("Invalid {} `{}` on line {} of {}", COLUMNS[index][0],
+                            value, number + 1, path.display())),
+                    }
+                }
+            };
+
+            let attitude = |value: f64| if radians { value.to_degrees() } else { value };
+            states.push(State {
+                time: try!(field(0)),
+                lat: try!(field(1)),
+                lon: try!(field(2)),
+                alt: try!(field(3)),
+                roll: attitude(try!(field(4))),
+                pitch: attitude(try!(field(5))),
+                yaw: attitude(try!(field(6))),
+            });
+        }
+
+        if states.len() < 2 {
+            return Err(format!("{} must contain at least two entries", path.display()));
+        }
+        Ok(Telemetry::new(states))
+    }
+
+    /// The time range covered by the log, in seconds since the Unix epoch
+    pub fn span(&self) -> (f64, f64) {
+        (self.states[0].time, self.states[self.states.len() - 1].time)
+    }
+
+    /// Interpolates the state at `time` (in seconds since the Unix epoch). Angles are interpolated
+    /// the shorter way around the circle. Returns `None` if `time` is outside of the log.
+    pub fn at(&self, time: f64) -> Option<State> {
+        if self.states.is_empty() {
+            return None;
+        }
+        let (first, last) = self.span();
+        if time < first || time > last {
+            return None;
+        }
+
+        let index = match self.states.binary_search_by(|x| x.time.partial_cmp(&time).unwrap()) {
+            Ok(index) => return Some(self.states[index]),
+            Err(index) => index,
+        };
+
+        let (a, b) = (self.states[index - 1], self.states[index]);
+        let fraction = if b.time > a.time { (time - a.time) / (b.time - a.time) } else { 0.0 };
+        let linear = |x: f64, y: f64| x + (y - x) * fraction;
+        let angular = |x: f64, y: f64| x + angle_difference(y, x) * fraction;
+
+        Some(State {
+            time: time,
+            lat: linear(a.lat, b.lat),
+            lon: linear(a.lon, b.lon),
+            alt: linear(a.alt, b.alt),
+            roll: angular(a.roll, b.roll),
+            pitch: angular(a.pitch, b.pitch),
+            yaw: wrap_degrees(angular(a.yaw, b.yaw)),
+        })
+    }
+
+    /// Interpolates the state at a sample of a capture
+    pub fn at_sample(&self, alignment: &Alignment, index: u64) -> Option<State> {
+        self.at(alignment.time_of(index))
+    }
+
+    /// The yaw of the drone over time, relative to the start of a capture
+    pub fn heading_log(&self, alignment: &Alignment) -> HeadingLog {
+        HeadingLog::new(self.states.iter().map(|x| (x.time - alignment.start, x.yaw)).collect())
+    }
+}
+
+/// The relationship between the samples of a capture and absolute time
+#[derive(Copy, Clone, Debug)]
+pub struct Alignment {
+    /// The time of the first sample in seconds since the Unix epoch
+    pub start: f64,
+
+    /// The sample rate of the capture
+    pub samp_rate: f64,
+}
+
+impl Alignment {
+    pub fn new(start: f64, samp_rate: f64) -> Alignment {
+        Alignment { start: start, samp_rate: samp_rate }
+    }
+
+    /// The time of a sample in seconds since the Unix epoch
+    pub fn time_of(&self, index: u64) -> f64 {
+        self.start + index as f64 / self.samp_rate
+    }
+
+    /// The time in seconds since the Unix epoch of a time relative to the start of the capture
+    pub fn absolute(&self, offset: f64) -> f64 {
+        self.start + offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_util::read_temp_file;
+
+    use super::*;
+
+    fn read(name: &str, contents: &str) -> Result<Telemetry, String> {
+        read_temp_file(&format!("telemetry_{}", name), contents, |path| {
+            Telemetry::read(path, false)
+        })
+    }
+
+    #[test]
+    fn interpolates_telemetry_at_samples() {
+        let state = |time: f64, lat: f64, yaw: f64| {
+            State { time: time, lat: lat, lon: 153.0, alt: 50.0, roll: 0.0, pitch: -2.0, yaw: yaw }
+        };
+        let telemetry = Telemetry::new(vec![state(1000.1, -27.0, 350.0),
+            state(1000.0, -27.1, 340.0), state(1000.2, -26.9, 10.0)]);
+        let alignment = Alignment::new(999.9, 2e6);
+
+        assert_eq!(telemetry.at_sample(&alignment, 0), None);
+        let at = telemetry.at_sample(&alignment, 300000).unwrap();
+        assert!((at.time - 1000.05).abs() < 1e-9);
+        assert!((at.lat + 27.05).abs() < 1e-9 && (at.yaw - 345.0).abs() < 1e-9);
+        assert_eq!(at.pitch, -2.0);
+
+        let at = telemetry.at_sample(&alignment, 500000).unwrap();
+        assert!(at.yaw < 1e-9 || at.yaw > 360.0 - 1e-9, "yaw {}", at.yaw);
+        assert_eq!(telemetry.at_sample(&alignment, 700000), None);
+
+        let log = telemetry.heading_log(&alignment);
+        assert!((log.heading_at(0.15).unwrap() - 345.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reads_logs_by_column_name() {
+        let telemetry = read("columns", "Timestamp,Yaw,Latitude,Longitude,Altitude,Roll,Pitch\n\
+            2016-05-04T10:30:01Z,-10,-27.1,153,50,1,2\n\
+            1462357800,20,-27,153,50,1,2\n").unwrap();
+        assert_eq!(telemetry.span(), (1462357800.0, 1462357801.0));
+        assert_eq!(telemetry.at(1462357801.0).map(|x| (x.lat, x.yaw)), Some((-27.1, 350.0)));
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_finite() {
+        let header = "time,lat,lon,alt,roll,pitch,yaw\n";
+        let log = format!("{}1000,-27,153,50,0,0,0\nnan,-27,153,50,0,0,0\n", header);
+        let error = read("nan_time", &log).err().unwrap();
+        assert!(error.contains("line 3"), "{}", error);
+
+        let log = format!("{}1000,-27,153,50,0,0,0\n1001,-27,153,50,0,0,inf\n", header);
+        let error = read("inf_yaw", &log).err().unwrap();
+        assert!(error.contains("Invalid yaw `inf` on line 3"), "{}", error);
+    }
+}
@@ -0,0 +1,19 @@
+//! Helpers shared by the tests of each module
+
+use std::env;
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+
+/// Writes `contents` to a temporary file and passes its path to `read`, removing the file again
+/// afterwards. `name` must be unique across the tests, since they run at the same time.
+pub fn read_temp_file<T, F>(name: &str, contents: &str, read: F) -> T
+    where F: FnOnce(&Path) -> T
+{
+    let path = env::temp_dir().join(format!("bearing_{}_{}.csv", name, process::id()));
+    File::create(&path).unwrap().write_all(contents.as_bytes()).unwrap();
+    let result = read(&path);
+    let _ = fs::remove_file(&path);
+    result
+}
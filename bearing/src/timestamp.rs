@@ -0,0 +1,262 @@
+//! Absolute times, used to line captures up with flight telemetry.
+//!
+//! Times are held as seconds since the Unix epoch in UTC. They can be given as a number of seconds,
+//! or as an ISO 8601 date and time such as `2016-05-04T10:30:00.5Z`. A time without a zone is
+//! taken to be UTC.
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Parses a time given as seconds since the Unix epoch or as an ISO 8601 date and time. Times that
+/// aren't finite, such as `nan`, are rejected.
+pub fn parse_timestamp(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds.is_finite() => return Ok(seconds),
+        _ => {}
+    }
+    parse_iso8601(value).ok_or_else(|| format!("Invalid time `{}`: expected seconds since the Unix \
+        epoch or a date and time such as 2016-05-04T10:30:00Z", value))
+}
+
+/// Formats a time as an ISO 8601 date and time in UTC, to the millisecond
+pub fn format_timestamp(time: f64) -> String {
+    let millis = (time * 1000.0).round() as i64;
+    let days = div_floor(millis, 86400000);
+    let millis = millis - days * 86400000;
+    let (year, month, day) = civil_from_days(days);
+    let seconds = millis / 1000;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", year, month, day, seconds / 3600,
+        seconds / 60 % 60, seconds % 60, millis % 1000)
+}
+
+fn parse_iso8601(value: &str) -> Option<f64> {
+    let value = value.trim_right_matches('Z');
+    let (date, time) = match value.find(|c| c == 'T' || c == ' ') {
+        Some(index) => (&value[..index], &value[index + 1..]),
+        None => (value, "00:00:00"),
+    };
+
+    // Split off a zone offset such as +10:00 or -0530
+    let (time, zone) = match time.rfind(|c| c == '+' || c == '-') {
+        Some(index) => (&time[..index], Some(&time[index..])),
+        None => (time, None),
+    };
+
+    let date: Option<Vec<_>> = date.split('-').map(|x| x.parse::<i64>().ok()).collect();
+    let time: Option<Vec<_>> = time.split(':').map(|x| x.parse::<f64>().ok()).collect();
+    let offset = match zone {
+        Some(zone) => parse_zone(zone),
+        None => Some(0.0),
+    };
+
+    match (date, time, offset) {
+        (Some(ref date), Some(ref time), Some(offset)) if date.len() == 3 &&
+            (time.len() == 2 || time.len() == 3) =>
+        {
+            let second = if time.len() == 3 { time[2] } else { 0.0 };
+            from_civil(date[0], date[1], date[2], time[0], time[1], second).map(|x| x - offset)
+        }
+        _ => None,
+    }
+}
+
+/// Parses a zone offset such as `+10:00`, `-0530` or `+08` into seconds east of UTC
+fn parse_zone(zone: &str) -> Option<f64> {
+    let sign = if zone.starts_with('-') { -1.0 } else { 1.0 };
+    let digits: String = zone[1..].chars().filter(|&c| c != ':').collect();
+    if digits.len() != 2 && digits.len() != 4 {
+        return None;
+    }
+
+    let hours = digits[..2].parse::<f64>().ok();
+    let minutes = if digits.len() == 4 { digits[2..].parse::<f64>().ok() } else { Some(0.0) };
+    match (hours, minutes) {
+        (Some(hours), Some(minutes)) => Some(sign * (hours * 3600.0 + minutes * 60.0)),
+        _ => None,
+    }
+}
+
+/// Converts a UTC date and time to seconds since the Unix epoch, checking that each field is in
+/// range
+fn from_civil(year: i64, month: i64, day: i64, hour: f64, minute: f64, second: f64)
+    -> Option<f64>
+{
+    if month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) || hour < 0.0 ||
+        hour >= 24.0 || minute < 0.0 || minute >= 60.0 || second < 0.0 || second >= 61.0
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day) as f64;
+    Some(days * 86400.0 + hour * 3600.0 + minute * 60.0 + second)
+}
+
+/// The number of days in a month of the proleptic Gregorian calendar
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The number of days from 1970-01-01 to a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = div_floor(year, 400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of a number of days from 1970-01-01, as the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = div_floor(days, 146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096)
+        / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn div_floor(a: i64, b: i64) -> i64 {
+    let quotient = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { quotient - 1 } else { quotient }
+}
+
+/// Finds a UTC date and time in a capture's file name, such as `hackrf_20160504_103000.bin` or
+/// `capture-2016-05-04T10-30-00.bin`. The date and time may be written with or without separators.
+pub fn from_filename(path: &Path) -> Option<f64> {
+    let name = match path.file_stem().and_then(|x| x.to_str()) {
+        Some(name) => name,
+        None => return None,
+    };
+
+    // Look for 14 digits (YYYYMMDDhhmmss) split into groups only at field boundaries
+    let groups: Vec<_> = name.split(|c: char| !c.is_digit(10)).filter(|x| !x.is_empty()).collect();
+    for start in 0..groups.len() {
+        let mut digits = String::new();
+        for group in &groups[start..] {
+            // Every field is an even number of digits long
+            if group.len() % 2 != 0 {
+                break;
+            }
+            digits.push_str(group);
+            if digits.len() >= 14 {
+                break;
+            }
+        }
+
+        if digits.len() == 14 {
+            let field = |range: Range<usize>| digits[range].parse::<i64>().unwrap();
+            let time = from_civil(field(0..4), field(4..6), field(6..8), field(8..10) as f64,
+                field(10..12) as f64, field(12..14) as f64);
+            if time.is_some() {
+                return time;
+            }
+        }
+    }
+    None
+}
+
+/// Reads the start time of a capture from the `core:datetime` field of a SigMF metadata file
+pub fn from_sigmf(path: &Path) -> Result<f64, String> {
+    let mut contents = String::new();
+    try!(File::open(path).and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e)));
+
+    // Only the first datetime is needed, so look for it directly rather than parsing the JSON
+    let key = "\"core:datetime\"";
+    let value = contents.find(key).and_then(|index| {
+        let rest = contents[index + key.len()..].trim_left();
+        if !rest.starts_with(':') {
+            return None;
+        }
+        let rest = rest[1..].trim_left();
+        if !rest.starts_with('"') {
+            return None;
+        }
+        rest[1..].find('"').map(|end| &rest[1..end + 1])
+    });
+
+    match value {
+        Some(value) => parse_timestamp(value)
+            .map_err(|e| format!("Invalid core:datetime in {}: {}", path.display(), e)),
+        None => Err(format!("{} doesn't contain a core:datetime", path.display())),
+    }
+}
+
+/// The path of the SigMF metadata file that would describe a capture
+pub fn sigmf_meta_path(capture: &Path) -> PathBuf {
+    capture.with_extension("sigmf-meta")
+}
+
+/// Finds the start time of a capture. A time given as `value` is used if there is one, then the
+/// SigMF metadata next to the capture, then a date and time in the capture's file name.
+pub fn capture_start(capture: &Path, value: Option<&str>) -> Result<f64, String> {
+    if let Some(value) = value {
+        return parse_timestamp(value);
+    }
+
+    let meta = sigmf_meta_path(capture);
+    if meta.exists() {
+        return from_sigmf(&meta);
+    }
+
+    from_filename(capture).ok_or_else(|| format!("Unable to find the start time of {}: give it \
+        with --start, in a SigMF metadata file or in the file name", capture.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1462357800.5"), Ok(1462357800.5));
+        assert_eq!(parse_timestamp("2016-05-04T10:30:00.5Z"), Ok(1462357800.5));
+        assert_eq!(parse_timestamp("2016-05-04 20:30:00.5+10:00"), Ok(1462357800.5));
+        assert_eq!(parse_timestamp("2016-05-04T05:00-0530"), Ok(1462357800.0));
+        assert_eq!(parse_timestamp("1969-12-31"), Ok(-86400.0));
+        assert!(parse_timestamp("2016-13-04T10:30:00Z").is_err());
+        assert!(parse_timestamp("yesterday").is_err());
+        assert!(parse_timestamp("nan").is_err());
+        assert!(parse_timestamp("-inf").is_err());
+
+        assert_eq!(format_timestamp(1462357800.5), "2016-05-04T10:30:00.500Z");
+        assert_eq!(format_timestamp(951782400.0), "2000-02-29T00:00:00.000Z");
+        assert_eq!(format_timestamp(-0.25), "1969-12-31T23:59:59.750Z");
+    }
+
+    #[test]
+    fn rejects_days_past_the_end_of_the_month() {
+        assert!(parse_timestamp("2016-02-31T00:00:00Z").is_err());
+        assert!(parse_timestamp("2016-04-31").is_err());
+        assert!(parse_timestamp("2015-02-29").is_err());
+        assert!(parse_timestamp("1900-02-29").is_err());
+        assert_eq!(parse_timestamp("2016-02-29"), Ok(1456704000.0));
+        assert_eq!(parse_timestamp("2000-02-29"), Ok(951782400.0));
+        assert_eq!(parse_timestamp("2016-12-31"), Ok(1483142400.0));
+        assert_eq!(from_filename(Path::new("hackrf_20160431_103000.bin")), None);
+    }
+
+    #[test]
+    fn finds_timestamps_in_file_names() {
+        let expected = Some(1462357800.0);
+        assert_eq!(from_filename(Path::new("hackrf_20160504_103000.bin")), expected);
+        assert_eq!(from_filename(Path::new("data/capture-2016-05-04T10-30-00.bin")), expected);
+        assert_eq!(from_filename(Path::new("2M_20160504103000_151M.bin")), expected);
+        assert_eq!(from_filename(Path::new("capture_3.bin")), None);
+        assert_eq!(from_filename(Path::new("20161304_103000.bin")), None);
+    }
+}
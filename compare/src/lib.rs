@@ -0,0 +1,306 @@
+//! Comparison of two captures of the same beacon, e.g. before and after a change of gain, antenna
+//! or firmware.
+//!
+//! Both captures are filtered at the collar frequency with the same settings, and the resulting
+//! magnitude streams are lined up either by a known offset or by cross-correlating their envelopes.
+//! The noise floor, pulse SNR and number of pulses detected are then measured over the part of the
+//! beacon's transmission that both captures recorded.
+
+extern crate iq_converter;
+
+use std::f32;
+
+use iq_converter::fft::Fft;
+use iq_converter::goertzel::{FilterSettings, Magnitudes};
+use iq_converter::noise::magnitude_to_db;
+use iq_converter::pulse::{Pulse, PulseDetector};
+
+/// The rate (in Hz) that envelopes are reduced to before cross-correlating them, which sets the
+/// resolution of the alignment. The actual rate is a whole fraction of the magnitude rate, so may
+/// differ slightly.
+pub const ENVELOPE_RATE: f64 = 1000.0;
+
+/// The default largest offset (in seconds) searched when aligning captures, which is under half of
+/// the 0.7 to 1 s interval that collars typically pulse at
+pub const DEFAULT_MAX_LAG: f64 = 0.3;
+
+/// How far apart (in seconds) the starts of two pulses can be and still be the same pulse
+const MATCH_TOLERANCE: f64 = 0.005;
+
+/// The magnitude stream of a capture at the collar frequency
+pub struct Capture {
+    /// The Goertzel magnitudes
+    pub magnitudes: Vec<f32>,
+
+    /// The number of magnitudes per second
+    pub rate: f64,
+
+    /// The pulses found in the magnitudes
+    pub pulses: Vec<Pulse>,
+}
+
+impl Capture {
+    /// Filters an I/Q stream sampled at `samp_rate` at `freq`, finding pulses at least `threshold`
+    /// dB above the noise floor
+    pub fn measure<I>(input: I, samp_rate: f64, freq: f64, settings: FilterSettings,
+        threshold: f32) -> Capture
+        where I: Iterator<Item=(f32, f32)>
+    {
+        let rate = settings.output_rate(samp_rate);
+        let magnitudes: Vec<_> = Magnitudes::new(input, samp_rate as f32, freq as f32, settings)
+            .collect();
+
+        let mut detector = PulseDetector::new(rate, threshold);
+        let mut pulses: Vec<_> = magnitudes.iter().filter_map(|&x| detector.process(x)).collect();
+        pulses.extend(detector.finish());
+
+        Capture {
+            magnitudes: magnitudes,
+            rate: rate,
+            pulses: pulses,
+        }
+    }
+
+    /// The length of the capture in seconds
+    pub fn duration(&self) -> f64 {
+        self.magnitudes.len() as f64 / self.rate
+    }
+
+    /// The magnitudes averaged down to roughly `ENVELOPE_RATE`, along with the actual rate of the
+    /// envelope
+    pub fn envelope(&self) -> (Vec<f32>, f64) {
+        let factor = ((self.rate / ENVELOPE_RATE).round() as usize).max(1);
+        let envelope = self.magnitudes.chunks(factor)
+            .map(|x| x.iter().sum::<f32>() / x.len() as f32)
+            .collect();
+        (envelope, self.rate / factor as f64)
+    }
+
+    /// Measures the capture between `start` and `end` seconds
+    pub fn stats(&self, start: f64, end: f64) -> Stats {
+        let first = ((start * self.rate).max(0.0) as usize).min(self.magnitudes.len());
+        let last = ((end * self.rate).max(0.0) as usize).min(self.magnitudes.len()).max(first);
+
+        let mut levels: Vec<_> = self.magnitudes[first..last].iter()
+            .map(|&x| magnitude_to_db(x)).collect();
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let pulses: Vec<_> = self.pulses.iter()
+            .filter(|x| x.start >= first && x.end <= last).collect();
+        let snrs: Vec<_> = pulses.iter().map(|x| x.snr).collect();
+        let (peak_snr, mean_snr) = match snrs.is_empty() {
+            true => (None, None),
+            false => (Some(snrs.iter().cloned().fold(f32::MIN, f32::max)),
+                Some(snrs.iter().sum::<f32>() / snrs.len() as f32)),
+        };
+
+        Stats {
+            noise_floor: levels.get(levels.len() / 2).cloned(),
+            pulses: pulses.len(),
+            peak_snr: peak_snr,
+            mean_snr: mean_snr,
+            peak_magnitude: pulses.iter().map(|x| x.peak).fold(0.0, f32::max),
+        }
+    }
+
+    /// The start times of the pulses in seconds
+    pub fn pulse_starts(&self) -> Vec<f64> {
+        self.pulses.iter().map(|x| x.start as f64 / self.rate).collect()
+    }
+}
+
+/// Measurements of part of a capture
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// The median magnitude in dB, which for a pulsed beacon is the noise floor
+    pub noise_floor: Option<f32>,
+
+    /// The number of pulses detected
+    pub pulses: usize,
+
+    /// The largest and mean SNR (in dB) of the pulses
+    pub peak_snr: Option<f32>,
+    pub mean_snr: Option<f32>,
+
+    /// The largest magnitude of any pulse
+    pub peak_magnitude: f32,
+}
+
+/// The alignment of two captures found by cross-correlation
+#[derive(Copy, Clone, Debug)]
+pub struct Alignment {
+    /// The time (in seconds) in the first capture at which the second capture starts
+    pub offset: f64,
+
+    /// The normalised correlation of the envelopes at the offset, from -1 to 1
+    pub correlation: f64,
+}
+
+/// Finds the offset that best lines up the envelopes of two captures, searching offsets of up to
+/// `max_lag` seconds either way. A beacon that pulses at a fixed interval correlates equally well
+/// at every whole number of intervals, so `max_lag` should be less than half an interval if the
+/// captures aren't already roughly lined up. Both captures must have been measured at the same
+/// sample rate with the same filter settings.
+pub fn align(first: &Capture, second: &Capture, max_lag: f64) -> Option<Alignment> {
+    let ((a, rate), (b, _)) = (first.envelope(), second.envelope());
+    let max_lag = (max_lag * rate).round() as isize;
+    cross_correlate(&a, &b, max_lag).map(|(lag, correlation)| {
+        Alignment {
+            offset: lag as f64 / rate,
+            correlation: correlation,
+        }
+    })
+}
+
+/// Finds the lag of `b` relative to `a` (so that `b[i]` lines up with `a[i + lag]`) within
+/// `max_lag` either way that gives the largest normalised correlation of their mean-removed
+/// values. Returns the lag and its correlation.
+pub fn cross_correlate(a: &[f32], b: &[f32], max_lag: isize) -> Option<(isize, f64)> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+
+    let centred = |x: &[f32]| {
+        let mean = x.iter().map(|&x| x as f64).sum::<f64>() / x.len() as f64;
+        x.iter().map(|&x| x as f64 - mean).collect::<Vec<_>>()
+    };
+    let (a, b) = (centred(a), centred(b));
+
+    // Correlate with FFTs, padding so that the circular correlation doesn't wrap
+    let size = (a.len() + b.len()).next_power_of_two();
+    let fft = Fft::new(size);
+    let spectrum = |x: &[f64]| {
+        let mut data: Vec<_> = x.iter().map(|&x| (x as f32, 0.0)).collect();
+        data.resize(size, (0.0, 0.0));
+        fft.process(&mut data);
+        data
+    };
+    let (fa, fb) = (spectrum(&a), spectrum(&b));
+
+    // The inverse transform of A * conj(B), computed as the conjugate of the forward transform of
+    // its conjugate
+    let mut product: Vec<_> = fa.iter().zip(&fb)
+        .map(|(&(ar, ai), &(br, bi))| (ar * br + ai * bi, -(ai * br - ar * bi)))
+        .collect();
+    fft.process(&mut product);
+
+    let energy = |x: &[f64]| x.iter().map(|x| x * x).sum::<f64>();
+    let norm = (energy(&a) * energy(&b)).sqrt() * size as f64;
+    if !(norm > 0.0) {
+        return Some((0, 0.0));
+    }
+
+    let max_lag = max_lag.max(0);
+    let lags = (-max_lag.min(b.len() as isize - 1))..(max_lag.min(a.len() as isize - 1) + 1);
+    lags.map(|lag| {
+        let index = if lag >= 0 { lag as usize } else { size - (-lag) as usize };
+        (lag, product[index].0 as f64 / norm)
+    }).fold(None, |best: Option<(isize, f64)>, x| match best {
+        Some(best) if best.1 >= x.1 => Some(best),
+        _ => Some(x),
+    })
+}
+
+/// The range of times (in the first capture's timeline) covered by both captures, given the time
+/// in the first capture at which the second starts
+pub fn overlap(first: &Capture, second: &Capture, offset: f64) -> (f64, f64) {
+    let start = offset.max(0.0);
+    let end = first.duration().min(offset + second.duration());
+    (start, end.max(start))
+}
+
+/// Counts the pulses in the second capture that line up with a pulse in the first, given the time
+/// in the first capture at which the second starts
+pub fn matched_pulses(first: &Capture, second: &Capture, offset: f64) -> usize {
+    let starts = first.pulse_starts();
+    second.pulse_starts().iter().filter(|&&x| {
+        starts.iter().any(|&y| (x + offset - y).abs() <= MATCH_TOLERANCE)
+    }).count()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate beacon_generator;
+
+    use self::beacon_generator::{Generator, Settings};
+    use self::beacon_generator::beacon::Beacon;
+
+    use iq_converter::pulse::DEFAULT_THRESHOLD;
+
+    use super::*;
+
+    const SAMP_RATE: f64 = 1e6;
+
+    fn capture(beacon: Beacon, noise: f64, seed: u32) -> Capture {
+        capture_at(beacon, noise, seed, SAMP_RATE, 100)
+    }
+
+    fn capture_at(beacon: Beacon, noise: f64, seed: u32, samp_rate: f64, block_size: usize)
+        -> Capture
+    {
+        let mut settings = Settings::new(samp_rate, 3.0);
+        settings.noise = noise;
+        settings.seed = seed;
+        Capture::measure(Generator::new(&[beacon], &settings), samp_rate, beacon.freq,
+            FilterSettings::new(block_size), DEFAULT_THRESHOLD)
+    }
+
+    fn beacon(start: f64) -> Beacon {
+        let mut beacon = Beacon::new(130e3);
+        beacon.interval = 0.7;
+        beacon.start = start;
+        beacon
+    }
+
+    #[test]
+    fn cross_correlation_finds_lag() {
+        let a: Vec<_> = (0..100).map(|x| if x % 37 < 3 { 1.0 } else { 0.0 }).collect();
+        let b = a[12..].to_vec();
+        assert_eq!(cross_correlate(&a, &b, 20).map(|x| x.0), Some(12));
+        assert_eq!(cross_correlate(&b, &a, 20).map(|x| x.0), Some(-12));
+
+        let (lag, correlation) = cross_correlate(&a, &a, 5).unwrap();
+        assert_eq!(lag, 0);
+        assert!((correlation - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn aligns_offset_captures() {
+        // The second capture starts 0.15 s after the first, so its pulses are 0.15 s earlier
+        let first = capture(beacon(0.4), 0.02, 1);
+        let second = capture(beacon(0.25), 0.02, 2);
+
+        let alignment = align(&first, &second, 0.3).unwrap();
+        assert!((alignment.offset - 0.15).abs() <= 0.001, "offset {}", alignment.offset);
+        assert!(alignment.correlation > 0.9);
+
+        let (start, end) = overlap(&first, &second, alignment.offset);
+        assert!((start - 0.15).abs() <= 0.001 && (end - 3.0).abs() < 1e-9);
+        assert!(matched_pulses(&first, &second, alignment.offset) >= 3);
+    }
+
+    #[test]
+    fn aligns_at_the_actual_envelope_rate() {
+        // 300 sample blocks at 2 MS/s give 6667 magnitudes per second, which are averaged in sevens
+        // to an envelope at 952 Hz rather than 1 kHz
+        let first = capture_at(beacon(0.4), 0.02, 1, 2e6, 300);
+        let second = capture_at(beacon(0.25), 0.02, 2, 2e6, 300);
+        assert!((first.envelope().1 - 2e6 / 300.0 / 7.0).abs() < 1e-9);
+
+        let alignment = align(&first, &second, DEFAULT_MAX_LAG).unwrap();
+        assert!((alignment.offset - 0.15).abs() <= 0.0011, "offset {}", alignment.offset);
+        assert!(matched_pulses(&first, &second, alignment.offset) >= 3);
+    }
+
+    #[test]
+    fn noisier_capture_has_higher_floor_and_lower_snr() {
+        let quiet = capture(beacon(0.4), 0.01, 1);
+        let noisy = capture(beacon(0.4), 0.1, 1);
+
+        let (a, b) = (quiet.stats(0.0, 3.0), noisy.stats(0.0, 3.0));
+        assert!(b.noise_floor.unwrap() > a.noise_floor.unwrap() + 15.0);
+        assert!(b.mean_snr.unwrap() < a.mean_snr.unwrap() - 15.0);
+        assert!(a.pulses >= 3 && b.pulses >= 3);
+        assert!(a.peak_snr.unwrap() >= a.mean_snr.unwrap());
+    }
+}
@@ -0,0 +1,144 @@
+//! A tool for comparing two captures of the same beacon
+
+extern crate clap;
+extern crate compare;
+extern crate iq_converter;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+
+use clap::{App, Arg};
+
+use iq_converter::IqConverter;
+use iq_converter::cli::{exit_with_error, parse_arg, read_capture};
+use iq_converter::goertzel::FilterSettings;
+use iq_converter::pulse::DEFAULT_THRESHOLD;
+use iq_converter::units::{format_frequency, parse_count, parse_duration, parse_frequency};
+use iq_converter::units::{parse_level, parse_rate, parse_signed_duration};
+use iq_converter::window::Window;
+
+use compare::{align, matched_pulses, overlap, Capture, Stats, DEFAULT_MAX_LAG};
+
+/// Correlations below this suggest the alignment found is not trustworthy
+const MIN_CORRELATION: f64 = 0.5;
+
+fn main() {
+    let matches = App::new("Capture Comparison")
+        .arg_from_usage("<first> 'The path to the first capture'")
+        .arg_from_usage("<second> 'The path to the second capture'")
+        .arg(Arg::from_usage("-f, --filter <freq> 'Set the collar frequency as an offset from the \
+            centre frequency, e.g. 130000, 130k or -0.25MHz'").allow_hyphen_values(true))
+        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set the sample rate of both captures, e.g. \
+            2e6, 2M or 2MS/s (default: 2MS/s)'")
+        .arg_from_usage("-w, --window [window] 'Set the filter window: rectangular, hann, hamming, \
+            blackman-harris, flat-top or kaiser[:<beta>] (default: blackman-harris)'")
+        .arg_from_usage("-n, --block_size [block_size] 'Set the number of samples in each filter \
+            block (default: 100)'")
+        .arg_from_usage("--pulse_threshold [threshold] 'Detect pulses where the filtered signal \
+            is at least this many dB above the noise floor (default: 10)'")
+        .arg(Arg::from_usage("--offset [offset] 'Set the time in the first capture at which the \
+            second capture starts, e.g. 1.5s or -200ms, instead of finding it by \
+            cross-correlation'").conflicts_with("max_lag").allow_hyphen_values(true))
+        .arg_from_usage("--max_lag [max_lag] 'Set the largest offset searched by \
+            cross-correlation, which should be under half the pulse interval (default: 300ms)'")
+        .arg_from_usage("-o, --output [output] 'Name of a CSV file to write the aligned magnitudes \
+            of both captures to'")
+        .get_matches();
+
+    let paths = [matches.value_of("first").unwrap(), matches.value_of("second").unwrap()];
+
+    let mut filter_settings = FilterSettings::new(parse_arg(&matches, "block_size", parse_count)
+        .unwrap_or(100));
+    if let Some(window) = matches.value_of("window") {
+        filter_settings.window = window.parse::<Window>()
+            .unwrap_or_else(|e| exit_with_error(&e));
+    }
+    if let Err(e) = filter_settings.validate() {
+        exit_with_error(&e);
+    }
+
+    let samp_rate = parse_arg(&matches, "samp_rate", parse_rate).unwrap_or(2e6);
+    let freq = parse_arg(&matches, "filter", parse_frequency).unwrap();
+    let threshold = parse_arg(&matches, "pulse_threshold", parse_level)
+        .unwrap_or(DEFAULT_THRESHOLD);
+
+    let captures: Vec<_> = paths.iter().map(|path| {
+        let data = read_capture(Path::new(path))
+            .unwrap_or_else(|e| exit_with_error(&format!("Unable to read {}: {}", path, e)));
+        Capture::measure(IqConverter::new(data), samp_rate, freq, filter_settings, threshold)
+    }).collect();
+    let (first, second) = (&captures[0], &captures[1]);
+
+    let offset = match parse_arg(&matches, "offset", parse_signed_duration) {
+        Some(offset) => offset,
+        None => {
+            let max_lag = parse_arg(&matches, "max_lag", parse_duration).unwrap_or(DEFAULT_MAX_LAG);
+            let alignment = align(first, second, max_lag).unwrap_or_else(|| {
+                exit_with_error("Unable to align captures shorter than a filter block")
+            });
+            println!("Aligned by cross-correlation (correlation {:.2})", alignment.correlation);
+            if alignment.correlation < MIN_CORRELATION {
+                println!("Warning: the captures are poorly correlated, so the alignment may be \
+                    wrong. Give it with --offset if it is known.");
+            }
+            alignment.offset
+        }
+    };
+
+    let (start, end) = overlap(first, second, offset);
+    if end <= start {
+        exit_with_error("The captures don't overlap at that offset");
+    }
+
+    println!("The second capture starts {:.3} s into the first; comparing {:.3} s to {:.3} s \
+        at {}", offset, start, end, format_frequency(freq));
+    print_stats(&paths, &[first.stats(start, end), second.stats(start - offset, end - offset)]);
+    println!("{} pulses in the second capture line up with pulses in the first",
+        matched_pulses(first, second, offset));
+
+    if let Some(output) = matches.value_of("output") {
+        if let Err(e) = write_magnitudes(Path::new(output), first, second, offset) {
+            exit_with_error(&format!("Unable to write {}: {}", output, e));
+        }
+    }
+}
+
+/// Prints the measurements of both captures side by side
+fn print_stats(paths: &[&str], stats: &[Stats]) {
+    let db = |x: Option<f32>| x.map_or("-".into(), |x| format!("{:.1} dB", x));
+
+    let width = paths.iter().map(|x| x.len()).max().unwrap_or(0).max(10);
+    println!("{:<16}  {:>w$}  {:>w$}", "", paths[0], paths[1], w = width);
+
+    let row = |name: &str, values: Vec<String>| {
+        println!("{:<16}  {:>w$}  {:>w$}", name, values[0], values[1], w = width);
+    };
+    row("Noise floor", stats.iter().map(|x| db(x.noise_floor)).collect());
+    row("Peak SNR", stats.iter().map(|x| db(x.peak_snr)).collect());
+    row("Mean SNR", stats.iter().map(|x| db(x.mean_snr)).collect());
+    row("Peak magnitude", stats.iter().map(|x| format!("{:.4}", x.peak_magnitude)).collect());
+    row("Pulses", stats.iter().map(|x| x.pulses.to_string()).collect());
+}
+
+/// Writes the magnitudes of both captures over the time they overlap, in the first capture's
+/// timeline
+fn write_magnitudes(path: &Path, first: &Capture, second: &Capture, offset: f64)
+    -> io::Result<()>
+{
+    let mut output = BufWriter::new(try!(File::create(path)));
+    try!(writeln!(output, "time,first,second"));
+
+    let shift = (offset * first.rate).round() as isize;
+    for (i, &magnitude) in first.magnitudes.iter().enumerate() {
+        let j = i as isize - shift;
+        if j >= 0 && (j as usize) < second.magnitudes.len() {
+            try!(writeln!(output, "{},{},{}", i as f64 / first.rate, magnitude,
+                second.magnitudes[j as usize]));
+        }
+    }
+
+    output.flush()
+}
@@ -1,5 +1,3 @@
-#![feature(question_mark)]
-
 extern crate byteorder;
 #[macro_use] extern crate clap;
 extern crate iq_converter;
@@ -0,0 +1,73 @@
+//! Helpers shared by the command line tools
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+
+use clap::ArgMatches;
+
+/// Parses an optional argument using `parser`, exiting with an error message if it is invalid
+pub fn parse_arg<T, F>(matches: &ArgMatches, name: &str, parser: F) -> Option<T>
+    where F: Fn(&str) -> Result<T, String>
+{
+    matches.value_of(name).map(|value| parser(value).unwrap_or_else(|e| exit_with_error(&e)))
+}
+
+/// Prints `message` to stderr and exits with a failure status
+pub fn exit_with_error(message: &str) -> ! {
+    let _ = writeln!(io::stderr(), "{}", message);
+    process::exit(1);
+}
+
+/// Reads a whole capture into memory
+pub fn read_capture(path: &Path) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    try!(try!(File::open(path)).read_to_end(&mut data));
+    Ok(data)
+}
+
+/// Prints a status message, to stderr if stdout is being used for the tool's output
+pub fn status(to_stderr: bool, message: &str) {
+    if to_stderr {
+        let _ = writeln!(io::stderr(), "{}", message);
+    }
+    else {
+        println!("{}", message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use clap::App;
+
+    use units::parse_count;
+
+    use super::*;
+
+    #[test]
+    fn parses_given_arguments() {
+        let matches = App::new("test").arg_from_usage("-n, --count [count] 'A count'")
+            .get_matches_from(vec!["test", "-n", "12"]);
+        assert_eq!(parse_arg(&matches, "count", parse_count), Some(12));
+
+        let matches = App::new("test").arg_from_usage("-n, --count [count] 'A count'")
+            .get_matches_from(vec!["test"]);
+        assert_eq!(parse_arg(&matches, "count", parse_count), None);
+    }
+
+    #[test]
+    fn reads_captures() {
+        let path = env::temp_dir().join(format!("iq_converter_capture_{}", process::id()));
+        File::create(&path).unwrap().write_all(&[1, 2, 255]).unwrap();
+        let data = read_capture(&path);
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(data.unwrap(), vec![1, 2, 255]);
+        assert!(read_capture(&path).is_err());
+    }
+}
@@ -0,0 +1,129 @@
+//! A radix-2 fast Fourier transform over complex samples stored as (re, im) pairs
+
+use std::f32::consts::PI;
+
+/// A precomputed radix-2 FFT of a fixed power of two size
+pub struct Fft {
+    size: usize,
+    twiddles: Vec<(f32, f32)>,
+    reversed: Vec<usize>,
+}
+
+impl Fft {
+    /// Creates an FFT for blocks of `size` samples. Panics if `size` is not a power of two.
+    pub fn new(size: usize) -> Fft {
+        assert!(size.is_power_of_two(), "FFT size must be a power of two");
+
+        let bits = size.trailing_zeros();
+        let reversed = (0..size).map(|i| reverse_bits(i, bits)).collect();
+
+        let twiddles = (0..size / 2).map(|k| {
+            let angle = -2.0 * PI * k as f32 / size as f32;
+            (angle.cos(), angle.sin())
+        }).collect();
+
+        Fft {
+            size: size,
+            twiddles: twiddles,
+            reversed: reversed,
+        }
+    }
+
+    /// The number of samples transformed at once
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Transforms `data` in place. `data` must contain exactly `size` samples.
+    pub fn process(&self, data: &mut [(f32, f32)]) {
+        assert_eq!(data.len(), self.size);
+
+        for i in 0..self.size {
+            let j = self.reversed[i];
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= self.size {
+            let half = len / 2;
+            let stride = self.size / len;
+            let mut start = 0;
+            while start < self.size {
+                for k in 0..half {
+                    let (wr, wi) = self.twiddles[k * stride];
+                    let (br, bi) = data[start + k + half];
+                    let t = (br * wr - bi * wi, br * wi + bi * wr);
+                    let a = data[start + k];
+                    data[start + k] = (a.0 + t.0, a.1 + t.1);
+                    data[start + k + half] = (a.0 - t.0, a.1 - t.1);
+                }
+                start += len;
+            }
+            len *= 2;
+        }
+    }
+}
+
+/// Reverses the lowest `bits` bits of `value`
+fn reverse_bits(mut value: usize, bits: u32) -> usize {
+    let mut result = 0;
+    for _ in 0..bits {
+        result = (result << 1) | (value & 1);
+        value >>= 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Computes the discrete Fourier transform of `data` directly
+    fn dft(data: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        let n = data.len();
+        (0..n).map(|k| {
+            data.iter().enumerate().fold((0.0, 0.0), |acc, (t, &(re, im))| {
+                let angle = -2.0 * PI * (k * t % n) as f32 / n as f32;
+                let (cos, sin) = (angle.cos(), angle.sin());
+                (acc.0 + re * cos - im * sin, acc.1 + re * sin + im * cos)
+            })
+        }).collect()
+    }
+
+    #[test]
+    fn reverses_bits() {
+        assert_eq!(reverse_bits(0b0001, 4), 0b1000);
+        assert_eq!(reverse_bits(0b0110, 4), 0b0110);
+        assert_eq!(reverse_bits(0b1011, 4), 0b1101);
+    }
+
+    #[test]
+    fn impulse_has_a_flat_spectrum() {
+        let mut data = vec![(0.0, 0.0); 16];
+        data[0] = (1.0, 0.0);
+        Fft::new(16).process(&mut data);
+        assert!(data.iter().all(|&x| x == (1.0, 0.0)));
+    }
+
+    #[test]
+    fn matches_direct_transform() {
+        let data: Vec<_> = (0..64).map(|i| {
+            let x = i as f32;
+            ((0.3 * x).sin() + 0.1 * (x % 5.0), (0.7 * x).cos() - 0.05 * x)
+        }).collect();
+
+        let mut fast = data.clone();
+        Fft::new(64).process(&mut fast);
+        for (a, b) in fast.iter().zip(dft(&data)) {
+            assert!((a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_sizes_that_are_not_powers_of_two() {
+        Fft::new(100);
+    }
+}
@@ -0,0 +1,409 @@
+//! Block based Goertzel filtering of I/Q streams.
+//!
+//! All of the tools compute their magnitude streams through this module so that the same settings
+//! always produce the same output.
+
+use std::collections::VecDeque;
+use std::f32;
+use std::f32::consts::PI;
+
+use window::Window;
+
+/// Settings controlling how an I/Q stream is split into blocks for filtering
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FilterSettings {
+    /// The window applied to each block
+    pub window: Window,
+
+    /// The number of samples in each block
+    pub block_size: usize,
+
+    /// The number of samples between the start of consecutive blocks. A hop smaller than the
+    /// block size gives overlapping blocks.
+    pub hop: usize,
+}
+
+impl FilterSettings {
+    /// Creates settings for non-overlapping blocks of `block_size` samples using the default window
+    pub fn new(block_size: usize) -> FilterSettings {
+        FilterSettings {
+            window: Window::default(),
+            block_size: block_size,
+            hop: block_size,
+        }
+    }
+
+    /// Checks that the settings describe a usable filter
+    pub fn validate(&self) -> Result<(), String> {
+        if self.block_size == 0 {
+            return Err("Block size must be greater than zero".into());
+        }
+        if self.hop == 0 {
+            return Err("Hop size must be greater than zero".into());
+        }
+        Ok(())
+    }
+
+    /// The rate at which magnitudes are produced for an input stream sampled at `samp_rate`
+    pub fn output_rate(&self, samp_rate: f64) -> f64 {
+        samp_rate / self.hop as f64
+    }
+}
+
+/// Computes the magnitude of a windowed block of samples at `freq` (in Hz), normalised so that a
+/// complex tone with amplitude `a` at `freq` gives a magnitude of `a`.
+pub fn block_magnitude<'a, I>(block: I, window: &[f32], samp_rate: f32, freq: f32) -> f32
+    where I: IntoIterator<Item=&'a (f32, f32)>
+{
+    let omega = 2.0 * PI * freq / samp_rate;
+    let (cos, sin) = (omega.cos(), omega.sin());
+    let coeff = 2.0 * cos;
+
+    let (mut s1, mut s2) = ((0.0, 0.0), (0.0, 0.0));
+    let mut gain = 0.0;
+    for (&(i, q), &w) in block.into_iter().zip(window) {
+        let s0 = (i * w + coeff * s1.0 - s2.0, q * w + coeff * s1.1 - s2.1);
+        s2 = s1;
+        s1 = s0;
+        gain += w;
+    }
+
+    let re = s1.0 - (cos * s2.0 + sin * s2.1);
+    let im = s1.1 - (cos * s2.1 - sin * s2.0);
+
+    if gain == 0.0 { 0.0 } else { (re * re + im * im).sqrt() / gain }
+}
+
+/// Splits an I/Q stream into (possibly overlapping) windowed blocks
+pub struct Blocks<I> {
+    input: I,
+    block: VecDeque<(f32, f32)>,
+    settings: FilterSettings,
+    started: bool,
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Blocks<I> {
+    pub fn new(input: I, settings: FilterSettings) -> Blocks<I> {
+        Blocks {
+            input: input,
+            block: VecDeque::with_capacity(settings.block_size),
+            settings: settings,
+            started: false,
+        }
+    }
+
+    /// Advances to the next full block, returning `None` once the input runs out
+    pub fn next_block(&mut self) -> Option<&VecDeque<(f32, f32)>> {
+        if self.started {
+            let hop = self.settings.hop;
+            let dropped = hop.min(self.block.len());
+            self.block.drain(..dropped);
+            for _ in dropped..hop {
+                if self.input.next().is_none() {
+                    return None;
+                }
+            }
+        }
+        self.started = true;
+
+        while self.block.len() < self.settings.block_size {
+            match self.input.next() {
+                Some(sample) => self.block.push_back(sample),
+                None => return None,
+            }
+        }
+
+        Some(&self.block)
+    }
+}
+
+/// An iterator over the Goertzel magnitudes of an I/Q stream at a fixed frequency
+pub struct Magnitudes<I> {
+    blocks: Blocks<I>,
+    window: Vec<f32>,
+    samp_rate: f32,
+    freq: f32,
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Magnitudes<I> {
+    pub fn new<T>(input: T, samp_rate: f32, freq: f32, settings: FilterSettings) -> Magnitudes<I>
+        where T: IntoIterator<IntoIter=I, Item=(f32, f32)>
+    {
+        Magnitudes {
+            blocks: Blocks::new(input.into_iter(), settings),
+            window: settings.window.coefficients(settings.block_size),
+            samp_rate: samp_rate,
+            freq: freq,
+        }
+    }
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Iterator for Magnitudes<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let (window, samp_rate, freq) = (&self.window, self.samp_rate, self.freq);
+        self.blocks.next_block().map(|block| block_magnitude(block, window, samp_rate, freq))
+    }
+}
+
+/// How much stronger than the signal outside the filter's main lobe the strongest frequency in the
+/// search band must be before the tracker will move to it.
+const TRACK_PROMINENCE: f32 = 3.0;
+
+/// The number of frequencies either side of the band that the tracked tone is compared with. Noise
+/// rarely stands out from the strongest of several.
+const TRACK_REFERENCES: usize = 4;
+
+/// The most steps either side of the nominal frequency that a tracking search may test, each of
+/// which costs a Goertzel filter per block
+pub const MAX_TRACK_STEPS: usize = 500;
+
+/// Settings for following a tone that drifts around its nominal frequency
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrackingSettings {
+    /// The maximum distance (in Hz) from the nominal frequency that is searched
+    pub band: f32,
+
+    /// The spacing (in Hz) between the frequencies tested within the band
+    pub step: f32,
+}
+
+impl TrackingSettings {
+    /// Creates settings that search `band` Hz either side of the nominal frequency in ten steps
+    pub fn new(band: f32) -> TrackingSettings {
+        TrackingSettings {
+            band: band,
+            step: band / 10.0,
+        }
+    }
+
+    /// Checks that the settings describe a usable search
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.band >= 0.0) {
+            return Err("Tracking band must be non-negative".into());
+        }
+        if !(self.step > 0.0) && self.band > 0.0 {
+            return Err("Tracking step must be greater than zero".into());
+        }
+        if self.band > 0.0 && !((self.band / self.step).floor() <= MAX_TRACK_STEPS as f32) {
+            return Err(format!("Tracking band can be at most {} steps either side of the filter \
+                frequency, use a larger step", MAX_TRACK_STEPS));
+        }
+        Ok(())
+    }
+
+    /// The frequency offsets from the nominal frequency that are tested in each block
+    fn offsets(&self) -> Vec<f32> {
+        if self.band == 0.0 {
+            return vec![0.0];
+        }
+        let steps = (self.band / self.step).floor() as i32;
+        (-steps..steps + 1).map(|k| k as f32 * self.step).collect()
+    }
+}
+
+/// An iterator that follows the strongest tone within a band around a nominal frequency, yielding
+/// the tracked frequency (in Hz) and the magnitude at that frequency for each block.
+///
+/// When no tone stands out (e.g. between pulses), the previously tracked frequency is held so that
+/// the frequency track does not wander with the noise. A tone stands out when it is stronger than
+/// the signal at frequencies spaced by the width of the window's main lobe beyond either side of
+/// the band, so that drift can be followed even when the whole band is within the main lobe of a
+/// short block. Another strong tone at one of those frequencies stops the tracker from moving.
+pub struct TrackingMagnitudes<I> {
+    blocks: Blocks<I>,
+    window: Vec<f32>,
+    samp_rate: f32,
+    nominal: f32,
+    offsets: Vec<f32>,
+    step: f32,
+
+    /// The offsets of the frequencies the tracked tone is compared with
+    references: Vec<f32>,
+    current: f32,
+    magnitudes: Vec<f32>,
+}
+
+impl<I: Iterator<Item=(f32, f32)>> TrackingMagnitudes<I> {
+    pub fn new<T>(input: T, samp_rate: f32, freq: f32, settings: FilterSettings,
+        tracking: TrackingSettings) -> TrackingMagnitudes<I>
+        where T: IntoIterator<IntoIter=I, Item=(f32, f32)>
+    {
+        let offsets = tracking.offsets();
+        let lobe = settings.window.main_lobe_width() * samp_rate / settings.block_size as f32;
+        let edge = offsets[offsets.len() - 1];
+        let references = (1..TRACK_REFERENCES + 1)
+            .flat_map(|k| vec![-edge - k as f32 * lobe, edge + k as f32 * lobe])
+            .collect();
+        TrackingMagnitudes {
+            blocks: Blocks::new(input.into_iter(), settings),
+            window: settings.window.coefficients(settings.block_size),
+            samp_rate: samp_rate,
+            nominal: freq,
+            magnitudes: vec![0.0; offsets.len()],
+            offsets: offsets,
+            step: tracking.step,
+            references: references,
+            current: freq,
+        }
+    }
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Iterator for TrackingMagnitudes<I> {
+    type Item = (f32, f32);
+
+    fn next(&mut self) -> Option<(f32, f32)> {
+        let block = match self.blocks.next_block() {
+            Some(block) => block,
+            None => return None,
+        };
+
+        for (magnitude, &offset) in self.magnitudes.iter_mut().zip(&self.offsets) {
+            *magnitude = block_magnitude(block, &self.window, self.samp_rate,
+                self.nominal + offset);
+        }
+
+        let (peak_index, peak) = self.magnitudes.iter().cloned().enumerate()
+            .fold((0, f32::NAN), |best, (i, x)| if !(x <= best.1) { (i, x) } else { best });
+
+        let n = self.magnitudes.len();
+        let (window, samp_rate, nominal) = (&self.window, self.samp_rate, self.nominal);
+        let rest = self.references.iter()
+            .map(|&offset| block_magnitude(block, window, samp_rate, nominal + offset))
+            .fold(0.0, f32::max);
+
+        if n == 1 || peak > TRACK_PROMINENCE * rest {
+            // Refine the estimate by fitting a parabola through the peak and its neighbours
+            let mut delta = 0.0;
+            if peak_index > 0 && peak_index + 1 < n {
+                let (a, b, c) = (self.magnitudes[peak_index - 1], peak,
+                    self.magnitudes[peak_index + 1]);
+                let denom = a - 2.0 * b + c;
+                if denom != 0.0 {
+                    delta = (0.5 * (a - c) / denom).max(-0.5).min(0.5);
+                }
+            }
+            self.current = self.nominal + self.offsets[peak_index] + delta * self.step;
+            Some((self.current, peak))
+        }
+        else {
+            Some((self.current, block_magnitude(block, &self.window, self.samp_rate,
+                self.current)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The default sample rate of the tools
+    const SAMP_RATE: f32 = 2e6;
+
+    fn tone(freq: f32, amplitude: f32, len: usize) -> Vec<(f32, f32)> {
+        (0..len).map(|n| {
+            let phase = 2.0 * PI * freq * n as f32 / SAMP_RATE;
+            (amplitude * phase.cos(), amplitude * phase.sin())
+        }).collect()
+    }
+
+    fn count_blocks(len: usize, block_size: usize, hop: usize) -> usize {
+        let mut settings = FilterSettings::new(block_size);
+        settings.hop = hop;
+        let mut blocks = Blocks::new(tone(0.0, 1.0, len).into_iter(), settings);
+        let mut count = 0;
+        while blocks.next_block().is_some() {
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn bin_centred_tone_magnitude() {
+        // 100 kHz is the centre of bin 5 of a 100 sample block
+        let block = tone(100e3, 0.5, 100);
+        for &window in &[Window::Rectangular, Window::Hann, Window::BlackmanHarris] {
+            let coefficients = window.coefficients(100);
+            let magnitude = block_magnitude(&block, &coefficients, SAMP_RATE, 100e3);
+            assert!((magnitude - 0.5).abs() < 1e-3, "{}: {}", window, magnitude);
+        }
+
+        // Without a window the other bins fall on the nulls of the tone's response
+        let rectangular = Window::Rectangular.coefficients(100);
+        assert!(block_magnitude(&block, &rectangular, SAMP_RATE, 120e3) < 1e-3);
+        assert!(block_magnitude(&block, &rectangular, SAMP_RATE, -100e3) < 1e-3);
+    }
+
+    /// Follows a tone 300 Hz above the nominal frequency, which pulses on for the first half of the
+    /// input and is followed by noise, returning the tracked frequency at the end of each half
+    fn track_pulse(block_size: usize, band: f32) -> (f32, f32) {
+        let len = 40 * block_size;
+        let mut input = tone(130.3e3, 0.5, len / 2);
+
+        // Uniform noise from a fixed linear congruential generator, so the test is repeatable
+        let mut seed = 1u32;
+        let mut noise = || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32 * 0.02 - 0.01
+        };
+        for _ in len / 2..len {
+            let sample = (noise(), noise());
+            input.push(sample);
+        }
+
+        let track: Vec<_> = TrackingMagnitudes::new(input, SAMP_RATE, 130e3,
+            FilterSettings::new(block_size), TrackingSettings::new(band)).map(|x| x.0).collect();
+        (track[track.len() / 2 - 1], track[track.len() - 1])
+    }
+
+    #[test]
+    fn tracks_drift_at_the_default_block_sizes() {
+        // The defaults of signal_to_wav and viscope, where the whole band is within the main lobe
+        for &(block_size, band) in &[(100, 500.0), (1000, 500.0), (1000, 5000.0)] {
+            let (pulse, noise) = track_pulse(block_size, band);
+            assert!((pulse - 130.3e3).abs() < 50.0, "block {} band {}: tracked {} Hz",
+                block_size, band, pulse);
+            assert_eq!(noise, pulse);
+        }
+    }
+
+    #[test]
+    fn tracks_drift_with_resolved_band() {
+        let (pulse, noise) = track_pulse(20000, 500.0);
+        assert!((pulse - 130.3e3).abs() < 10.0, "tracked {} Hz", pulse);
+        assert_eq!(noise, pulse);
+    }
+
+    #[test]
+    fn block_counts() {
+        assert_eq!(count_blocks(1000, 100, 100), 10);
+        assert_eq!(count_blocks(1000, 100, 50), 19);
+        assert_eq!(count_blocks(1000, 100, 200), 5);
+        assert_eq!(count_blocks(99, 100, 100), 0);
+    }
+
+    #[test]
+    fn magnitudes_follow_the_settings() {
+        let mut settings = FilterSettings::new(100);
+        settings.hop = 25;
+        let magnitudes: Vec<_> = Magnitudes::new(tone(100e3, 0.5, 1000), SAMP_RATE, 100e3,
+            settings).collect();
+        assert_eq!(magnitudes.len(), 37);
+        assert!(magnitudes.iter().all(|&x| (x - 0.5).abs() < 1e-3));
+        assert_eq!(settings.output_rate(1e6), 40e3);
+    }
+
+    #[test]
+    fn limits_the_tracking_steps() {
+        let settings = |band, step| TrackingSettings { band: band, step: step };
+        assert!(TrackingSettings::new(1000.0).validate().is_ok());
+        assert!(settings(0.0, 0.0).validate().is_ok());
+        assert!(settings(-1.0, 1.0).validate().is_err());
+        assert!(settings(100.0, 0.0).validate().is_err());
+        assert!(settings(500.0, 1.0).validate().is_ok());
+        assert!(settings(501.0, 1.0).validate().is_err());
+        assert!(settings(1e6, 0.001).validate().is_err());
+        assert!(settings(f32::INFINITY, 1.0).validate().is_err());
+    }
+}
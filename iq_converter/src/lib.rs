@@ -1,3 +1,14 @@
+extern crate clap;
+
+pub mod cli;
+pub mod fft;
+pub mod goertzel;
+pub mod noise;
+pub mod pulse;
+pub mod spectrum;
+pub mod units;
+pub mod window;
+
 use std::iter::{Iterator, IntoIterator};
 
 fn gen_lookup_table() -> [f32; 256] {
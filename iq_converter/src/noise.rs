@@ -0,0 +1,114 @@
+//! Estimation of the noise floor of a magnitude stream
+
+/// The default rate (in dB per second) at which the noise floor estimate adapts
+pub const DEFAULT_ADAPT_RATE: f32 = 20.0;
+
+/// How much faster the estimate adapts during the first second, so that it settles quickly
+/// however far the first magnitude is from the noise floor
+const WARMUP_SPEEDUP: f32 = 10.0;
+
+/// Tracks the median level of a magnitude stream, which for a pulsed beacon with a duty cycle well
+/// under 50% is the level of the noise between the pulses.
+///
+/// The estimate is kept in dB and nudged up or down by a fixed step for each magnitude above or
+/// below it, so it follows slow changes in the noise (e.g. as the drone moves) while ignoring
+/// short pulses.
+#[derive(Clone, Debug)]
+pub struct NoiseFloor {
+    floor_db: Option<f32>,
+    step: f32,
+    warmup: usize,
+}
+
+impl NoiseFloor {
+    /// Creates an estimator for a magnitude stream produced at `rate` magnitudes per second that
+    /// adapts by up to `adapt_rate` dB per second
+    pub fn new(rate: f64, adapt_rate: f32) -> NoiseFloor {
+        NoiseFloor {
+            floor_db: None,
+            step: (adapt_rate as f64 / rate.max(1.0)) as f32,
+            warmup: rate.max(1.0) as usize,
+        }
+    }
+
+    /// Updates the estimate with the next magnitude, returning the noise floor in dB
+    pub fn update(&mut self, magnitude: f32) -> f32 {
+        let level = magnitude_to_db(magnitude);
+
+        let step = if self.warmup > 0 { self.step * WARMUP_SPEEDUP } else { self.step };
+        self.warmup = self.warmup.saturating_sub(1);
+
+        let floor = match self.floor_db {
+            Some(floor) if level > floor => floor + step,
+            Some(floor) if level < floor => floor - step,
+            Some(floor) => floor,
+            None => level,
+        };
+        self.floor_db = Some(floor);
+        floor
+    }
+
+    /// The current noise floor estimate in dB, if any magnitudes have been seen
+    pub fn floor_db(&self) -> Option<f32> {
+        self.floor_db
+    }
+}
+
+/// Converts a magnitude to dB, clamping silence to a finite floor
+pub fn magnitude_to_db(magnitude: f32) -> f32 {
+    20.0 * magnitude.max(1e-10).log10()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rate of the magnitude streams in the tests
+    const RATE: f64 = 1000.0;
+
+    fn run(noise_floor: &mut NoiseFloor, magnitude: f32, count: usize) -> f32 {
+        (0..count).fold(0.0, |_, _| noise_floor.update(magnitude))
+    }
+
+    #[test]
+    fn starts_at_the_first_magnitude() {
+        let mut noise_floor = NoiseFloor::new(RATE, DEFAULT_ADAPT_RATE);
+        assert_eq!(noise_floor.floor_db(), None);
+        assert_eq!(noise_floor.update(0.01), -40.0);
+        assert_eq!(noise_floor.floor_db(), Some(-40.0));
+    }
+
+    #[test]
+    fn settles_within_the_first_second() {
+        // Starting 60 dB above the noise, the warm up moves the estimate 200 dB in the first second
+        let mut noise_floor = NoiseFloor::new(RATE, DEFAULT_ADAPT_RATE);
+        noise_floor.update(1.0);
+        let floor = run(&mut noise_floor, 0.001, 400);
+        assert!((floor + 60.0).abs() <= 0.2, "floor {}", floor);
+    }
+
+    #[test]
+    fn adapts_at_the_adapt_rate() {
+        let mut noise_floor = NoiseFloor::new(RATE, DEFAULT_ADAPT_RATE);
+        run(&mut noise_floor, 0.001, 1000);
+
+        // After the warm up, a 20 dB rise in the noise is followed at 20 dB per second
+        let floor = run(&mut noise_floor, 0.01, 250);
+        assert!((floor + 55.0).abs() < 0.1, "floor {}", floor);
+        let floor = run(&mut noise_floor, 0.01, 800);
+        assert!((floor + 40.0).abs() <= 0.02, "floor {}", floor);
+    }
+
+    #[test]
+    fn ignores_short_pulses() {
+        let mut noise_floor = NoiseFloor::new(RATE, DEFAULT_ADAPT_RATE);
+        run(&mut noise_floor, 0.001, 1000);
+
+        // 20 ms pulses 40 dB above the noise every 200 ms
+        for _ in 0..20 {
+            run(&mut noise_floor, 0.1, 20);
+            let floor = run(&mut noise_floor, 0.001, 180);
+            assert!((floor + 60.0).abs() <= 0.02, "floor {}", floor);
+        }
+    }
+}
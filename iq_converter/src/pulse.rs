@@ -0,0 +1,164 @@
+//! Detection of beacon pulses in a magnitude stream
+
+use noise::{magnitude_to_db, NoiseFloor, DEFAULT_ADAPT_RATE};
+
+/// The default level (in dB above the noise floor) a magnitude must reach to be part of a pulse
+pub const DEFAULT_THRESHOLD: f32 = 10.0;
+
+/// The shortest run (in seconds) above the threshold that is treated as a pulse. Collar pulses last
+/// tens of milliseconds, while noise only occasionally reaches the threshold for a block or two.
+const MIN_DURATION: f64 = 0.005;
+
+/// The longest dip (in seconds) below the threshold that is treated as part of the same pulse
+const MAX_GAP: f64 = 0.002;
+
+/// A pulse found in a magnitude stream
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pulse {
+    /// The index of the first magnitude in the pulse
+    pub start: usize,
+
+    /// The index one past the last magnitude in the pulse
+    pub end: usize,
+
+    /// The largest magnitude in the pulse
+    pub peak: f32,
+
+    /// How far (in dB) the largest magnitude was above the noise floor
+    pub snr: f32,
+}
+
+/// Finds runs of magnitudes more than a threshold above the noise floor
+pub struct PulseDetector {
+    noise_floor: NoiseFloor,
+    threshold: f32,
+    min_length: usize,
+    max_gap: usize,
+    index: usize,
+    gap: usize,
+    current: Option<Pulse>,
+}
+
+impl PulseDetector {
+    /// Creates a detector for a magnitude stream produced at `rate` magnitudes per second, which
+    /// finds pulses at least `threshold` dB above the noise floor
+    pub fn new(rate: f64, threshold: f32) -> PulseDetector {
+        PulseDetector {
+            noise_floor: NoiseFloor::new(rate, DEFAULT_ADAPT_RATE),
+            threshold: threshold,
+            min_length: ((MIN_DURATION * rate).round() as usize).max(1),
+            max_gap: (MAX_GAP * rate).round() as usize,
+            index: 0,
+            gap: 0,
+            current: None,
+        }
+    }
+
+    /// Processes the next magnitude, returning a pulse if this magnitude completed one
+    pub fn process(&mut self, magnitude: f32) -> Option<Pulse> {
+        let floor = self.noise_floor.update(magnitude);
+        let snr = magnitude_to_db(magnitude) - floor;
+        let index = self.index;
+        self.index += 1;
+
+        if snr < self.threshold {
+            if self.current.is_some() {
+                self.gap += 1;
+                if self.gap > self.max_gap {
+                    return self.finish();
+                }
+            }
+            return None;
+        }
+
+        self.gap = 0;
+        let pulse = self.current.get_or_insert(Pulse {
+            start: index,
+            end: index,
+            peak: magnitude,
+            snr: snr,
+        });
+        pulse.end = index + 1;
+        if magnitude > pulse.peak {
+            pulse.peak = magnitude;
+            pulse.snr = snr;
+        }
+        None
+    }
+
+    /// The current noise floor estimate in dB, if any magnitudes have been seen
+    pub fn noise_floor_db(&self) -> Option<f32> {
+        self.noise_floor.floor_db()
+    }
+
+    /// Completes any pulse in progress, e.g. at the end of the stream
+    pub fn finish(&mut self) -> Option<Pulse> {
+        self.gap = 0;
+        match self.current.take() {
+            Some(pulse) if pulse.end - pulse.start >= self.min_length => Some(pulse),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rate of the magnitude streams in the tests, at which pulses must last 5 magnitudes and
+    /// gaps of up to 2 magnitudes are bridged
+    const RATE: f64 = 1000.0;
+
+    const NOISE: f32 = 0.001;
+    const PULSE: f32 = 0.1;
+
+    /// Runs a detector over a second of noise followed by `levels`, then the end of the stream
+    fn detect(levels: &[f32]) -> Vec<Pulse> {
+        let mut detector = PulseDetector::new(RATE, DEFAULT_THRESHOLD);
+        let mut pulses: Vec<_> = (0..1000).filter_map(|_| detector.process(NOISE)).collect();
+        pulses.extend(levels.iter().filter_map(|&x| detector.process(x)));
+        pulses.extend(detector.finish());
+        pulses
+    }
+
+    /// Builds a stream from runs of noise and pulse magnitudes
+    fn stream(runs: &[(f32, usize)]) -> Vec<f32> {
+        runs.iter().flat_map(|&(level, count)| vec![level; count]).collect()
+    }
+
+    #[test]
+    fn finds_pulses() {
+        let pulses = detect(&stream(&[(PULSE, 20), (NOISE, 100), (PULSE * 2.0, 10), (NOISE, 10)]));
+        assert_eq!(pulses.len(), 2);
+
+        assert_eq!((pulses[0].start, pulses[0].end), (1000, 1020));
+        assert_eq!(pulses[0].peak, PULSE);
+        assert!((pulses[0].snr - 40.0).abs() < 1.0, "snr {}", pulses[0].snr);
+        assert_eq!((pulses[1].start, pulses[1].end), (1120, 1130));
+        assert_eq!(pulses[1].peak, PULSE * 2.0);
+    }
+
+    #[test]
+    fn ignores_pulses_shorter_than_min_duration() {
+        assert!(detect(&stream(&[(PULSE, 4), (NOISE, 100)])).is_empty());
+        assert_eq!(detect(&stream(&[(PULSE, 5), (NOISE, 100)])).len(), 1);
+    }
+
+    #[test]
+    fn merges_pulses_across_short_gaps() {
+        let pulses = detect(&stream(&[(PULSE, 10), (NOISE, 2), (PULSE, 10), (NOISE, 100)]));
+        assert_eq!(pulses.len(), 1);
+        assert_eq!((pulses[0].start, pulses[0].end), (1000, 1022));
+
+        let pulses = detect(&stream(&[(PULSE, 10), (NOISE, 3), (PULSE, 10), (NOISE, 100)]));
+        assert_eq!(pulses.len(), 2);
+        assert_eq!((pulses[1].start, pulses[1].end), (1013, 1023));
+    }
+
+    #[test]
+    fn finishes_pulse_at_end_of_stream() {
+        let pulses = detect(&[PULSE; 10]);
+        assert_eq!(pulses.len(), 1);
+        assert_eq!((pulses[0].start, pulses[0].end), (1000, 1010));
+    }
+}
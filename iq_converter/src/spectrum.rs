@@ -0,0 +1,131 @@
+//! Short-time power spectra of I/Q streams
+
+use fft::Fft;
+use goertzel::{Blocks, FilterSettings};
+
+/// Converts a power ratio to decibels, clamping silence to a finite floor
+pub fn to_db(power: f32) -> f32 {
+    10.0 * power.max(1e-20).log10()
+}
+
+/// Gets the frequency (in Hz) at the centre of a bin of a spectrum produced by `Spectra`
+pub fn bin_frequency(bin: usize, size: usize, samp_rate: f64) -> f64 {
+    (bin as f64 - (size / 2) as f64) * samp_rate / size as f64
+}
+
+/// Gets the bin of a spectrum produced by `Spectra` closest to `freq` (in Hz)
+pub fn frequency_bin(freq: f64, size: usize, samp_rate: f64) -> usize {
+    let bin = (freq * size as f64 / samp_rate).round() + (size / 2) as f64;
+    bin.max(0.0).min(size as f64 - 1.0) as usize
+}
+
+/// An iterator over the power spectra of consecutive (possibly overlapping) windowed blocks of an
+/// I/Q stream.
+///
+/// Each spectrum contains `block_size` bins of linear power, ordered from `-samp_rate / 2` up to
+/// just below `+samp_rate / 2`. Spectra are normalised so that a complex tone with amplitude `a`
+/// centred in a bin has a power of `a^2`, matching the magnitudes computed by the Goertzel filter.
+pub struct Spectra<I> {
+    blocks: Blocks<I>,
+    window: Vec<f32>,
+    fft: Fft,
+    gain: f32,
+    buffer: Vec<(f32, f32)>,
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Spectra<I> {
+    /// Creates a new iterator. Panics if the block size is not a power of two.
+    pub fn new<T>(input: T, settings: FilterSettings) -> Spectra<I>
+        where T: IntoIterator<IntoIter=I, Item=(f32, f32)>
+    {
+        let window = settings.window.coefficients(settings.block_size);
+        let gain = window.iter().fold(0.0, |acc, &x| acc + x);
+
+        Spectra {
+            blocks: Blocks::new(input.into_iter(), settings),
+            window: window,
+            fft: Fft::new(settings.block_size),
+            gain: gain,
+            buffer: Vec::with_capacity(settings.block_size),
+        }
+    }
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Iterator for Spectra<I> {
+    type Item = Vec<f32>;
+
+    fn next(&mut self) -> Option<Vec<f32>> {
+        let block = match self.blocks.next_block() {
+            Some(block) => block,
+            None => return None,
+        };
+
+        self.buffer.clear();
+        self.buffer.extend(block.iter().zip(&self.window).map(|(&(i, q), &w)| (i * w, q * w)));
+        self.fft.process(&mut self.buffer);
+
+        let size = self.buffer.len();
+        let scale = 1.0 / (self.gain * self.gain);
+        Some((0..size).map(|bin| {
+            let (re, im) = self.buffer[(bin + size / 2) % size];
+            (re * re + im * im) * scale
+        }).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use window::Window;
+
+    use super::*;
+
+    const SAMP_RATE: f64 = 2e6;
+
+    fn tone(freq: f64, amplitude: f32, len: usize) -> Vec<(f32, f32)> {
+        (0..len).map(|n| {
+            let phase = 2.0 * PI * (freq / SAMP_RATE) as f32 * n as f32;
+            (amplitude * phase.cos(), amplitude * phase.sin())
+        }).collect()
+    }
+
+    #[test]
+    fn converts_power_to_db() {
+        assert_eq!(to_db(1.0), 0.0);
+        assert!((to_db(0.01) + 20.0).abs() < 1e-4);
+        assert_eq!(to_db(0.0), -200.0);
+    }
+
+    #[test]
+    fn maps_bins_to_frequencies() {
+        assert_eq!(bin_frequency(0, 1024, SAMP_RATE), -1e6);
+        assert_eq!(bin_frequency(512, 1024, SAMP_RATE), 0.0);
+        for &bin in &[0, 100, 512, 1023] {
+            assert_eq!(frequency_bin(bin_frequency(bin, 1024, SAMP_RATE), 1024, SAMP_RATE), bin);
+        }
+        assert_eq!(frequency_bin(-5e6, 1024, SAMP_RATE), 0);
+        assert_eq!(frequency_bin(5e6, 1024, SAMP_RATE), 1023);
+    }
+
+    #[test]
+    fn bin_centred_tone_has_its_power() {
+        let freq = bin_frequency(600, 1024, SAMP_RATE);
+        for &window in &[Window::Rectangular, Window::BlackmanHarris] {
+            let mut settings = FilterSettings::new(1024);
+            settings.window = window;
+
+            let spectrum = Spectra::new(tone(freq, 0.5, 1024), settings).next().unwrap();
+            assert_eq!(spectrum.len(), 1024);
+            assert!((spectrum[600] - 0.25).abs() < 1e-3, "{}: {}", window, spectrum[600]);
+            assert!(spectrum[300] < 1e-6);
+        }
+    }
+
+    #[test]
+    fn overlapping_spectra() {
+        let mut settings = FilterSettings::new(256);
+        settings.hop = 128;
+        assert_eq!(Spectra::new(tone(0.0, 1.0, 1024), settings).count(), 7);
+    }
+}
@@ -0,0 +1,309 @@
+//! Parsing of human friendly frequencies, sample rates, durations and levels, e.g. `130k`,
+//! `-0.25MHz`, `2MS/s`, `20ms` or `-90dB`.
+//!
+//! A lowercase `m` is never accepted as a prefix for frequencies or rates, since `2m` or `2ms/s`
+//! is far more likely to be a mistyped `2M` than a request for milli-hertz.
+
+/// Parses a frequency in Hz. Accepts an optional SI prefix (`k`, `M`, `G`) and an optional `Hz`
+/// unit, e.g. `130000`, `130k`, `130kHz` or `-0.25MHz`.
+pub fn parse_frequency(value: &str) -> Result<f64, String> {
+    let (number, suffix) = try!(split_number(value, "frequency"));
+    let prefix = strip_unit(suffix, &["hz"]);
+    let multiplier = try!(large_prefix(prefix, "Hz").map_err(|e| {
+        format!("Invalid frequency `{}`: {}", value.trim(), e)
+    }));
+
+    scale(number, multiplier, value, "frequency")
+}
+
+/// Parses a sample rate in samples per second. Accepts an optional SI prefix (`k`, `M`, `G`) and
+/// an optional unit (`S/s`, `Sa/s`, `sps` or `Hz`), e.g. `2e6`, `2M`, `2MS/s` or `10Msps`.
+pub fn parse_rate(value: &str) -> Result<f64, String> {
+    let (number, suffix) = try!(split_number(value, "rate"));
+    let prefix = strip_unit(suffix, &["sa/s", "s/s", "sps", "hz"]);
+    let multiplier = try!(large_prefix(prefix, "S/s").map_err(|e| {
+        format!("Invalid rate `{}`: {}", value.trim(), e)
+    }));
+
+    let rate = try!(scale(number, multiplier, value, "rate"));
+    if rate <= 0.0 {
+        return Err(format!("Invalid rate `{}`: must be greater than zero", value.trim()));
+    }
+    Ok(rate)
+}
+
+/// Parses a duration in seconds. Accepts an optional unit (`s`, `ms`, `us`/`µs`, `ns`, `min` or
+/// `h`), e.g. `0.5`, `20ms` or `1.5min`. A bare number is taken to be in seconds.
+pub fn parse_duration(value: &str) -> Result<f64, String> {
+    let duration = try!(parse_signed_duration(value));
+    if duration < 0.0 {
+        return Err(format!("Invalid duration `{}`: must not be negative", value.trim()));
+    }
+    Ok(duration)
+}
+
+/// Parses a duration in seconds that may be negative, such as an offset between two timelines,
+/// e.g. `1.5s` or `-200ms`. Accepts the same units as `parse_duration`.
+pub fn parse_signed_duration(value: &str) -> Result<f64, String> {
+    let (number, suffix) = try!(split_number(value, "duration"));
+
+    let multiplier = match suffix {
+        "" | "s" | "sec" => 1.0,
+        "ms" => 1e-3,
+        "us" | "µs" | "μs" => 1e-6,
+        "ns" => 1e-9,
+        "min" => 60.0,
+        "h" => 3600.0,
+        "m" | "M" => {
+            return Err(format!("Invalid duration `{}`: `{}` is ambiguous, use `ms` for \
+                milliseconds or `min` for minutes", value.trim(), suffix));
+        }
+        _ => {
+            return Err(format!("Invalid duration `{}`: unknown unit `{}` (expected s, ms, us, \
+                ns, min or h)", value.trim(), suffix));
+        }
+    };
+
+    scale(number, multiplier, value, "duration")
+}
+
+/// Parses a level in dB, with or without a `dB` unit, e.g. `-90`, `10dB` or `-3 dB`.
+pub fn parse_level(value: &str) -> Result<f32, String> {
+    let (number, suffix) = try!(split_number(value, "level"));
+    match suffix.to_lowercase().as_str() {
+        "" | "db" if (number as f32).is_finite() => Ok(number as f32),
+        "" | "db" => Err(format!("Invalid level `{}`: too large", value.trim())),
+        _ => Err(format!("Invalid level `{}`: expected a number of dB", value.trim())),
+    }
+}
+
+/// Parses a count of samples, blocks or threads, e.g. `1024`.
+pub fn parse_count(value: &str) -> Result<usize, String> {
+    value.trim().parse::<usize>()
+        .map_err(|_| format!("Invalid count `{}`: expected a whole number", value.trim()))
+}
+
+/// Formats a frequency using the largest SI prefix that keeps the value above one, e.g.
+/// `-250 kHz` or `1.2 MHz`.
+pub fn format_frequency(freq: f64) -> String {
+    let abs = freq.abs();
+    if abs >= 1e9 {
+        format!("{} GHz", trim_float(freq / 1e9))
+    }
+    else if abs >= 1e6 {
+        format!("{} MHz", trim_float(freq / 1e6))
+    }
+    else if abs >= 1e3 {
+        format!("{} kHz", trim_float(freq / 1e3))
+    }
+    else {
+        format!("{} Hz", trim_float(freq))
+    }
+}
+
+/// Formats a duration in seconds using the largest unit that keeps the value above one, e.g.
+/// `-20 ms` or `1.5 s`.
+pub fn format_duration(seconds: f64) -> String {
+    let abs = seconds.abs();
+    if abs >= 1.0 || abs == 0.0 {
+        format!("{} s", trim_float(seconds))
+    }
+    else if abs >= 1e-3 {
+        format!("{} ms", trim_float(seconds * 1e3))
+    }
+    else if abs >= 1e-6 {
+        format!("{} µs", trim_float(seconds * 1e6))
+    }
+    else {
+        format!("{} ns", trim_float(seconds * 1e9))
+    }
+}
+
+/// Formats a value with up to 6 decimal places, dropping any trailing zeros
+fn trim_float(value: f64) -> String {
+    let text = format!("{:.6}", value);
+    text.trim_right_matches('0').trim_right_matches('.').into()
+}
+
+/// Splits the leading number (including any sign and exponent) from its unit suffix
+fn split_number<'a>(value: &'a str, quantity: &str) -> Result<(f64, &'a str), String> {
+    let value = value.trim();
+    let bytes = value.as_bytes();
+
+    let mut end = 0;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+    while end < bytes.len() && (bytes[end].is_ascii_digit() || bytes[end] == b'.') {
+        end += 1;
+    }
+
+    // Only treat an `e` as an exponent if it is followed by digits, so that suffixes starting
+    // with `e` are still reported as bad units rather than bad numbers
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exp_end = end + 1;
+        if exp_end < bytes.len() && (bytes[exp_end] == b'+' || bytes[exp_end] == b'-') {
+            exp_end += 1;
+        }
+        if exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+            while exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+                exp_end += 1;
+            }
+            end = exp_end;
+        }
+    }
+
+    let number = match value[..end].parse::<f64>() {
+        Ok(number) if number.is_finite() => number,
+        _ => return Err(format!("Invalid {} `{}`: expected a number", quantity, value)),
+    };
+
+    Ok((number, value[end..].trim()))
+}
+
+/// Multiplies a number by the multiplier of its unit, rejecting results too large to represent
+fn scale(number: f64, multiplier: f64, value: &str, quantity: &str) -> Result<f64, String> {
+    let scaled = number * multiplier;
+    match scaled.is_finite() {
+        true => Ok(scaled),
+        false => Err(format!("Invalid {} `{}`: too large", quantity, value.trim())),
+    }
+}
+
+/// Removes the first matching unit (compared case insensitively) from the end of a suffix,
+/// returning the remaining prefix
+fn strip_unit<'a>(suffix: &'a str, units: &[&str]) -> &'a str {
+    let lower = suffix.to_lowercase();
+    for unit in units {
+        if lower.ends_with(unit) && suffix.len() >= unit.len() {
+            return suffix[..suffix.len() - unit.len()].trim();
+        }
+    }
+    suffix
+}
+
+/// Gets the multiplier for an SI prefix on a frequency or rate
+fn large_prefix(prefix: &str, unit: &str) -> Result<f64, String> {
+    match prefix {
+        "" => Ok(1.0),
+        "k" | "K" => Ok(1e3),
+        "M" => Ok(1e6),
+        "G" | "g" => Ok(1e9),
+        "m" => {
+            Err(format!("`m{}` is ambiguous, use `M{}` for mega (milli is not supported)", unit,
+                unit))
+        }
+        _ => Err(format!("unknown prefix or unit `{}` (expected k, M or G followed by {})", prefix,
+            unit)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_frequencies() {
+        assert_eq!(parse_frequency("130000"), Ok(130e3));
+        assert_eq!(parse_frequency("130k"), Ok(130e3));
+        assert_eq!(parse_frequency(" 130 kHz "), Ok(130e3));
+        assert_eq!(parse_frequency("-0.25MHz"), Ok(-0.25e6));
+        assert_eq!(parse_frequency("1.5e3Hz"), Ok(1500.0));
+        assert_eq!(parse_frequency("2G"), Ok(2e9));
+
+        assert!(parse_frequency("2m").is_err());
+        assert!(parse_frequency("130x").is_err());
+        assert!(parse_frequency("kHz").is_err());
+        assert!(parse_frequency("").is_err());
+        assert!(parse_frequency("inf").is_err());
+        assert!(parse_frequency("1e300G").is_err());
+        assert_eq!(parse_frequency("1e300"), Ok(1e300));
+    }
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(parse_rate("2e6"), Ok(2e6));
+        assert_eq!(parse_rate("2M"), Ok(2e6));
+        assert_eq!(parse_rate("2MS/s"), Ok(2e6));
+        assert_eq!(parse_rate("10 Msps"), Ok(10e6));
+        assert_eq!(parse_rate("250kSa/s"), Ok(250e3));
+        assert_eq!(parse_rate("8kHz"), Ok(8e3));
+
+        assert!(parse_rate("2ms/s").is_err());
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("-2M").is_err());
+        assert_eq!(parse_rate("1e300G").err(), Some("Invalid rate `1e300G`: too large".into()));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("0.5"), Ok(0.5));
+        assert_eq!(parse_duration("2s"), Ok(2.0));
+        assert_eq!(parse_duration("20ms"), Ok(0.02));
+        assert_eq!(parse_duration("1.5min"), Ok(90.0));
+        assert_eq!(parse_duration("2h"), Ok(7200.0));
+        assert!((parse_duration("250us").unwrap() - 250e-6).abs() < 1e-12);
+        assert!((parse_duration("250µs").unwrap() - 250e-6).abs() < 1e-12);
+
+        assert!(parse_duration("5m").is_err());
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("3 days").is_err());
+        assert!(parse_duration("1e307h").is_err());
+    }
+
+    #[test]
+    fn parses_signed_durations() {
+        assert_eq!(parse_signed_duration("1.5s"), Ok(1.5));
+        assert_eq!(parse_signed_duration("-2s"), Ok(-2.0));
+        assert_eq!(parse_signed_duration(" -200ms"), Ok(-0.2));
+        assert_eq!(parse_signed_duration("+1min"), Ok(60.0));
+        assert!(parse_signed_duration("-5m").is_err());
+        assert!(parse_signed_duration("-").is_err());
+        assert!(parse_signed_duration("-1e307h").is_err());
+    }
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!(parse_level("-90"), Ok(-90.0));
+        assert_eq!(parse_level("10dB"), Ok(10.0));
+        assert_eq!(parse_level(" -3.5 dB"), Ok(-3.5));
+        assert!(parse_level("10dBm").is_err());
+        assert!(parse_level("loud").is_err());
+        assert!(parse_level("1e300").is_err());
+    }
+
+    #[test]
+    fn parses_counts() {
+        assert_eq!(parse_count("1024"), Ok(1024));
+        assert_eq!(parse_count(" 0 "), Ok(0));
+        assert!(parse_count("-1").is_err());
+        assert!(parse_count("1.5").is_err());
+        assert!(parse_count("1k").is_err());
+    }
+
+    #[test]
+    fn formats_frequencies() {
+        assert_eq!(format_frequency(0.0), "0 Hz");
+        assert_eq!(format_frequency(500.0), "500 Hz");
+        assert_eq!(format_frequency(130e3), "130 kHz");
+        assert_eq!(format_frequency(-250e3), "-250 kHz");
+        assert_eq!(format_frequency(1.2e6), "1.2 MHz");
+        assert_eq!(format_frequency(2.4e9), "2.4 GHz");
+    }
+
+    #[test]
+    fn formatted_frequencies_parse() {
+        for &freq in &[12.5, -130e3, 1.25e6] {
+            assert_eq!(parse_frequency(&format_frequency(freq)), Ok(freq));
+        }
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(0.0), "0 s");
+        assert_eq!(format_duration(1.5), "1.5 s");
+        assert_eq!(format_duration(-0.02), "-20 ms");
+        assert_eq!(format_duration(250e-6), "250 µs");
+        assert_eq!(format_duration(5e-9), "5 ns");
+    }
+}
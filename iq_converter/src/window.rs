@@ -0,0 +1,196 @@
+//! Window functions applied to each block of samples before filtering
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// The default shape parameter used when a Kaiser window is requested without one
+pub const DEFAULT_KAISER_BETA: f32 = 8.6;
+
+/// A window function.
+///
+/// Windows with lower sidelobes reject adjacent collars better, at the cost of a wider main lobe
+/// (and so poorer time resolution for a given block size).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    FlatTop,
+    /// A Kaiser window with the specified beta parameter
+    Kaiser(f32),
+}
+
+impl Window {
+    /// The names accepted when parsing a window
+    pub fn variants() -> [&'static str; 6] {
+        ["rectangular", "hann", "hamming", "blackman-harris", "flat-top", "kaiser[:<beta>]"]
+    }
+
+    /// Computes the coefficients of the window for a block of `n` samples
+    pub fn coefficients(&self, n: usize) -> Vec<f32> {
+        (0..n).map(|i| self.value(i, n)).collect()
+    }
+
+    /// Computes the value of the window at index `i` of a block of `n` samples
+    pub fn value(&self, i: usize, n: usize) -> f32 {
+        if n <= 1 {
+            return 1.0;
+        }
+        let x = i as f32 / (n - 1) as f32;
+
+        match *self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * (2.0 * PI * x).cos(),
+            Window::Hamming => 0.54 - 0.46 * (2.0 * PI * x).cos(),
+            Window::BlackmanHarris => {
+                cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168], x)
+            }
+            Window::FlatTop => {
+                cosine_sum(&[0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368], x)
+            }
+            Window::Kaiser(beta) => {
+                let r = 2.0 * x - 1.0;
+                bessel_i0(beta * (1.0 - r * r).max(0.0).sqrt()) / bessel_i0(beta)
+            }
+        }
+    }
+
+    /// The distance (in bins) from the centre of the window's frequency response to its first null
+    pub fn main_lobe_width(&self) -> f32 {
+        match *self {
+            Window::Rectangular => 1.0,
+            Window::Hann | Window::Hamming => 2.0,
+            Window::BlackmanHarris => 4.0,
+            Window::FlatTop => 5.0,
+            Window::Kaiser(beta) => (1.0 + (beta / PI) * (beta / PI)).sqrt(),
+        }
+    }
+}
+
+impl Default for Window {
+    fn default() -> Window {
+        Window::BlackmanHarris
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Window::Rectangular => write!(f, "rectangular"),
+            Window::Hann => write!(f, "hann"),
+            Window::Hamming => write!(f, "hamming"),
+            Window::BlackmanHarris => write!(f, "blackman-harris"),
+            Window::FlatTop => write!(f, "flat-top"),
+            Window::Kaiser(beta) => write!(f, "kaiser:{}", beta),
+        }
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Window, String> {
+        let lower = s.trim().to_lowercase().replace('_', "-");
+        let mut parts = lower.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let param = parts.next();
+
+        let window = match name {
+            "rectangular" | "rect" | "none" => Window::Rectangular,
+            "hann" | "hanning" => Window::Hann,
+            "hamming" => Window::Hamming,
+            "blackman-harris" | "blackmanharris" => Window::BlackmanHarris,
+            "flat-top" | "flattop" => Window::FlatTop,
+            "kaiser" => {
+                let beta = match param {
+                    Some(beta) => try!(beta.parse::<f32>()
+                        .map_err(|_| format!("Invalid Kaiser beta: `{}`", beta))),
+                    None => DEFAULT_KAISER_BETA,
+                };
+                if !(beta >= 0.0) {
+                    return Err(format!("Kaiser beta must be non-negative (got {})", beta));
+                }
+                return Ok(Window::Kaiser(beta));
+            }
+            _ => {
+                return Err(format!("Unknown window `{}` (expected one of: {})", s,
+                    Window::variants().join(", ")));
+            }
+        };
+
+        match param {
+            Some(_) => Err(format!("The {} window does not take a parameter", window)),
+            None => Ok(window),
+        }
+    }
+}
+
+/// Evaluates a generalised cosine-sum window with alternating signs at position `x` in [0, 1]
+fn cosine_sum(coefficients: &[f32], x: f32) -> f32 {
+    coefficients.iter().enumerate().fold(0.0, |acc, (k, &a)| {
+        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+        acc + sign * a * (2.0 * PI * k as f32 * x).cos()
+    })
+}
+
+/// Computes the zeroth order modified Bessel function of the first kind
+fn bessel_i0(x: f32) -> f32 {
+    let half_x = x as f64 / 2.0;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..50 {
+        term *= half_x / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sum(window: Window, n: usize) -> f32 {
+        window.coefficients(n).iter().sum()
+    }
+
+    #[test]
+    fn window_sums() {
+        // The symmetric cosine windows cover exactly one period, so the cosine terms add up to
+        // the single repeated end point
+        assert_eq!(sum(Window::Rectangular, 100), 100.0);
+        assert!((sum(Window::Hann, 101) - 50.0).abs() < 1e-3);
+        assert!((sum(Window::Hamming, 101) - (0.54 * 101.0 - 0.46)).abs() < 1e-3);
+        assert!((sum(Window::BlackmanHarris, 101) -
+            (0.35875 * 101.0 - 0.48829 + 0.14128 - 0.01168)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn windows_peak_at_the_centre() {
+        for &window in &[Window::Hann, Window::Hamming, Window::BlackmanHarris, Window::FlatTop,
+            Window::Kaiser(DEFAULT_KAISER_BETA)]
+        {
+            let coefficients = window.coefficients(101);
+            assert!((coefficients[50] - 1.0).abs() < 1e-3, "{} peaks at {}", window,
+                coefficients[50]);
+            assert!((coefficients[0] - coefficients[100]).abs() < 1e-6);
+        }
+        assert!(Window::Hann.value(0, 101).abs() < 1e-6);
+        assert_eq!(Window::Hann.coefficients(1), vec![1.0]);
+    }
+
+    #[test]
+    fn parses_windows() {
+        assert_eq!("Hann".parse(), Ok(Window::Hann));
+        assert_eq!("blackman_harris".parse(), Ok(Window::BlackmanHarris));
+        assert_eq!("kaiser".parse(), Ok(Window::Kaiser(DEFAULT_KAISER_BETA)));
+        assert_eq!("kaiser:5".parse(), Ok(Window::Kaiser(5.0)));
+        assert!("kaiser:-1".parse::<Window>().is_err());
+        assert!("hann:2".parse::<Window>().is_err());
+        assert!("triangle".parse::<Window>().is_err());
+    }
+}
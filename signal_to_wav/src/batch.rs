@@ -0,0 +1,239 @@
+//! Conversion of many captures at once
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+
+use glob::glob;
+
+use iq_converter::units::format_frequency;
+
+use output::OutputFormat;
+
+use {convert, default_track_log, Settings, Summary};
+
+/// The file extensions treated as captures when the input is a directory
+const CAPTURE_EXTENSIONS: &'static [&'static str] = &["bin", "cs8", "iq", "raw", "dat"];
+
+/// Finds the captures to convert from a directory or a glob pattern
+pub fn find_captures(input: &str) -> Result<Vec<PathBuf>, String> {
+    let mut captures = vec![];
+
+    if Path::new(input).is_dir() {
+        let entries = try!(fs::read_dir(input)
+            .map_err(|e| format!("Unable to read directory {}: {}", input, e)));
+
+        for entry in entries {
+            let path = try!(entry.map_err(|e| format!("Unable to read directory {}: {}", input,
+                e))).path();
+            let is_capture = path.extension().and_then(|x| x.to_str())
+                .map_or(false, |x| CAPTURE_EXTENSIONS.contains(&&*x.to_lowercase()));
+            if path.is_file() && is_capture {
+                captures.push(path);
+            }
+        }
+    }
+    else {
+        let paths = try!(glob(input).map_err(|e| format!("Invalid pattern {}: {}", input, e)));
+        for path in paths {
+            match path {
+                Ok(path) => if path.is_file() { captures.push(path) },
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "Skipping {}: {}", e.path().display(),
+                        e.error());
+                }
+            }
+        }
+    }
+
+    if captures.is_empty() {
+        return Err(format!("No captures found matching {}", input));
+    }
+
+    captures.sort();
+    Ok(captures)
+}
+
+/// Gets the path of the output file for a capture, placing it in `output_dir` if provided or
+/// otherwise next to the input
+pub fn output_path(input: &Path, output_dir: Option<&Path>, format: OutputFormat) -> PathBuf {
+    let output = input.with_extension(format.extension());
+    match (output_dir, output.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => output,
+    }
+}
+
+/// Gets the path of the output file for each input, failing if any two inputs would be written to
+/// the same file (e.g. `a.bin` and `a.cs8`, or captures with the same name in different directories
+/// when `output_dir` is provided)
+pub fn output_paths(inputs: &[PathBuf], output_dir: Option<&Path>, format: OutputFormat)
+    -> Result<Vec<PathBuf>, String>
+{
+    let outputs: Vec<_> = inputs.iter().map(|x| output_path(x, output_dir, format)).collect();
+
+    let mut written_by = HashMap::new();
+    let mut conflicts = vec![];
+    for (input, output) in inputs.iter().zip(&outputs) {
+        if let Some(other) = written_by.insert(output, input) {
+            conflicts.push(format!("{} and {} would both be written to {}", other.display(),
+                input.display(), output.display()));
+        }
+    }
+
+    match conflicts.is_empty() {
+        true => Ok(outputs),
+        false => Err(format!("{}\nRename the captures or convert them in separate batches",
+            conflicts.join("\n"))),
+    }
+}
+
+/// Converts all of the inputs using `jobs` threads, continuing past any failures, and prints a
+/// summary table once every input has been processed. Returns `true` if every conversion succeeded.
+pub fn run(inputs: Vec<PathBuf>, output_dir: Option<PathBuf>, settings: Settings, jobs: usize)
+    -> bool
+{
+    let outputs = match output_paths(&inputs, output_dir.as_ref().map(|x| x.as_path()),
+        settings.format)
+    {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "{}", e);
+            return false;
+        }
+    };
+
+    if let Some(ref dir) = output_dir {
+        if let Err(e) = fs::create_dir_all(dir) {
+            let _ = writeln!(io::stderr(), "Unable to create output directory {}: {}",
+                dir.display(), e);
+            return false;
+        }
+    }
+
+    println!("Converting {} captures using {} threads", inputs.len(), jobs);
+
+    let total = inputs.len();
+    let queue = Arc::new(Mutex::new(inputs.into_iter().zip(outputs).enumerate()));
+    let (sender, receiver) = mpsc::channel();
+
+    let workers: Vec<_> = (0..jobs.min(total)).map(|_| {
+        let queue = queue.clone();
+        let sender = sender.clone();
+
+        thread::spawn(move || {
+            loop {
+                let next = queue.lock().unwrap().next();
+                let (index, (input, output)) = match next {
+                    Some(job) => job,
+                    None => break,
+                };
+
+                let result = convert(&input, &output, &default_track_log(&output), &settings)
+                    .map_err(|e| e.to_string());
+
+                match result {
+                    Ok(_) => println!("[{}/{}] {}: done", index + 1, total, input.display()),
+                    Err(ref e) => {
+                        let _ = writeln!(io::stderr(), "[{}/{}] {}: failed: {}", index + 1,
+                            total, input.display(), e);
+                    }
+                }
+
+                let row = Row { input: input, output: output, result: result };
+                if sender.send((index, row)).is_err() {
+                    break;
+                }
+            }
+        })
+    }).collect();
+    drop(sender);
+
+    let mut rows: Vec<_> = receiver.iter().collect();
+    for worker in workers {
+        let _ = worker.join();
+    }
+    rows.sort_by_key(|&(index, _)| index);
+
+    let rows: Vec<_> = rows.into_iter().map(|(_, row)| row).collect();
+    print_summary(&rows, total);
+
+    rows.len() == total && rows.iter().all(|row| row.result.is_ok())
+}
+
+/// The outcome of converting a single capture
+struct Row {
+    input: PathBuf,
+    output: PathBuf,
+    result: Result<Summary, String>,
+}
+
+/// Prints a table summarising the outcome of each conversion
+fn print_summary(rows: &[Row], total: usize) {
+    let inputs: Vec<_> = rows.iter().map(|row| row.input.display().to_string()).collect();
+    let width = inputs.iter().map(|x| x.len()).max().unwrap_or(0).max("Capture".len());
+
+    println!("");
+    println!("{:<width$}  {:<6}  {:>10}  {}", "Capture", "Status", "Duration", "Details",
+        width = width);
+
+    for (row, input) in rows.iter().zip(&inputs) {
+        match row.result {
+            Ok(ref summary) => {
+                let mut details = row.output.display().to_string();
+                if let Some((min, max)) = summary.tracked {
+                    details.push_str(&format!(" (drift {})",
+                        format_frequency((max - min) as f64)));
+                }
+                if let Some(open) = summary.squelch_open {
+                    details.push_str(&format!(" (squelch open {:.1}%)", open * 100.0));
+                }
+                if let Some(pulses) = summary.pulses {
+                    details.push_str(&format!(" ({} pulses)", pulses));
+                }
+                println!("{:<width$}  {:<6}  {:>9.1}s  {}", input, "ok", summary.duration,
+                    details, width = width);
+            }
+            Err(ref e) => {
+                println!("{:<width$}  {:<6}  {:>10}  {}", input, "FAILED", "-", e, width = width);
+            }
+        }
+    }
+
+    let succeeded = rows.iter().filter(|row| row.result.is_ok()).count();
+    println!("");
+    println!("{} of {} captures converted, {} failed", succeeded, total, total - succeeded);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn outputs_are_placed_next_to_inputs_or_in_output_dir() {
+        let inputs = paths(&["a/one.bin", "b/two.cs8"]);
+        assert_eq!(output_paths(&inputs, None, OutputFormat::Wav),
+            Ok(paths(&["a/one.wav", "b/two.wav"])));
+        assert_eq!(output_paths(&inputs, Some(Path::new("out")), OutputFormat::Wav),
+            Ok(paths(&["out/one.wav", "out/two.wav"])));
+    }
+
+    #[test]
+    fn clashing_outputs_are_rejected() {
+        let inputs = paths(&["a/one.bin", "a/one.cs8"]);
+        let error = output_paths(&inputs, None, OutputFormat::Wav).unwrap_err();
+        assert!(error.contains("a/one.bin and a/one.cs8"), "{}", error);
+
+        let inputs = paths(&["a/one.bin", "b/one.bin"]);
+        assert!(output_paths(&inputs, None, OutputFormat::Wav).is_ok());
+        assert!(output_paths(&inputs, Some(Path::new("out")), OutputFormat::Wav).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+//! Incremental reading of captures, so that conversion can start before a capture is complete
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use output::STDIO;
+
+/// The number of bytes requested from the input at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Opens a capture for reading, or stdin if `path` is `-`
+pub fn open(path: &Path) -> io::Result<ReadBytes<Box<Read>>> {
+    let input: Box<Read> = if path == Path::new(STDIO) {
+        Box::new(io::stdin())
+    }
+    else {
+        Box::new(try!(File::open(path)))
+    };
+    Ok(ReadBytes::new(input))
+}
+
+/// An iterator over the bytes of a reader, which reads in chunks as data becomes available. Any
+/// error ends the iteration and is kept to be checked once the bytes have been consumed.
+pub struct ReadBytes<R> {
+    input: R,
+    buffer: Vec<u8>,
+    pos: usize,
+    len: usize,
+    count: usize,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> ReadBytes<R> {
+    pub fn new(input: R) -> ReadBytes<R> {
+        ReadBytes {
+            input: input,
+            buffer: vec![0; CHUNK_SIZE],
+            pos: 0,
+            len: 0,
+            count: 0,
+            error: None,
+        }
+    }
+
+    /// The number of bytes read so far
+    pub fn bytes_read(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the error that stopped the iteration, if there was one
+    pub fn take_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: Read> Iterator for ReadBytes<R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        while self.pos == self.len {
+            if self.error.is_some() {
+                return None;
+            }
+
+            match self.input.read(&mut self.buffer) {
+                Ok(0) => return None,
+                Ok(len) => {
+                    self.pos = 0;
+                    self.len = len;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => self.error = Some(e),
+            }
+        }
+
+        let byte = self.buffer[self.pos];
+        self.pos += 1;
+        self.count += 1;
+        Some(byte)
+    }
+}
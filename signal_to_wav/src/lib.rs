@@ -0,0 +1,676 @@
+//! Conversion of raw I/Q samples from the HackRF into audio.
+//!
+//! The filtered path splits the I/Q stream into blocks, computes the Goertzel magnitude of each
+//! block at the filter frequency (the envelope), and uses the envelope to modulate an audible tone.
+//! The unfiltered path simply resamples the in-phase component to the output rate.
+
+extern crate byteorder;
+extern crate glob;
+extern crate iq_converter;
+extern crate sample;
+
+pub mod batch;
+pub mod input;
+pub mod output;
+pub mod squelch;
+
+use std::error::Error;
+use std::f32;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use iq_converter::IqConverter;
+use iq_converter::goertzel::{FilterSettings, Magnitudes, TrackingMagnitudes, TrackingSettings};
+use iq_converter::pulse::{Pulse, PulseDetector, DEFAULT_THRESHOLD};
+use iq_converter::units::format_frequency;
+
+use sample::signal;
+use sample::rate::Converter;
+
+use output::{AudioSink, InfoField, Marker, OutputFormat, STDIO};
+use squelch::{Squelch, SquelchSettings};
+
+/// The default number of samples in each filter block
+pub const DEFAULT_BLOCK_SIZE: usize = 100;
+
+/// The sample rate of the audio output
+pub const OUTPUT_RATE: u32 = 44100;
+
+/// The frequency of the tone used to make the filtered signal audible
+pub const TONE_FREQ: f64 = 1200.0;
+
+/// The number of output samples passed to the writer at a time
+const OUTPUT_BLOCK: usize = 2048;
+
+/// The settings used when converting a capture
+#[derive(Copy, Clone)]
+pub struct Settings {
+    /// The format of the output file
+    pub format: OutputFormat,
+
+    /// The sample rate of the input file
+    pub samp_rate: f64,
+
+    /// The frequency to filter the input at, or `None` to output the raw signal
+    pub filter: Option<f64>,
+
+    pub filter_settings: FilterSettings,
+    pub tracking: Option<TrackingSettings>,
+    pub squelch: Option<SquelchSettings>,
+
+    /// How far (in dB) above the noise floor the filtered signal must be to be marked as a pulse
+    pub pulse_threshold: f32,
+}
+
+impl Settings {
+    /// Creates settings for converting an unfiltered capture sampled at `samp_rate` to a wav file
+    pub fn new(samp_rate: f64) -> Settings {
+        Settings {
+            format: OutputFormat::Wav,
+            samp_rate: samp_rate,
+            filter: None,
+            filter_settings: FilterSettings::new(DEFAULT_BLOCK_SIZE),
+            tracking: None,
+            squelch: None,
+            pulse_threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /// The rate at which the envelope is produced when filtering
+    pub fn envelope_rate(&self) -> f64 {
+        self.filter_settings.output_rate(self.samp_rate)
+    }
+}
+
+/// A summary of a completed conversion
+pub struct Summary {
+    /// The length of the input capture in seconds
+    pub duration: f64,
+
+    /// The lowest and highest frequencies followed when tracking a drifting tone
+    pub tracked: Option<(f32, f32)>,
+
+    /// The fraction of the capture for which the squelch was open
+    pub squelch_open: Option<f64>,
+
+    /// The number of pulses found in the filtered signal
+    pub pulses: Option<usize>,
+}
+
+/// Filters an I/Q stream at `filter_freq`, following the tone within the tracking band if there is
+/// one. The envelope is produced at `settings.envelope_rate()` entries per second.
+pub fn envelope<I>(input: I, filter_freq: f64, settings: &Settings) -> Envelope<I>
+    where I: Iterator<Item=(f32, f32)>
+{
+    let samp_rate = settings.samp_rate as f32;
+    match settings.tracking {
+        Some(tracking) => {
+            Envelope::Tracking(TrackingMagnitudes::new(input, samp_rate, filter_freq as f32,
+                settings.filter_settings, tracking))
+        }
+        None => {
+            Envelope::Fixed(Magnitudes::new(input, samp_rate, filter_freq as f32,
+                settings.filter_settings), filter_freq as f32)
+        }
+    }
+}
+
+/// An iterator over the frequency and Goertzel magnitude of each block of an I/Q stream
+pub enum Envelope<I> {
+    Fixed(Magnitudes<I>, f32),
+    Tracking(TrackingMagnitudes<I>),
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Iterator for Envelope<I> {
+    type Item = (f32, f32);
+
+    fn next(&mut self) -> Option<(f32, f32)> {
+        match *self {
+            Envelope::Fixed(ref mut magnitudes, freq) => magnitudes.next().map(|x| (freq, x)),
+            Envelope::Tracking(ref mut magnitudes) => magnitudes.next(),
+        }
+    }
+}
+
+/// An iterator that turns envelope magnitudes into amplitudes in the range [0, 1] for the tone,
+/// muting them when the squelch is closed.
+///
+/// Magnitudes are scaled by the loudest magnitude seen so far, so the first few amplitudes may be
+/// louder than they would be if the whole envelope were known in advance (see `with_peak`).
+pub struct Amplitudes<I> {
+    magnitudes: I,
+    squelch: Option<Squelch>,
+    peak: f32,
+}
+
+impl<I: Iterator<Item=f32>> Amplitudes<I> {
+    /// Creates amplitudes for an envelope produced at `rate` magnitudes per second
+    pub fn new(magnitudes: I, rate: f64, squelch: Option<SquelchSettings>) -> Amplitudes<I> {
+        Amplitudes {
+            magnitudes: magnitudes,
+            squelch: squelch.map(|x| Squelch::new(x, rate)),
+            peak: 0.0,
+        }
+    }
+
+    /// Scales the magnitudes by `peak` (the loudest magnitude in the envelope) from the start
+    pub fn with_peak(mut self, peak: f32) -> Amplitudes<I> {
+        self.peak = peak;
+        self
+    }
+
+    /// The squelch applied to the amplitudes, if there is one
+    pub fn squelch(&self) -> Option<&Squelch> {
+        self.squelch.as_ref()
+    }
+}
+
+impl<I: Iterator<Item=f32>> Iterator for Amplitudes<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.magnitudes.next().map(|magnitude| {
+            self.peak = f32::max(self.peak, magnitude);
+            let gain = self.squelch.as_mut().map_or(1.0, |x| x.process(magnitude));
+            if self.peak > 0.0 { magnitude * gain / self.peak } else { 0.0 }
+        })
+    }
+}
+
+type Frames<I, T> = iter::Map<I, fn(T) -> [f32; 1]>;
+
+/// Modulates a tone at `TONE_FREQ` with amplitudes produced at `rate` amplitudes per second,
+/// giving audio samples at `OUTPUT_RATE`
+pub fn tone<I: Iterator<Item=f32>>(amplitudes: I, rate: f64) -> Tone<I> {
+    fn frame(x: f32) -> [f32; 1] { [x] }
+
+    let out_rate = OUTPUT_RATE as f64;
+    Tone {
+        amplitudes: Converter::from_hz_to_hz(amplitudes.map(frame as fn(f32) -> [f32; 1]), rate,
+            out_rate),
+        signal: signal::rate(out_rate).const_hz(TONE_FREQ).sine(),
+    }
+}
+
+/// An iterator over the samples of a tone modulated by an envelope
+pub struct Tone<I: Iterator<Item=f32>> {
+    amplitudes: Converter<Frames<I, f32>>,
+    signal: signal::Sine<signal::ConstHz>,
+}
+
+impl<I: Iterator<Item=f32>> Iterator for Tone<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        match (self.amplitudes.next(), self.signal.next()) {
+            (Some(amplitude), Some(signal)) => Some(signal[0] as f32 * amplitude[0]),
+            _ => None,
+        }
+    }
+}
+
+/// Resamples the in-phase component of an I/Q stream sampled at `samp_rate` to `OUTPUT_RATE`
+pub fn resample<I: Iterator<Item=(f32, f32)>>(input: I, samp_rate: f64) -> Resample<I> {
+    fn frame(x: (f32, f32)) -> [f32; 1] { [x.0] }
+
+    Resample {
+        converter: Converter::from_hz_to_hz(input.map(frame as fn((f32, f32)) -> [f32; 1]),
+            samp_rate, OUTPUT_RATE as f64),
+    }
+}
+
+/// An iterator over an I/Q stream resampled to audio
+pub struct Resample<I: Iterator<Item=(f32, f32)>> {
+    converter: Converter<Frames<I, (f32, f32)>>,
+}
+
+impl<I: Iterator<Item=(f32, f32)>> Iterator for Resample<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.converter.next().map(|x| x[0])
+    }
+}
+
+/// Converts an I/Q stream into audio samples at `OUTPUT_RATE` in the range [-1, 1] as the input
+/// arrives. When filtering, the tone is scaled by the loudest magnitude seen so far.
+pub fn audio<'a, I>(input: I, settings: &Settings) -> Box<Iterator<Item=f32> + 'a>
+    where I: Iterator<Item=(f32, f32)> + 'a
+{
+    match settings.filter {
+        Some(filter_freq) => {
+            let rate = settings.envelope_rate();
+            let magnitudes = envelope(input, filter_freq, settings).map(|(_, magnitude)| magnitude);
+            Box::new(tone(Amplitudes::new(magnitudes, rate, settings.squelch), rate))
+        }
+        None => Box::new(resample(input, settings.samp_rate)),
+    }
+}
+
+/// Converts a capture to a wav file using the provided settings. Either path may be `-` to read
+/// from stdin or write to stdout, in which case the output is written as the input arrives.
+pub fn convert(input_path: &Path, output_path: &Path, track_log_path: &Path, settings: &Settings)
+    -> Result<Summary, Box<Error>>
+{
+    let live = input_path == Path::new(STDIO) || output_path == Path::new(STDIO);
+
+    let mut data = try!(input::open(input_path));
+    let mut output = try!(output::create(output_path, settings.format, OUTPUT_RATE,
+        &info_fields(input_path, settings)));
+
+    let mut summary = Summary {
+        duration: 0.0,
+        tracked: None,
+        squelch_open: None,
+        pulses: None,
+    };
+    let mut markers = vec![];
+
+    if let Some(filter_freq) = settings.filter {
+        let rate = settings.envelope_rate();
+        let mut track_log = match settings.tracking {
+            Some(_) => Some(try!(TrackLog::create(track_log_path, settings))),
+            None => None,
+        };
+        let mut pulses = vec![];
+
+        {
+            let magnitudes = envelope(IqConverter::new(&mut data), filter_freq, settings)
+                .map(|(freq, magnitude)| {
+                    if let Some(ref mut track_log) = track_log {
+                        track_log.record(freq, magnitude);
+                    }
+                    magnitude
+                });
+            summary.squelch_open = try!(write_magnitudes(magnitudes, rate, live, settings,
+                &mut pulses, &mut *output));
+        }
+
+        if let Some(track_log) = track_log {
+            summary.tracked = Some(try!(track_log.finish()));
+        }
+        summary.pulses = Some(pulses.len());
+        markers = pulse_markers(&pulses, rate);
+    }
+    else {
+        try!(write_blocks(resample(IqConverter::new(&mut data), settings.samp_rate),
+            &mut *output));
+    }
+
+    try!(data.take_error());
+    try!(output.finish(&markers));
+
+    summary.duration = (data.bytes_read() / 2) as f64 / settings.samp_rate;
+    Ok(summary)
+}
+
+/// Turns an envelope produced at `rate` magnitudes per second into a tone and writes it to
+/// `output`, returning the fraction of the time the squelch was open. Any pulses found in the
+/// envelope are added to `pulses`.
+///
+/// The tone is normally scaled by the loudest magnitude in the capture, which means reading the
+/// whole capture first. When `live` is set it is scaled by the loudest magnitude seen so far
+/// instead, so that audio is written as soon as the input arrives.
+fn write_magnitudes<I>(magnitudes: I, rate: f64, live: bool, settings: &Settings,
+    pulses: &mut Vec<Pulse>, output: &mut AudioSink) -> Result<Option<f64>, Box<Error>>
+    where I: Iterator<Item=f32>
+{
+    let mut detector = PulseDetector::new(rate, settings.pulse_threshold);
+    let magnitudes = magnitudes.map(|magnitude| {
+        pulses.extend(detector.process(magnitude));
+        magnitude
+    });
+
+    let squelch_open = if live {
+        let mut amplitudes = Amplitudes::new(magnitudes, rate, settings.squelch);
+        try!(write_blocks(tone(amplitudes.by_ref(), rate), output));
+        amplitudes.squelch().map(|x| x.open_fraction())
+    }
+    else {
+        let buffer: Vec<_> = magnitudes.collect();
+        let max = buffer.iter().cloned().fold(f32::NAN, f32::max);
+
+        let mut amplitudes = Amplitudes::new(buffer.into_iter(), rate, settings.squelch)
+            .with_peak(max);
+        try!(write_blocks(tone(amplitudes.by_ref(), rate), output));
+        amplitudes.squelch().map(|x| x.open_fraction())
+    };
+
+    pulses.extend(detector.finish());
+    Ok(squelch_open)
+}
+
+/// Writes samples to `output` in blocks of `OUTPUT_BLOCK` samples
+fn write_blocks<I: Iterator<Item=f32>>(samples: I, output: &mut AudioSink) -> io::Result<()> {
+    let mut block = Vec::with_capacity(OUTPUT_BLOCK);
+    for sample in samples {
+        block.push(sample);
+        if block.len() == OUTPUT_BLOCK {
+            try!(output.write_block(&block));
+            block.clear();
+        }
+    }
+
+    if !block.is_empty() {
+        try!(output.write_block(&block));
+    }
+    Ok(())
+}
+
+/// Converts pulses found in an envelope produced at `rate` magnitudes per second into markers on
+/// the output
+fn pulse_markers(pulses: &[Pulse], rate: f64) -> Vec<Marker> {
+    let scale = OUTPUT_RATE as f64 / rate;
+    pulses.iter().enumerate().map(|(i, pulse)| {
+        Marker {
+            position: (pulse.start as f64 * scale).round() as u32,
+            length: ((pulse.end - pulse.start) as f64 * scale).round() as u32,
+            label: format!("Pulse {} ({:.1} dB)", i + 1, pulse.snr),
+        }
+    }).collect()
+}
+
+/// Describes where a conversion came from, so that the wav file can be traced back to its capture
+fn info_fields(input_path: &Path, settings: &Settings) -> Vec<InfoField> {
+    let source = if input_path == Path::new(STDIO) {
+        "stdin".into()
+    }
+    else {
+        input_path.file_name().map_or(input_path.display().to_string(),
+            |x| x.to_string_lossy().into_owned())
+    };
+
+    let mut parameters = vec![format!("sample rate {}", format_frequency(settings.samp_rate))];
+    if let Some(filter_freq) = settings.filter {
+        let filter = settings.filter_settings;
+        parameters.push(format!("filter {}", format_frequency(filter_freq)));
+        parameters.push(format!("window {}", filter.window));
+        parameters.push(format!("block size {}", filter.block_size));
+        parameters.push(format!("hop {}", filter.hop));
+        if let Some(tracking) = settings.tracking {
+            parameters.push(format!("tracking band {} step {}",
+                format_frequency(tracking.band as f64), format_frequency(tracking.step as f64)));
+        }
+        if let Some(squelch) = settings.squelch {
+            parameters.push(format!("squelch {} dB hang {} s", squelch.threshold, squelch.hang));
+        }
+        parameters.push(format!("pulse threshold {} dB", settings.pulse_threshold));
+    }
+
+    let title = match settings.filter {
+        Some(filter_freq) => format!("{} filtered at {}", source, format_frequency(filter_freq)),
+        None => source.clone(),
+    };
+
+    vec![
+        InfoField::new(b"INAM", title),
+        InfoField::new(b"ISRC", source),
+        InfoField::new(b"ICMT", parameters.join(", ")),
+        InfoField::new(b"ISFT", format!("signal_to_wav {}", env!("CARGO_PKG_VERSION"))),
+    ]
+}
+
+/// Gets the default path of the tracking log for an output file
+pub fn default_track_log(output_path: &Path) -> PathBuf {
+    output_path.with_extension("track.csv")
+}
+
+/// Writes the tracked frequency and magnitude at the centre of each block to a CSV file as the
+/// blocks are processed
+struct TrackLog {
+    output: BufWriter<File>,
+    samp_rate: f64,
+    settings: FilterSettings,
+    index: usize,
+    min: f32,
+    max: f32,
+    error: Option<io::Error>,
+}
+
+impl TrackLog {
+    fn create(path: &Path, settings: &Settings) -> io::Result<TrackLog> {
+        let mut output = BufWriter::new(try!(File::create(path)));
+        try!(writeln!(output, "time,frequency,magnitude"));
+
+        Ok(TrackLog {
+            output: output,
+            samp_rate: settings.samp_rate,
+            settings: settings.filter_settings,
+            index: 0,
+            min: f32::NAN,
+            max: f32::NAN,
+            error: None,
+        })
+    }
+
+    /// Records the frequency and magnitude of the next block. Write errors are kept until `finish`
+    /// so that this can be called from within an iterator.
+    fn record(&mut self, freq: f32, magnitude: f32) {
+        let settings = self.settings;
+        let centre = (self.index * settings.hop) as f64 + settings.block_size as f64 / 2.0;
+        self.index += 1;
+        self.min = self.min.min(freq);
+        self.max = self.max.max(freq);
+
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.output, "{},{},{}", centre / self.samp_rate, freq,
+                magnitude)
+            {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Flushes the log, returning the lowest and highest frequencies recorded
+    fn finish(mut self) -> io::Result<(f32, f32)> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        try!(self.output.flush());
+        Ok((self.min, self.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::f32::consts::PI;
+    use std::fs;
+    use std::process;
+
+    use iq_converter::goertzel::TrackingSettings;
+
+    use super::*;
+
+    const SAMP_RATE: f64 = 2e6;
+
+    /// Generates `n` samples of a complex tone at `freq` with amplitude `amplitude`
+    fn tone_iq(freq: f32, amplitude: f32, n: usize) -> Vec<(f32, f32)> {
+        let omega = 2.0 * PI * freq / SAMP_RATE as f32;
+        (0..n).map(|i| {
+            let phase = omega * i as f32;
+            (amplitude * phase.cos(), amplitude * phase.sin())
+        }).collect()
+    }
+
+    fn filtered(freq: f64) -> Settings {
+        let mut settings = Settings::new(SAMP_RATE);
+        settings.filter = Some(freq);
+        settings
+    }
+
+    /// The largest absolute value in a slice
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |max, x| f32::max(max, x.abs()))
+    }
+
+    #[test]
+    fn envelope_measures_tone_amplitude() {
+        let input = tone_iq(130e3, 0.5, 10000);
+        let envelope: Vec<_> = envelope(input.into_iter(), 130e3, &filtered(130e3)).collect();
+
+        assert_eq!(envelope.len(), 10000 / DEFAULT_BLOCK_SIZE);
+        for &(freq, magnitude) in &envelope {
+            assert_eq!(freq, 130e3);
+            assert!((magnitude - 0.5).abs() < 0.01, "magnitude {}", magnitude);
+        }
+    }
+
+    #[test]
+    fn envelope_rejects_other_frequencies() {
+        let input = tone_iq(-250e3, 0.5, 10000);
+        let envelope: Vec<_> = envelope(input.into_iter(), 130e3, &filtered(130e3)).collect();
+
+        assert!(envelope.iter().all(|&(_, magnitude)| magnitude < 0.01));
+    }
+
+    #[test]
+    fn envelope_follows_offset_tone() {
+        let mut settings = filtered(130e3);
+        settings.tracking = Some(TrackingSettings::new(2000.0));
+
+        let input = tone_iq(130.8e3, 0.5, 100000);
+        let envelope: Vec<_> = envelope(input.into_iter(), 130e3, &settings).collect();
+
+        let (freq, magnitude) = *envelope.last().unwrap();
+        assert!((freq - 130.8e3).abs() < 100.0, "tracked {} Hz", freq);
+        assert!(magnitude > 0.4, "magnitude {}", magnitude);
+    }
+
+    #[test]
+    fn amplitudes_are_scaled_by_peak() {
+        let magnitudes = vec![0.01, 0.01, 1.0, 2.0, 2.0, 0.01, 0.01];
+        let scaled: Vec<_> = Amplitudes::new(magnitudes.clone().into_iter(), 100.0, None)
+            .with_peak(2.0).collect();
+        assert_eq!(scaled, vec![0.005, 0.005, 0.5, 1.0, 1.0, 0.005, 0.005]);
+
+        let running: Vec<_> = Amplitudes::new(magnitudes.into_iter(), 100.0, None).collect();
+        assert_eq!(&running[..4], &[1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(running[6], 0.005);
+    }
+
+    #[test]
+    fn unfiltered_audio_is_resampled() {
+        let input = tone_iq(1000.0, 0.5, 200000);
+        let audio: Vec<_> = audio(input.into_iter(), &Settings::new(SAMP_RATE)).collect();
+
+        let expected = 200000.0 * OUTPUT_RATE as f64 / SAMP_RATE;
+        assert!((audio.len() as f64 - expected).abs() <= 1.0, "{} samples", audio.len());
+        assert!((peak(&audio) - 0.5).abs() < 0.01);
+
+        // The in-phase component of the tone is a 1 kHz cosine
+        let t = 100.0 / OUTPUT_RATE as f32;
+        assert!((audio[100] - 0.5 * (2.0 * PI * 1000.0 * t).cos()).abs() < 0.05);
+    }
+
+    /// A directory for the files written by a test, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> TempDir {
+            let path = env::temp_dir().join(format!("signal_to_wav_{}_{}", name,
+                process::id()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Writes a capture of three 100 ms pulses of a tone at 130.5 kHz, half a second apart and
+    /// starting 200 ms in, over a little noise
+    fn write_capture(path: &Path) {
+        let period = SAMP_RATE as usize / 2;
+        let mut seed = 1u32;
+        let mut noise = move || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 16) as f32 / 65536.0 * 0.1 - 0.05
+        };
+
+        let mut input = tone_iq(130.5e3, 0.5, 3 * period);
+        for (i, sample) in input.iter_mut().enumerate() {
+            if i % period < 2 * period / 5 || i % period >= 3 * period / 5 {
+                *sample = (0.0, 0.0);
+            }
+            *sample = (sample.0 + noise(), sample.1 + noise());
+        }
+
+        let to_cs8 = |x: f32| (x * 128.0).round() as i8 as u8;
+        let data: Vec<_> = input.iter().flat_map(|&(i, q)| vec![to_cs8(i), to_cs8(q)]).collect();
+        File::create(path).unwrap().write_all(&data).unwrap();
+    }
+
+    #[test]
+    fn converts_captures() {
+        let dir = TempDir::new("convert");
+        let input = dir.0.join("capture.cs8");
+        write_capture(&input);
+
+        let mut settings = filtered(130e3);
+        settings.tracking = Some(TrackingSettings::new(1000.0));
+        let (output, track_log) = (dir.0.join("capture.wav"), dir.0.join("capture.track.csv"));
+        let summary = convert(&input, &output, &track_log, &settings).unwrap();
+
+        assert!((summary.duration - 1.5).abs() < 1e-9);
+        assert_eq!(summary.pulses, Some(3));
+        let (min, max) = summary.tracked.unwrap();
+        assert!(min >= 129e3 && max <= 131e3, "{} to {}", min, max);
+
+        // The header, audio and markers for the pulses are all there
+        let mut wav = vec![];
+        File::open(&output).unwrap().read_to_end(&mut wav).unwrap();
+        let samples = (1.5 * OUTPUT_RATE as f64) as usize;
+        assert!(wav.len() > 44 + 2 * samples, "{} bytes", wav.len());
+        assert!(wav.windows(4).any(|x| x == b"cue "));
+
+        // The log has a row for each block, following the tone through each pulse
+        let mut log = String::new();
+        File::open(&track_log).unwrap().read_to_string(&mut log).unwrap();
+        let rows: Vec<Vec<f64>> = log.lines().skip(1)
+            .map(|line| line.split(',').map(|x| x.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(log.lines().next(), Some("time,frequency,magnitude"));
+        assert_eq!(rows.len(), 3 * SAMP_RATE as usize / 2 / DEFAULT_BLOCK_SIZE);
+        for pulse in 0..3 {
+            let middle: Vec<_> = rows.iter()
+                .filter(|row| (row[0] - 0.25 - pulse as f64 * 0.5).abs() < 0.02)
+                .collect();
+            let freq = middle.iter().map(|row| row[1]).sum::<f64>() / middle.len() as f64;
+            assert!((freq - 130.5e3).abs() < 50.0, "pulse {} at {}", pulse, freq);
+            assert!(middle.iter().all(|row| (row[2] - 0.5).abs() < 0.05));
+        }
+
+        // Without a filter, the capture is resampled and there is no tracking log
+        let unused = dir.0.join("unused.csv");
+        let summary = convert(&input, &output, &unused, &Settings::new(SAMP_RATE)).unwrap();
+        assert_eq!(summary.pulses, None);
+        assert!(!unused.exists());
+    }
+
+    #[test]
+    fn filtered_audio_follows_pulses() {
+        let mut input = tone_iq(130e3, 0.5, 200000);
+        for sample in &mut input[100000..] {
+            *sample = (0.0, 0.0);
+        }
+        let audio: Vec<_> = audio(input.into_iter(), &filtered(130e3)).collect();
+
+        let half = OUTPUT_RATE as usize / 20;
+        assert!((peak(&audio[..half - 100]) - 1.0).abs() < 0.01);
+        assert!(peak(&audio[half + 100..]) < 0.01);
+
+        // The output tone crosses zero going upwards once per cycle
+        let crossings = audio[..half - 100].windows(2).filter(|x| x[0] < 0.0 && x[1] >= 0.0)
+            .count();
+        let expected = TONE_FREQ * (half - 100) as f64 / OUTPUT_RATE as f64;
+        assert!((crossings as f64 - expected).abs() <= 2.0, "{} crossings", crossings);
+    }
+}
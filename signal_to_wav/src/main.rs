@@ -1,87 +1,169 @@
 //! A tool for converting raw data from the HackRF to a wav file
 
-#[macro_use]
 extern crate clap;
-extern crate hound;
 extern crate iq_converter;
-extern crate sample;
-extern crate dsp_filters;
+extern crate num_cpus;
+extern crate signal_to_wav;
 
-use std::io::prelude::*;
-use std::fs::File;
-use std::f32;
+use std::path::{Path, PathBuf};
+use std::process;
 
-use clap::App;
+use clap::{App, Arg};
 
-use dsp_filters::{GoertzelFilter, window};
-use iq_converter::IqConverter;
+use iq_converter::cli::{exit_with_error, parse_arg, status};
+use iq_converter::goertzel::{FilterSettings, TrackingSettings};
+use iq_converter::pulse::DEFAULT_THRESHOLD;
+use iq_converter::units::{format_frequency, parse_count, parse_duration, parse_frequency};
+use iq_converter::units::{parse_level, parse_rate};
+use iq_converter::window::Window;
 
-use hound::{WavSpec, WavWriter};
-
-use sample::Sample;
-use sample::signal;
-use sample::rate::Converter;
-
-const FILTER_ELEMENTS: usize = 100;
+use signal_to_wav::{batch, convert, default_track_log, Settings, DEFAULT_BLOCK_SIZE, OUTPUT_RATE};
+use signal_to_wav::output::{OutputFormat, STDIO};
+use signal_to_wav::squelch::{SquelchSettings, DEFAULT_HANG};
 
 fn main() {
     let matches = App::new("Signal to Wav Converter")
-        .arg_from_usage("<input> 'The path to the input file.'")
-        .arg_from_usage("-o, --output [output] 'Name of output file. (default: output.wav)'")
-        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set input file sample rate (default: 2e6)'")
-        .arg_from_usage("-f, --filter [freq] 'Set filter frequency (offset in MHz)'")
+        .arg_from_usage("<input> 'The path to the input file, or - to read from stdin. In batch \
+            mode this may be a directory or a glob pattern such as \"captures/*.bin\".'")
+        .arg_from_usage("-o, --output [output] 'Name of output file, or - to write to stdout. \
+            (default: output.wav)'")
+        .arg_from_usage("-F, --format [format] 'Set the output format: wav, s16 (headerless \
+            16-bit PCM) or f32 (headerless float PCM) (default: wav)'")
+        .arg_from_usage("-b, --batch 'Convert every capture matched by the input'")
+        .arg_from_usage("-d, --output_dir [output_dir] 'Directory to write batch outputs to. \
+            (default: next to each input)'")
+        .arg_from_usage("-j, --jobs [jobs] 'Number of captures to convert at once in batch mode. \
+            (default: number of CPUs)'")
+        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set input file sample rate, e.g. 2e6, 2M or \
+            2MS/s (default: 2MS/s)'")
+        .arg(Arg::from_usage("-f, --filter [freq] 'Set filter frequency as an offset from the \
+            centre frequency, e.g. 130000, 130k or -0.25MHz'").allow_hyphen_values(true))
+        .arg_from_usage("-w, --window [window] 'Set the filter window: rectangular, hann, hamming, \
+            blackman-harris, flat-top or kaiser[:<beta>] (default: blackman-harris)'")
+        .arg_from_usage("-n, --block_size [block_size] 'Set the number of samples in each filter \
+            block (default: 100)'")
+        .arg_from_usage("--hop [hop] 'Set the number of samples between the start of each filter \
+            block (default: block_size)'")
+        .arg_from_usage("-t, --track [band] 'Follow a drifting tone within this distance of the \
+            filter frequency, e.g. 500 or 1.5kHz'")
+        .arg_from_usage("--track_step [step] 'Set the spacing of the frequencies searched when \
+            tracking (default: band / 10)'")
+        .arg_from_usage("--track_log [track_log] 'Name of the CSV file to write the tracked \
+            frequency to. (default: <output>.track.csv)'")
+        .arg_from_usage("-q, --squelch [threshold] 'Mute the output when the filtered signal is \
+            less than this many dB above the noise floor'")
+        .arg_from_usage("--hang [hang] 'Set how long the squelch stays open after the signal \
+            drops below the threshold, e.g. 200ms (default: 200ms)'")
+        .arg_from_usage("--pulse_threshold [threshold] 'Mark pulses in the wav file where the \
+            filtered signal is at least this many dB above the noise floor (default: 10)'")
         .get_matches();
 
-    let input_filename = matches.value_of("input").unwrap();
-    let output_filename = matches.value_of("output").unwrap_or("output.wav".into());
-    let filter = value_t!(matches, "filter", f64);
-    let input_samp_rate = value_t!(matches, "samp_rate", f64).unwrap_or(2e6);
+    let input = matches.value_of("input").unwrap();
 
-    let spec = WavSpec {
-        channels: 1,
-        sample_rate: 44100,
-        bits_per_sample: 16
-    };
+    let mut filter_settings = FilterSettings::new(parse_arg(&matches, "block_size", parse_count)
+        .unwrap_or(DEFAULT_BLOCK_SIZE));
+    filter_settings.hop = parse_arg(&matches, "hop", parse_count)
+        .unwrap_or(filter_settings.block_size);
+    if let Some(window) = matches.value_of("window") {
+        filter_settings.window = window.parse::<Window>()
+            .unwrap_or_else(|e| exit_with_error(&e));
+    }
+    if let Err(e) = filter_settings.validate() {
+        exit_with_error(&e);
+    }
 
-    let mut input_file = File::open(&input_filename).unwrap();
-    let mut output = WavWriter::create(&output_filename, spec).unwrap();
+    let tracking = parse_arg(&matches, "track", parse_frequency).map(|band| {
+        let mut tracking = TrackingSettings::new(band as f32);
+        if let Some(step) = parse_arg(&matches, "track_step", parse_frequency) {
+            tracking.step = step as f32;
+        }
+        if let Err(e) = tracking.validate() {
+            exit_with_error(&e);
+        }
+        tracking
+    });
 
-    let mut data = vec![];
-    input_file.read_to_end(&mut data).unwrap();
+    let squelch = parse_arg(&matches, "squelch", parse_level).map(|threshold| {
+        SquelchSettings {
+            threshold: threshold,
+            hang: parse_arg(&matches, "hang", parse_duration).unwrap_or(DEFAULT_HANG),
+        }
+    });
+
+    let settings = Settings {
+        format: parse_arg(&matches, "format", |x| x.parse::<OutputFormat>())
+            .unwrap_or(OutputFormat::Wav),
+        samp_rate: parse_arg(&matches, "samp_rate", parse_rate).unwrap_or(2e6),
+        filter: parse_arg(&matches, "filter", parse_frequency),
+        filter_settings: filter_settings,
+        tracking: tracking,
+        squelch: squelch,
+        pulse_threshold: parse_arg(&matches, "pulse_threshold", parse_level)
+            .unwrap_or(DEFAULT_THRESHOLD),
+    };
 
-    if let Some(filter_freq) = filter.ok() {
-        let input = IqConverter::new(data).collect::<Vec<_>>();
+    if settings.squelch.is_some() && settings.filter.is_none() {
+        exit_with_error("The squelch can only be used with a filter, see --filter");
+    }
 
-        let mut buffer = vec![];
+    if matches.is_present("batch") || Path::new(input).is_dir() {
+        if matches.is_present("output") || matches.is_present("track_log") {
+            exit_with_error("--output and --track_log cannot be used in batch mode, use \
+                --output_dir instead");
+        }
 
-        let mut filter = GoertzelFilter::new(input_samp_rate as f32, filter_freq as f32,
-            FILTER_ELEMENTS, window::blackman_harris);
+        let inputs = batch::find_captures(input).unwrap_or_else(|e| exit_with_error(&e));
+        let output_dir = matches.value_of("output_dir").map(PathBuf::from);
+        let jobs = parse_arg(&matches, "jobs", parse_count).unwrap_or_else(num_cpus::get).max(1);
 
-        for chunk in input.chunks(FILTER_ELEMENTS) {
-            for &sample in chunk {
-                filter.input(sample.1, sample.0);
-            }
-            buffer.push([filter.output()])
+        if !batch::run(inputs, output_dir, settings, jobs) {
+            process::exit(1);
         }
-        let max = buffer.iter().map(|x| x[0]).fold(f32::NAN, f32::max);
+        return;
+    }
 
-        let input_rate = input_samp_rate / FILTER_ELEMENTS as f64;
-        let out_rate = spec.sample_rate as f64;
+    let default_output = format!("output.{}", settings.format.extension());
+    let output_filename = matches.value_of("output").unwrap_or(&default_output);
 
-        let converter = Converter::from_hz_to_hz(buffer.into_iter(), input_rate, out_rate);
-        let base_signal = signal::rate(out_rate).const_hz(1200.0).sine();
+    // When the audio is going to stdout, status messages go to stderr so they don't end up in it
+    let to_stdout = output_filename == STDIO;
 
-        for (amplitude, signal) in converter.into_iter().zip(base_signal) {
-            let amp = amplitude[0] / max;
-            output.write_sample((signal[0] as f32 * amp).to_sample::<i16>()).unwrap();
-        }
+    let track_log_base = if to_stdout { &*default_output } else { output_filename };
+    let track_log_filename = matches.value_of("track_log").map(PathBuf::from)
+        .unwrap_or_else(|| default_track_log(Path::new(track_log_base)));
+
+    if let Some(filter_freq) = settings.filter {
+        status(to_stdout, &format!("Filtering at {}", format_frequency(filter_freq)));
+    }
+    if to_stdout {
+        status(true, &format!("Writing {} to stdout ({}, mono, {} Hz)", settings.format,
+            settings.format.sample_format().name(), OUTPUT_RATE));
     }
-    else {
-        let converter = Converter::from_hz_to_hz(IqConverter::new(data).map(|s| [s.0]),
-            input_samp_rate, spec.sample_rate as f64);
 
-        for sample in converter {
-            output.write_sample(sample[0].to_sample::<i16>()).unwrap();
+    match convert(Path::new(input), Path::new(output_filename), &track_log_filename, &settings) {
+        Ok(summary) => {
+            if let Some((min, max)) = summary.tracked {
+                status(to_stdout, &format_track(min, max));
+            }
+            if let Some(open) = summary.squelch_open {
+                status(to_stdout, &format!("Squelch open for {:.1}% of the capture",
+                    open * 100.0));
+            }
+            if let Some(pulses) = summary.pulses {
+                status(to_stdout, &format!("Found {} pulses", pulses));
+                if to_stdout && settings.format == OutputFormat::Wav && pulses > 0 {
+                    status(true, "Warning: the pulse markers were not written, since they can't \
+                        be added to a wav file on stdout");
+                }
+            }
         }
+        Err(e) => exit_with_error(&format!("Unable to convert {}: {}", input, e)),
     }
 }
+
+/// Describes the range of frequencies the tracker followed
+fn format_track(min: f32, max: f32) -> String {
+    let (min, max) = (min as f64, max as f64);
+    format!("Tracked frequency: {} to {} (drift {})", format_frequency(min),
+        format_frequency(max), format_frequency(max - min))
+}
@@ -0,0 +1,488 @@
+//! Writers for the audio produced by the converter
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufWriter, SeekFrom, Stdout};
+use std::path::Path;
+use std::str::FromStr;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use sample::Sample;
+
+/// The file name used to read from stdin or write to stdout
+pub const STDIO: &'static str = "-";
+
+/// The value used for the sizes in a wav header when the length of the stream is not yet known.
+/// Most players treat this as "read until the end of the stream".
+const UNKNOWN_SIZE: u32 = 0xFFFFFFFF;
+
+/// The format of the output file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A 16-bit wav file
+    Wav,
+    /// Headerless signed 16-bit little endian PCM
+    S16,
+    /// Headerless 32-bit little endian floating point PCM
+    F32,
+}
+
+impl OutputFormat {
+    /// The extension used for files written in this format
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            OutputFormat::Wav => "wav",
+            OutputFormat::S16 => "s16",
+            OutputFormat::F32 => "f32",
+        }
+    }
+
+    /// The format of the samples written
+    pub fn sample_format(&self) -> SampleFormat {
+        match *self {
+            OutputFormat::Wav | OutputFormat::S16 => SampleFormat::S16,
+            OutputFormat::F32 => SampleFormat::F32,
+        }
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OutputFormat, String> {
+        match &*s.trim().to_lowercase() {
+            "wav" => Ok(OutputFormat::Wav),
+            "s16" | "s16le" => Ok(OutputFormat::S16),
+            "f32" | "f32le" => Ok(OutputFormat::F32),
+            _ => Err(format!("Unknown output format `{}` (expected wav, s16 or f32)", s)),
+        }
+    }
+}
+
+/// The encoding of individual samples
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    S16,
+    F32,
+}
+
+impl SampleFormat {
+    /// The number of bits used for each sample
+    pub fn bits(&self) -> u16 {
+        match *self {
+            SampleFormat::S16 => 16,
+            SampleFormat::F32 => 32,
+        }
+    }
+
+    /// A short description of the format, as used by `sox` and `ffmpeg`
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SampleFormat::S16 => "s16le",
+            SampleFormat::F32 => "f32le",
+        }
+    }
+
+    /// Writes a single sample in the range [-1, 1]
+    fn write<W: Write>(&self, output: &mut W, sample: f32) -> io::Result<()> {
+        let sample = sample.max(-1.0).min(1.0);
+        match *self {
+            SampleFormat::S16 => output.write_i16::<LittleEndian>(sample.to_sample::<i16>()),
+            SampleFormat::F32 => output.write_f32::<LittleEndian>(sample),
+        }
+    }
+}
+
+/// A text field stored in the `LIST/INFO` chunk of a wav file
+#[derive(Clone, Debug)]
+pub struct InfoField {
+    /// The four character id of the field, e.g. `ICMT` for comments
+    pub id: [u8; 4],
+    pub value: String,
+}
+
+impl InfoField {
+    pub fn new(id: &[u8; 4], value: String) -> InfoField {
+        InfoField {
+            id: *id,
+            value: value,
+        }
+    }
+}
+
+/// A labelled region of the output, stored as a `cue` point in a wav file
+#[derive(Clone, Debug)]
+pub struct Marker {
+    /// The first sample of the region
+    pub position: u32,
+
+    /// The number of samples in the region
+    pub length: u32,
+
+    pub label: String,
+}
+
+/// A destination for blocks of audio samples
+pub trait AudioSink {
+    /// Writes a block of samples in the range [-1, 1]
+    fn write_block(&mut self, samples: &[f32]) -> io::Result<()>;
+
+    /// Completes the output once all of the samples have been written, storing `markers` in it if
+    /// the format supports them
+    fn finish(self: Box<Self>, markers: &[Marker]) -> io::Result<()>;
+}
+
+/// Creates the sink for an output file, or for stdout if `path` is `-`. The `info` fields are
+/// stored in the header of wav files and ignored for headerless formats.
+pub fn create(path: &Path, format: OutputFormat, sample_rate: u32, info: &[InfoField])
+    -> io::Result<Box<AudioSink>>
+{
+    let sample_format = format.sample_format();
+
+    if path == Path::new(STDIO) {
+        let stdout = BufWriter::new(io::stdout());
+        return Ok(match format {
+            OutputFormat::Wav => {
+                Box::new(try!(WavWriter::new(stdout, sample_format, sample_rate, info)))
+            }
+            _ => Box::new(RawWriter::new(stdout, sample_format)),
+        });
+    }
+
+    let file = BufWriter::new(try!(File::create(path)));
+    Ok(match format {
+        OutputFormat::Wav => Box::new(try!(WavWriter::new(file, sample_format, sample_rate, info))),
+        _ => Box::new(RawWriter::new(file, sample_format)),
+    })
+}
+
+/// Writes headerless PCM, flushing after every block so that it can be listened to live
+pub struct RawWriter<W> {
+    output: W,
+    format: SampleFormat,
+}
+
+impl<W: Write> RawWriter<W> {
+    pub fn new(output: W, format: SampleFormat) -> RawWriter<W> {
+        RawWriter {
+            output: output,
+            format: format,
+        }
+    }
+}
+
+impl<W: Write> AudioSink for RawWriter<W> {
+    fn write_block(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            try!(self.format.write(&mut self.output, sample));
+        }
+        self.output.flush()
+    }
+
+    fn finish(mut self: Box<Self>, _markers: &[Marker]) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Writes a mono wav file incrementally. The header is written up front with placeholder sizes,
+/// which are patched once all of the samples have been written if the output can be rewound.
+pub struct WavWriter<W> {
+    output: W,
+    format: SampleFormat,
+    data_size_pos: u64,
+    data_len: u32,
+
+    /// The position of the sample count in the `fact` chunk, which float files must have
+    fact_len_pos: Option<u64>,
+}
+
+impl<W: Write> WavWriter<W> {
+    /// Creates a new writer, immediately writing the header and any `info` fields to `output`
+    pub fn new(mut output: W, format: SampleFormat, sample_rate: u32, info: &[InfoField])
+        -> io::Result<WavWriter<W>>
+    {
+        let bytes_per_sample = format.bits() / 8;
+        let format_tag = match format {
+            SampleFormat::S16 => 1,
+            SampleFormat::F32 => 3,
+        };
+
+        try!(output.write_all(b"RIFF"));
+        try!(output.write_u32::<LittleEndian>(UNKNOWN_SIZE));
+        try!(output.write_all(b"WAVE"));
+
+        let mut fmt = vec![];
+        try!(fmt.write_u16::<LittleEndian>(format_tag));
+        try!(fmt.write_u16::<LittleEndian>(1));
+        try!(fmt.write_u32::<LittleEndian>(sample_rate));
+        try!(fmt.write_u32::<LittleEndian>(sample_rate * bytes_per_sample as u32));
+        try!(fmt.write_u16::<LittleEndian>(bytes_per_sample));
+        try!(fmt.write_u16::<LittleEndian>(format.bits()));
+
+        // Formats other than integer PCM need the size of the (empty) format extension, and a
+        // `fact` chunk holding the number of samples
+        let fact = format_tag != 1;
+        if fact {
+            try!(fmt.write_u16::<LittleEndian>(0));
+        }
+        let mut header_len = 12 + try!(write_chunk(&mut output, b"fmt ", &fmt));
+
+        let mut fact_len_pos = None;
+        if fact {
+            let mut len = vec![];
+            try!(len.write_u32::<LittleEndian>(UNKNOWN_SIZE));
+            fact_len_pos = Some(header_len + 8);
+            header_len += try!(write_chunk(&mut output, b"fact", &len));
+        }
+
+        if !info.is_empty() {
+            let mut list = b"INFO".to_vec();
+            for field in info {
+                let mut value = field.value.clone().into_bytes();
+                value.push(0);
+                try!(write_chunk(&mut list, &field.id, &value));
+            }
+            header_len += try!(write_chunk(&mut output, b"LIST", &list));
+        }
+
+        try!(output.write_all(b"data"));
+        try!(output.write_u32::<LittleEndian>(UNKNOWN_SIZE));
+
+        Ok(WavWriter {
+            output: output,
+            format: format,
+            data_size_pos: header_len + 4,
+            data_len: 0,
+            fact_len_pos: fact_len_pos,
+        })
+    }
+
+    /// Writes a single sample in the range [-1, 1]
+    pub fn write_sample(&mut self, sample: f32) -> io::Result<()> {
+        try!(self.format.write(&mut self.output, sample));
+        self.data_len = self.data_len.saturating_add(self.format.bits() as u32 / 8);
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Writes the `markers` after the data and patches the sizes in the header now that the
+    /// length of the file is known
+    pub fn finalize(mut self, markers: &[Marker]) -> io::Result<()> {
+        if self.data_len % 2 == 1 {
+            try!(self.output.write_u8(0));
+        }
+
+        if !markers.is_empty() {
+            let mut cue = vec![];
+            try!(cue.write_u32::<LittleEndian>(markers.len() as u32));
+            for (id, marker) in (1..).zip(markers) {
+                try!(cue.write_u32::<LittleEndian>(id));
+                try!(cue.write_u32::<LittleEndian>(marker.position));
+                try!(cue.write_all(b"data"));
+                try!(cue.write_u32::<LittleEndian>(0));
+                try!(cue.write_u32::<LittleEndian>(0));
+                try!(cue.write_u32::<LittleEndian>(marker.position));
+            }
+            try!(write_chunk(&mut self.output, b"cue ", &cue));
+
+            // Labels and region lengths for each cue point go in an associated data list
+            let mut adtl = b"adtl".to_vec();
+            for (id, marker) in (1..).zip(markers) {
+                let mut label = vec![];
+                try!(label.write_u32::<LittleEndian>(id));
+                try!(label.write_all(marker.label.as_bytes()));
+                try!(label.write_u8(0));
+                try!(write_chunk(&mut adtl, b"labl", &label));
+
+                let mut region = vec![];
+                try!(region.write_u32::<LittleEndian>(id));
+                try!(region.write_u32::<LittleEndian>(marker.length));
+                try!(region.write_all(b"rgn "));
+                try!(region.write_all(&[0; 8]));
+                try!(write_chunk(&mut adtl, b"ltxt", &region));
+            }
+            try!(write_chunk(&mut self.output, b"LIST", &adtl));
+        }
+
+        let file_len = try!(self.output.seek(SeekFrom::Current(0)));
+        try!(self.output.seek(SeekFrom::Start(4)));
+        try!(self.output.write_u32::<LittleEndian>((file_len - 8).min(UNKNOWN_SIZE as u64) as u32));
+        try!(self.output.seek(SeekFrom::Start(self.data_size_pos)));
+        try!(self.output.write_u32::<LittleEndian>(self.data_len));
+        if let Some(pos) = self.fact_len_pos {
+            try!(self.output.seek(SeekFrom::Start(pos)));
+            try!(self.output.write_u32::<LittleEndian>(self.data_len / (self.format.bits() / 8)
+                as u32));
+        }
+        self.output.flush()
+    }
+}
+
+impl AudioSink for WavWriter<BufWriter<File>> {
+    fn write_block(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            try!(self.write_sample(sample));
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>, markers: &[Marker]) -> io::Result<()> {
+        self.finalize(markers)
+    }
+}
+
+impl AudioSink for WavWriter<BufWriter<Stdout>> {
+    fn write_block(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            try!(self.write_sample(sample));
+        }
+        self.output.flush()
+    }
+
+    /// stdout can't be rewound, so the sizes in the header are left as unknown. Anything written
+    /// after the data would be played as audio, so the markers are dropped.
+    fn finish(mut self: Box<Self>, _markers: &[Marker]) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// Writes a RIFF chunk, padding it to an even length, and returns the number of bytes written
+fn write_chunk<W: Write>(output: &mut W, id: &[u8; 4], body: &[u8]) -> io::Result<u64> {
+    try!(output.write_all(id));
+    try!(output.write_u32::<LittleEndian>(body.len() as u32));
+    try!(output.write_all(body));
+    if body.len() % 2 == 1 {
+        try!(output.write_u8(0));
+    }
+    Ok(8 + body.len() as u64 + body.len() as u64 % 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use byteorder::{ByteOrder, LittleEndian};
+
+    use super::*;
+
+    /// Writes `samples` to a wav file in memory
+    fn wav(format: SampleFormat, samples: &[f32], info: &[InfoField], markers: &[Marker])
+        -> Vec<u8>
+    {
+        let mut output = Cursor::new(vec![]);
+        {
+            let mut writer = WavWriter::new(&mut output, format, 8000, info).unwrap();
+            for &sample in samples {
+                writer.write_sample(sample).unwrap();
+            }
+            writer.finalize(markers).unwrap();
+        }
+        output.into_inner()
+    }
+
+    /// Splits the body of a RIFF file into its chunks, checking that they fill it exactly
+    fn chunks(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(LittleEndian::read_u32(&data[4..]) as usize, data.len() - 8);
+        assert_eq!(&data[8..12], b"WAVE");
+        sub_chunks(&data[12..])
+    }
+
+    fn sub_chunks(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut chunks = vec![];
+        while !data.is_empty() {
+            let len = LittleEndian::read_u32(&data[4..]) as usize;
+            chunks.push((&data[..4], &data[8..8 + len]));
+            data = &data[(8 + len + len % 2).min(data.len())..];
+        }
+        chunks
+    }
+
+    #[test]
+    fn writes_pcm_header() {
+        let data = wav(SampleFormat::S16, &[0.0, 0.5, -1.0], &[], &[]);
+        let chunks = chunks(&data);
+        let ids: Vec<_> = chunks.iter().map(|x| x.0).collect();
+        assert_eq!(ids, vec![&b"fmt "[..], b"data"]);
+
+        let fmt = chunks[0].1;
+        assert_eq!(fmt.len(), 16);
+        assert_eq!(LittleEndian::read_u16(&fmt[0..]), 1);
+        assert_eq!(LittleEndian::read_u32(&fmt[4..]), 8000);
+        assert_eq!(LittleEndian::read_u16(&fmt[14..]), 16);
+        assert_eq!(chunks[1].1.len(), 6);
+    }
+
+    #[test]
+    fn writes_float_header() {
+        let data = wav(SampleFormat::F32, &[0.0, 0.5, -1.0], &[], &[]);
+        let chunks = chunks(&data);
+        let ids: Vec<_> = chunks.iter().map(|x| x.0).collect();
+        assert_eq!(ids, vec![&b"fmt "[..], b"fact", b"data"]);
+
+        // The format extension size follows the standard fields, and the fact chunk holds the
+        // number of samples
+        let fmt = chunks[0].1;
+        assert_eq!(fmt.len(), 18);
+        assert_eq!(LittleEndian::read_u16(&fmt[0..]), 3);
+        assert_eq!(LittleEndian::read_u16(&fmt[14..]), 32);
+        assert_eq!(LittleEndian::read_u16(&fmt[16..]), 0);
+        assert_eq!(LittleEndian::read_u32(chunks[1].1), 3);
+
+        let samples = chunks[2].1;
+        assert_eq!(samples.len(), 12);
+        assert_eq!(LittleEndian::read_f32(&samples[4..]), 0.5);
+    }
+
+    #[test]
+    fn writes_info_and_markers() {
+        let info = [InfoField::new(b"ICMT", "odds".into())];
+        let markers = [
+            Marker { position: 10, length: 5, label: "Pulse 1".into() },
+            Marker { position: 100, length: 20, label: "Pulse 10".into() },
+        ];
+        let data = wav(SampleFormat::S16, &[0.25; 201], &info, &markers);
+        let chunks = chunks(&data);
+        let ids: Vec<_> = chunks.iter().map(|x| x.0).collect();
+        assert_eq!(ids, vec![&b"fmt "[..], b"LIST", b"data", b"cue ", b"LIST"]);
+
+        // Odd length chunks are padded, which `sub_chunks` checks by finding the next chunk
+        let list = chunks[1].1;
+        assert_eq!(&list[..4], b"INFO");
+        assert_eq!(sub_chunks(&list[4..]), vec![(&b"ICMT"[..], &b"odds\0"[..])]);
+        assert_eq!(chunks[2].1.len(), 402);
+
+        let cue = chunks[3].1;
+        assert_eq!(cue.len(), 4 + 2 * 24);
+        assert_eq!(LittleEndian::read_u32(cue), 2);
+        for (i, point) in cue[4..].chunks(24).enumerate() {
+            assert_eq!(LittleEndian::read_u32(point), i as u32 + 1);
+            assert_eq!(LittleEndian::read_u32(&point[4..]), markers[i].position);
+            assert_eq!(&point[8..12], b"data");
+            assert_eq!(LittleEndian::read_u32(&point[20..]), markers[i].position);
+        }
+
+        let adtl = chunks[4].1;
+        assert_eq!(&adtl[..4], b"adtl");
+        let labels = sub_chunks(&adtl[4..]);
+        let ids: Vec<_> = labels.iter().map(|x| x.0).collect();
+        assert_eq!(ids, vec![&b"labl"[..], b"ltxt", b"labl", b"ltxt"]);
+        assert_eq!(labels[0].1, &b"\x01\0\0\0Pulse 1\0"[..]);
+        assert_eq!(labels[2].1, &b"\x02\0\0\0Pulse 10\0"[..]);
+
+        let region = labels[3].1;
+        assert_eq!(region.len(), 20);
+        assert_eq!(LittleEndian::read_u32(region), 2);
+        assert_eq!(LittleEndian::read_u32(&region[4..]), 20);
+        assert_eq!(&region[8..12], b"rgn ");
+    }
+}
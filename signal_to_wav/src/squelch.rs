@@ -0,0 +1,121 @@
+//! A noise gate for muting the output between pulses
+
+use iq_converter::noise::{magnitude_to_db, NoiseFloor, DEFAULT_ADAPT_RATE};
+
+/// The default time the squelch stays open after the signal drops below the threshold
+pub const DEFAULT_HANG: f64 = 0.2;
+
+/// The time taken to fade the output out when the squelch closes, to avoid clicks
+const FADE_TIME: f64 = 0.005;
+
+/// Settings controlling when the squelch opens
+#[derive(Copy, Clone, Debug)]
+pub struct SquelchSettings {
+    /// How far (in dB) the signal must be above the noise floor to open the squelch
+    pub threshold: f32,
+
+    /// How long (in seconds) the squelch stays open after the signal drops below the threshold
+    pub hang: f64,
+}
+
+/// A noise gate operating on a Goertzel magnitude stream
+pub struct Squelch {
+    noise_floor: NoiseFloor,
+    threshold: f32,
+    hang: usize,
+    remaining: usize,
+    gain: f32,
+    fade_step: f32,
+    open: usize,
+    total: usize,
+}
+
+impl Squelch {
+    /// Creates a squelch for a magnitude stream produced at `rate` magnitudes per second
+    pub fn new(settings: SquelchSettings, rate: f64) -> Squelch {
+        Squelch {
+            noise_floor: NoiseFloor::new(rate, DEFAULT_ADAPT_RATE),
+            threshold: settings.threshold,
+            hang: (settings.hang * rate).round() as usize,
+            remaining: 0,
+            gain: 0.0,
+            fade_step: (1.0 / (FADE_TIME * rate).max(1.0)) as f32,
+            open: 0,
+            total: 0,
+        }
+    }
+
+    /// Processes the next magnitude, returning the gain that should be applied to it
+    pub fn process(&mut self, magnitude: f32) -> f32 {
+        let floor = self.noise_floor.update(magnitude);
+        if magnitude_to_db(magnitude) - floor >= self.threshold {
+            self.remaining = self.hang + 1;
+        }
+
+        self.total += 1;
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.open += 1;
+            self.gain = 1.0;
+        }
+        else {
+            self.gain = (self.gain - self.fade_step).max(0.0);
+        }
+
+        self.gain
+    }
+
+    /// The fraction of the magnitudes processed so far for which the squelch was open
+    pub fn open_fraction(&self) -> f64 {
+        if self.total == 0 { 0.0 } else { self.open as f64 / self.total as f64 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rate of the magnitude streams in the tests
+    const RATE: f64 = 1000.0;
+
+    fn squelch() -> Squelch {
+        Squelch::new(SquelchSettings { threshold: 10.0, hang: 0.05 }, RATE)
+    }
+
+    fn process(squelch: &mut Squelch, magnitude: f32, count: usize) -> Vec<f32> {
+        (0..count).map(|_| squelch.process(magnitude)).collect()
+    }
+
+    #[test]
+    fn stays_closed_on_noise() {
+        let mut squelch = squelch();
+        assert!(process(&mut squelch, 0.001, 1000).iter().all(|&gain| gain == 0.0));
+        assert_eq!(squelch.open_fraction(), 0.0);
+    }
+
+    #[test]
+    fn opens_on_pulses_and_closes_after_the_hang() {
+        let mut squelch = squelch();
+        process(&mut squelch, 0.001, 1000);
+
+        assert!(process(&mut squelch, 0.1, 20).iter().all(|&gain| gain == 1.0));
+
+        // Open for the 50 ms hang, then faded out over 5 ms
+        let gains = process(&mut squelch, 0.001, 100);
+        assert!(gains[..50].iter().all(|&gain| gain == 1.0));
+        assert!(gains[50] > 0.0 && gains[50] < 1.0);
+        assert!(gains[51..55].windows(2).all(|x| x[1] < x[0]));
+        assert!(gains[55..].iter().all(|&gain| gain == 0.0));
+
+        assert!((squelch.open_fraction() - 70.0 / 1120.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ignores_pulses_below_the_threshold() {
+        let mut squelch = squelch();
+        process(&mut squelch, 0.001, 1000);
+
+        // 6 dB above the noise
+        assert!(process(&mut squelch, 0.002, 20).iter().all(|&gain| gain == 0.0));
+    }
+}
@@ -0,0 +1,129 @@
+//! Tick placement and labelling for plot axes
+
+/// A unit used to label an axis, e.g. milliseconds or kilohertz
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Unit {
+    /// The size of the unit in base units (seconds or Hz)
+    pub scale: f64,
+
+    /// The symbol for the unit
+    pub symbol: &'static str,
+}
+
+const TIME_UNITS: &'static [Unit] = &[
+    Unit { scale: 1.0, symbol: "s" },
+    Unit { scale: 1e-3, symbol: "ms" },
+    Unit { scale: 1e-6, symbol: "µs" },
+];
+
+const FREQUENCY_UNITS: &'static [Unit] = &[
+    Unit { scale: 1e9, symbol: "GHz" },
+    Unit { scale: 1e6, symbol: "MHz" },
+    Unit { scale: 1e3, symbol: "kHz" },
+    Unit { scale: 1.0, symbol: "Hz" },
+];
+
+/// Chooses the unit for labelling times spanning the given range
+pub fn time_unit(min: f64, max: f64) -> Unit {
+    choose_unit(TIME_UNITS, min.abs().max(max.abs()))
+}
+
+/// Chooses the unit for labelling frequencies spanning the given range
+pub fn frequency_unit(min: f64, max: f64) -> Unit {
+    choose_unit(FREQUENCY_UNITS, min.abs().max(max.abs()))
+}
+
+/// Picks the largest unit that `value` is at least one of
+fn choose_unit(units: &[Unit], value: f64) -> Unit {
+    units.iter().cloned().find(|unit| value >= unit.scale).unwrap_or(units[units.len() - 1])
+}
+
+/// Chooses the spacing between ticks so that there are at most `max_ticks` ticks between `min`
+/// and `max`. Steps are always 1, 2 or 5 times a power of ten.
+pub fn tick_step(min: f64, max: f64, max_ticks: usize) -> f64 {
+    let range = (max - min).abs();
+    if !(range > 0.0) || max_ticks == 0 {
+        return 1.0;
+    }
+
+    let rough = range / max_ticks as f64;
+    let magnitude = 10.0_f64.powf(rough.log10().floor());
+    for &multiple in &[1.0, 2.0, 5.0, 10.0] {
+        if multiple * magnitude >= rough {
+            return multiple * magnitude;
+        }
+    }
+    10.0 * magnitude
+}
+
+/// Gets the positions of ticks spaced `step` apart that lie between `min` and `max`
+pub fn ticks(min: f64, max: f64, step: f64) -> Vec<f64> {
+    let (min, max) = if min <= max { (min, max) } else { (max, min) };
+    let first = (min / step).ceil() as i64;
+    let last = (max / step).floor() as i64;
+    (first..last + 1).map(|i| i as f64 * step).collect()
+}
+
+/// Formats a tick value in the given unit, using just enough decimal places to distinguish ticks
+/// spaced `step` apart
+pub fn format_tick(value: f64, step: f64, unit: Unit) -> String {
+    let scaled_step = step / unit.scale;
+    let decimals = (0..9).find(|&d| {
+        let x = scaled_step * 10.0_f64.powi(d as i32);
+        (x - x.round()).abs() < 1e-6 * x.max(1.0)
+    }).unwrap_or(9);
+
+    // Avoid labelling the tick at zero as "-0"
+    let value = if (value / step).abs() < 1e-9 { 0.0 } else { value / unit.scale };
+    format!("{:.*}", decimals, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_at_1_2_or_5_times_a_power_of_ten() {
+        assert_eq!(tick_step(0.0, 1.0, 10), 0.1);
+        assert_eq!(tick_step(0.0, 1.0, 8), 0.2);
+        assert_eq!(tick_step(0.0, 1.0, 3), 0.5);
+        assert_eq!(tick_step(0.0, 7.0, 1), 10.0);
+        assert_eq!(tick_step(-1e6, 1e6, 5), 5e5);
+        assert_eq!(tick_step(1.0, 0.0, 10), 0.1);
+
+        // Empty ranges and no room for ticks fall back to a step of one
+        assert_eq!(tick_step(2.0, 2.0, 10), 1.0);
+        assert_eq!(tick_step(0.0, 1.0, 0), 1.0);
+    }
+
+    #[test]
+    fn places_ticks_on_multiples_of_the_step() {
+        assert_eq!(ticks(-1.0, 1.0, 0.5), vec![-1.0, -0.5, 0.0, 0.5, 1.0]);
+        assert_eq!(ticks(0.1, 0.8, 0.25), vec![0.25, 0.5, 0.75]);
+        assert_eq!(ticks(0.8, 0.1, 0.25), vec![0.25, 0.5, 0.75]);
+        assert!(ticks(0.1, 0.2, 0.25).is_empty());
+    }
+
+    #[test]
+    fn chooses_units_for_the_range() {
+        assert_eq!(time_unit(0.0, 2.5).symbol, "s");
+        assert_eq!(time_unit(0.0, 0.5).symbol, "ms");
+        assert_eq!(time_unit(0.0, 1e-9).symbol, "µs");
+        assert_eq!(frequency_unit(-1e6, 1e6).symbol, "MHz");
+        assert_eq!(frequency_unit(-500.0, 500.0).symbol, "Hz");
+    }
+
+    #[test]
+    fn formats_ticks_with_enough_decimals_for_the_step() {
+        let khz = frequency_unit(-2e3, 2e3);
+        let ms = time_unit(0.0, 0.5);
+        assert_eq!(format_tick(1500.0, 500.0, khz), "1.5");
+        assert_eq!(format_tick(-2000.0, 1000.0, khz), "-2");
+        assert_eq!(format_tick(0.25, 0.25, time_unit(0.0, 1.0)), "0.25");
+        assert_eq!(format_tick(0.002, 0.001, ms), "2");
+        assert_eq!(format_tick(0.0025, 0.0005, ms), "2.5");
+
+        // A tick at zero that picked up rounding error isn't labelled as negative
+        assert_eq!(format_tick(-1e-15, 0.1, time_unit(0.0, 1.0)), "0.0");
+    }
+}
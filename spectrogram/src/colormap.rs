@@ -0,0 +1,136 @@
+//! Colour maps for turning power levels into pixels
+
+use std::fmt;
+use std::str::FromStr;
+
+const VIRIDIS: &'static [[u8; 3]] = &[
+    [68, 1, 84], [72, 40, 120], [62, 74, 137], [49, 104, 142], [38, 130, 142], [31, 158, 137],
+    [53, 183, 121], [109, 205, 89], [180, 222, 44], [218, 227, 25], [253, 231, 37],
+];
+
+const INFERNO: &'static [[u8; 3]] = &[
+    [0, 0, 4], [22, 11, 57], [58, 9, 99], [96, 19, 110], [133, 33, 107], [169, 46, 94],
+    [203, 71, 72], [230, 104, 44], [247, 144, 16], [250, 193, 39], [252, 255, 164],
+];
+
+const JET: &'static [[u8; 3]] = &[
+    [0, 0, 143], [0, 0, 255], [0, 127, 255], [0, 255, 255], [127, 255, 127], [255, 255, 0],
+    [255, 127, 0], [255, 0, 0], [127, 0, 0],
+];
+
+const GREY: &'static [[u8; 3]] = &[[0, 0, 0], [255, 255, 255]];
+
+/// A mapping from a normalised level in [0, 1] to a colour
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Inferno,
+    Jet,
+    Grey,
+}
+
+impl Colormap {
+    /// The names accepted when parsing a colour map
+    pub fn variants() -> [&'static str; 4] {
+        ["viridis", "inferno", "jet", "grey"]
+    }
+
+    /// Gets the colour for a level between 0 and 1. Levels outside this range are clamped.
+    pub fn map(&self, level: f32) -> [u8; 3] {
+        let points = match *self {
+            Colormap::Viridis => VIRIDIS,
+            Colormap::Inferno => INFERNO,
+            Colormap::Jet => JET,
+            Colormap::Grey => GREY,
+        };
+
+        let level = if level.is_nan() { 0.0 } else { level.max(0.0).min(1.0) };
+        let position = level * (points.len() - 1) as f32;
+        let index = (position.floor() as usize).min(points.len() - 2);
+        let t = position - index as f32;
+
+        let (a, b) = (points[index], points[index + 1]);
+        [lerp(a[0], b[0], t), lerp(a[1], b[1], t), lerp(a[2], b[2], t)]
+    }
+}
+
+impl Default for Colormap {
+    fn default() -> Colormap {
+        Colormap::Viridis
+    }
+}
+
+impl fmt::Display for Colormap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Colormap::Viridis => "viridis",
+            Colormap::Inferno => "inferno",
+            Colormap::Jet => "jet",
+            Colormap::Grey => "grey",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Colormap, String> {
+        match &*s.trim().to_lowercase() {
+            "viridis" => Ok(Colormap::Viridis),
+            "inferno" => Ok(Colormap::Inferno),
+            "jet" => Ok(Colormap::Jet),
+            "grey" | "gray" => Ok(Colormap::Grey),
+            _ => Err(format!("Unknown colour map `{}` (expected one of: {})", s,
+                Colormap::variants().join(", "))),
+        }
+    }
+}
+
+fn lerp(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * t).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+
+    use super::*;
+
+    const ALL: [Colormap; 4] = [Colormap::Viridis, Colormap::Inferno, Colormap::Jet,
+        Colormap::Grey];
+
+    #[test]
+    fn parses_colormaps_by_name() {
+        assert_eq!(" Viridis ".parse::<Colormap>(), Ok(Colormap::Viridis));
+        assert_eq!("INFERNO".parse::<Colormap>(), Ok(Colormap::Inferno));
+        assert_eq!("gray".parse::<Colormap>(), Ok(Colormap::Grey));
+        assert_eq!("rainbow".parse::<Colormap>().err(), Some("Unknown colour map `rainbow` \
+            (expected one of: viridis, inferno, jet, grey)".into()));
+
+        for &colormap in &ALL {
+            assert_eq!(colormap.to_string().parse::<Colormap>(), Ok(colormap));
+        }
+    }
+
+    #[test]
+    fn maps_levels_between_the_end_colours() {
+        for &colormap in &ALL {
+            let (low, high) = (colormap.map(0.0), colormap.map(1.0));
+            assert!(low != high);
+
+            // Levels outside the range are clamped, and missing levels are drawn as the lowest
+            assert_eq!(colormap.map(-0.5), low);
+            assert_eq!(colormap.map(1.5), high);
+            assert_eq!(colormap.map(f32::NAN), low);
+        }
+
+        assert_eq!(Colormap::Viridis.map(0.0), [68, 1, 84]);
+        assert_eq!(Colormap::Viridis.map(1.0), [253, 231, 37]);
+        assert_eq!(Colormap::Jet.map(0.5), [127, 255, 127]);
+
+        // Levels between the points of the map are interpolated
+        assert_eq!(Colormap::Grey.map(0.5), [128, 128, 128]);
+        assert_eq!(Colormap::Grey.map(0.25), [64, 64, 64]);
+    }
+}
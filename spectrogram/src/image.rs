@@ -0,0 +1,81 @@
+//! A simple RGB image that can be drawn into and saved as a PNG
+
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use png;
+use png::HasParameters;
+
+use font;
+
+pub const WHITE: [u8; 3] = [255, 255, 255];
+pub const BLACK: [u8; 3] = [0, 0, 0];
+
+/// An 8-bit RGB image
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+    /// Creates a new image filled with `color`
+    pub fn new(width: usize, height: usize, color: [u8; 3]) -> Image {
+        let mut data = Vec::with_capacity(width * height * 3);
+        for _ in 0..width * height {
+            data.extend_from_slice(&color);
+        }
+
+        Image {
+            width: width,
+            height: height,
+            data: data,
+        }
+    }
+
+    /// Sets the colour of a pixel, ignoring pixels outside of the image
+    pub fn set(&mut self, x: usize, y: usize, color: [u8; 3]) {
+        if x < self.width && y < self.height {
+            let offset = (y * self.width + x) * 3;
+            self.data[offset..offset + 3].copy_from_slice(&color);
+        }
+    }
+
+    /// Fills a rectangle with its top left corner at (`x`, `y`)
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: [u8; 3]) {
+        for j in y..y + height {
+            for i in x..x + width {
+                self.set(i, j, color);
+            }
+        }
+    }
+
+    /// Draws text with its top left corner at (`x`, `y`), scaling each font pixel to a
+    /// `scale` x `scale` block
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, scale: usize, color: [u8; 3]) {
+        for (n, c) in text.chars().enumerate() {
+            let left = x + n * font::ADVANCE * scale;
+            for row in 0..font::GLYPH_HEIGHT {
+                for col in 0..font::GLYPH_WIDTH {
+                    if font::pixel(c, col, row) {
+                        self.fill_rect(left + col * scale, y + row * scale, scale, scale, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Saves the image as a PNG file
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<Error>> {
+        let output = BufWriter::new(try!(File::create(path)));
+
+        let mut encoder = png::Encoder::new(output, self.width as u32, self.height as u32);
+        encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+
+        let mut writer = try!(encoder.write_header());
+        try!(writer.write_image_data(&self.data));
+        Ok(())
+    }
+}
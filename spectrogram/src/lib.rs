@@ -0,0 +1,313 @@
+//! Rendering of spectrograms of raw I/Q samples from the HackRF
+
+extern crate iq_converter;
+extern crate png;
+
+pub mod axis;
+pub mod colormap;
+pub mod font;
+pub mod image;
+
+use iq_converter::goertzel::FilterSettings;
+use iq_converter::spectrum::{self, Spectra};
+
+use colormap::Colormap;
+use image::{Image, BLACK, WHITE};
+
+/// The scale that label text is drawn at
+const TEXT_SCALE: usize = 2;
+
+/// The length of the tick marks on each axis in pixels
+const TICK_LENGTH: usize = 5;
+
+/// The gap between the plot and the edges of the image, and between labels, in pixels
+const PADDING: usize = 10;
+
+/// The width of the colour bar in pixels
+const COLOR_BAR_WIDTH: usize = 20;
+
+/// The minimum horizontal and vertical spacing between tick labels in pixels
+const TICK_SPACING_X: usize = 120;
+const TICK_SPACING_Y: usize = 50;
+
+/// The power spectra of a capture, reduced to at most a fixed number of columns
+pub struct Spectrogram {
+    /// The power (in dB) of each frequency bin for each column, ordered from the lowest frequency
+    /// to the highest
+    pub columns: Vec<Vec<f32>>,
+
+    /// The sample rate of the capture
+    pub samp_rate: f64,
+
+    /// The number of frequency bins in each column
+    pub fft_size: usize,
+
+    /// The length of time covered by each column in seconds
+    pub column_duration: f64,
+}
+
+impl Spectrogram {
+    /// Computes the spectrogram of an I/Q stream containing `samples` samples, using at most
+    /// `max_columns` columns. When several spectra share a column the peak power of each bin is
+    /// kept, so that short pulses are not averaged away.
+    ///
+    /// Panics if the block size of `settings` is not a power of two.
+    pub fn compute<I>(input: I, samples: usize, samp_rate: f64, settings: FilterSettings,
+        max_columns: usize) -> Spectrogram
+        where I: Iterator<Item=(f32, f32)>
+    {
+        let frames = match samples >= settings.block_size {
+            true => (samples - settings.block_size) / settings.hop + 1,
+            false => 0,
+        };
+        let max_columns = max_columns.max(1);
+        let frames_per_column = ((frames + max_columns - 1) / max_columns).max(1);
+
+        let mut columns: Vec<Vec<f32>> = vec![];
+        for (i, spectrum) in Spectra::new(input, settings).enumerate() {
+            if i % frames_per_column == 0 {
+                columns.push(spectrum);
+            }
+            else if let Some(column) = columns.last_mut() {
+                for (peak, power) in column.iter_mut().zip(spectrum) {
+                    *peak = peak.max(power);
+                }
+            }
+        }
+
+        for column in &mut columns {
+            for power in column.iter_mut() {
+                *power = spectrum::to_db(*power);
+            }
+        }
+
+        Spectrogram {
+            columns: columns,
+            samp_rate: samp_rate,
+            fft_size: settings.block_size,
+            column_duration: (frames_per_column * settings.hop) as f64 / samp_rate,
+        }
+    }
+
+    /// The length of time covered by the spectrogram in seconds
+    pub fn duration(&self) -> f64 {
+        self.columns.len() as f64 * self.column_duration
+    }
+
+    /// Chooses a range of levels (in dB) to display, from just below the median level (which is
+    /// usually the noise floor) up to the peak level
+    pub fn auto_range(&self) -> (f32, f32) {
+        let mut levels: Vec<f32> = self.columns.iter().flat_map(|x| x.iter().cloned()).collect();
+        if levels.is_empty() {
+            return (-100.0, 0.0);
+        }
+        levels.sort_by(|a, b| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+
+        let median = levels[levels.len() / 2];
+        let peak = levels[levels.len() - 1];
+        (median - 10.0, peak.max(median + 10.0))
+    }
+}
+
+/// Options controlling how a spectrogram is drawn
+#[derive(Copy, Clone, Debug)]
+pub struct Style {
+    /// The level (in dB) drawn with the lowest colour of the colour map
+    pub min_db: f32,
+
+    /// The level (in dB) drawn with the highest colour of the colour map
+    pub max_db: f32,
+
+    pub colormap: Colormap,
+
+    /// The maximum number of rows used to draw the frequency bins. Neighbouring bins are combined
+    /// by taking their peak if there are more bins than rows.
+    pub max_rows: usize,
+}
+
+/// Draws a spectrogram with labelled time and frequency axes and a colour bar
+pub fn render(spectrogram: &Spectrogram, style: &Style) -> Image {
+    let (bins_per_row, rows) = row_binning(spectrogram.fft_size, style.max_rows);
+    let cols = spectrogram.columns.len().max(1);
+    let char_height = font::GLYPH_HEIGHT * TEXT_SCALE;
+
+    let half_rate = spectrogram.samp_rate / 2.0;
+    let freq_step = axis::tick_step(-half_rate, half_rate, (rows / TICK_SPACING_Y).max(2));
+    let freq_unit = axis::frequency_unit(-half_rate, half_rate);
+    let freq_ticks: Vec<_> = axis::ticks(-half_rate, half_rate, freq_step).into_iter()
+        .map(|f| (f, axis::format_tick(f, freq_step, freq_unit)))
+        .collect();
+
+    let duration = spectrogram.duration();
+    let time_step = axis::tick_step(0.0, duration, (cols / TICK_SPACING_X).max(2));
+    let time_unit = axis::time_unit(0.0, duration);
+    let time_ticks: Vec<_> = axis::ticks(0.0, duration, time_step).into_iter()
+        .map(|t| (t, axis::format_tick(t, time_step, time_unit)))
+        .collect();
+
+    let level_range = style.max_db - style.min_db;
+    let level_step = axis::tick_step(style.min_db as f64, style.max_db as f64,
+        (rows / TICK_SPACING_Y).max(2));
+    let level_unit = axis::Unit { scale: 1.0, symbol: "dB" };
+    let level_ticks: Vec<_> = axis::ticks(style.min_db as f64, style.max_db as f64, level_step)
+        .into_iter()
+        .map(|x| (x, axis::format_tick(x, level_step, level_unit)))
+        .collect();
+
+    let widest = |labels: &[(f64, String)]| {
+        labels.iter().map(|x| font::text_width(&x.1, TEXT_SCALE)).max().unwrap_or(0)
+    };
+
+    let left = PADDING + widest(&freq_ticks) + 4 + TICK_LENGTH;
+    let top = PADDING + char_height + PADDING;
+    let bottom = TICK_LENGTH + 4 + char_height + PADDING + char_height + PADDING;
+    let right = PADDING + COLOR_BAR_WIDTH + TICK_LENGTH + 4 + widest(&level_ticks) + PADDING;
+
+    let mut image = Image::new(left + cols + right, top + rows + bottom, WHITE);
+
+    // Draw the spectrogram itself, with the highest frequency at the top
+    let level = |db: f32| if level_range > 0.0 { (db - style.min_db) / level_range } else { 0.0 };
+    for (x, column) in spectrogram.columns.iter().enumerate() {
+        for row in 0..rows {
+            let peak = row_peak(column, row, bins_per_row);
+            image.set(left + x, top + row, style.colormap.map(level(peak)));
+        }
+    }
+
+    draw_border(&mut image, left, top, cols, rows);
+
+    // Frequency axis
+    image.draw_text(PADDING, PADDING, &format!("Frequency ({})", freq_unit.symbol), TEXT_SCALE,
+        BLACK);
+    for &(freq, ref label) in &freq_ticks {
+        let offset = ((freq + half_rate) / spectrogram.samp_rate * rows as f64) as usize;
+        let y = top + rows - offset.min(rows);
+        image.fill_rect(left - TICK_LENGTH, y, TICK_LENGTH, 1, BLACK);
+
+        let x = left - TICK_LENGTH - 4 - font::text_width(label, TEXT_SCALE);
+        image.draw_text(x, y.saturating_sub(char_height / 2), label, TEXT_SCALE, BLACK);
+    }
+
+    // Time axis
+    for &(time, ref label) in &time_ticks {
+        let offset = if duration > 0.0 { (time / duration * cols as f64) as usize } else { 0 };
+        let x = left + offset.min(cols);
+        image.fill_rect(x, top + rows, 1, TICK_LENGTH, BLACK);
+
+        let label_x = x.saturating_sub(font::text_width(label, TEXT_SCALE) / 2);
+        image.draw_text(label_x, top + rows + TICK_LENGTH + 4, label, TEXT_SCALE, BLACK);
+    }
+    let title = format!("Time ({})", time_unit.symbol);
+    let title_x = (left + cols / 2).saturating_sub(font::text_width(&title, TEXT_SCALE) / 2);
+    image.draw_text(title_x, top + rows + TICK_LENGTH + 4 + char_height + PADDING, &title,
+        TEXT_SCALE, BLACK);
+
+    // Colour bar
+    let bar_left = left + cols + PADDING;
+    for row in 0..rows {
+        let fraction = 1.0 - row as f32 / (rows.max(2) - 1) as f32;
+        image.fill_rect(bar_left, top + row, COLOR_BAR_WIDTH, 1, style.colormap.map(fraction));
+    }
+    draw_border(&mut image, bar_left, top, COLOR_BAR_WIDTH, rows);
+    image.draw_text(bar_left, PADDING, "dB", TEXT_SCALE, BLACK);
+
+    for &(db, ref label) in &level_ticks {
+        let offset = (level(db as f32) * rows as f32) as usize;
+        let y = top + rows - offset.min(rows);
+        image.fill_rect(bar_left + COLOR_BAR_WIDTH, y, TICK_LENGTH, 1, BLACK);
+        image.draw_text(bar_left + COLOR_BAR_WIDTH + TICK_LENGTH + 4,
+            y.saturating_sub(char_height / 2), label, TEXT_SCALE, BLACK);
+    }
+
+    image
+}
+
+/// Gets the number of frequency bins combined into each row so that `bins` bins fit in at most
+/// `max_rows` rows, and the number of rows that takes
+fn row_binning(bins: usize, max_rows: usize) -> (usize, usize) {
+    let bins_per_row = ((bins + max_rows.max(1) - 1) / max_rows.max(1)).max(1);
+    (bins_per_row, (bins + bins_per_row - 1) / bins_per_row)
+}
+
+/// Gets the peak level of the bins of `column` drawn in `row`, counting down from the highest
+/// frequency. The bottom row covers fewer bins if they don't divide evenly.
+fn row_peak(column: &[f32], row: usize, bins_per_row: usize) -> f32 {
+    let high = column.len() - row * bins_per_row;
+    let low = high.saturating_sub(bins_per_row);
+    column[low..high].iter().cloned().fold(::std::f32::NAN, f32::max)
+}
+
+/// Draws a one pixel border just outside of a rectangle
+fn draw_border(image: &mut Image, x: usize, y: usize, width: usize, height: usize) {
+    image.fill_rect(x - 1, y - 1, width + 2, 1, BLACK);
+    image.fill_rect(x - 1, y + height, width + 2, 1, BLACK);
+    image.fill_rect(x - 1, y - 1, 1, height + 2, BLACK);
+    image.fill_rect(x + width, y - 1, 1, height + 2, BLACK);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use iq_converter::goertzel::FilterSettings;
+    use iq_converter::spectrum::frequency_bin;
+
+    use super::*;
+
+    const SAMP_RATE: f64 = 64e3;
+    const FFT_SIZE: usize = 64;
+
+    /// `frames` blocks of silence, with a tone at `freq` Hz in the block `pulse`
+    fn capture(frames: usize, pulse: usize, freq: f64) -> Vec<(f32, f32)> {
+        (0..frames * FFT_SIZE).map(|n| match n / FFT_SIZE == pulse {
+            true => {
+                let phase = 2.0 * PI * (freq / SAMP_RATE) as f32 * n as f32;
+                (0.5 * phase.cos(), 0.5 * phase.sin())
+            }
+            false => (0.0, 0.0),
+        }).collect()
+    }
+
+    #[test]
+    fn keeps_the_peak_of_the_frames_in_each_column() {
+        // 10 frames in at most 3 columns puts 4 frames in each, with 2 in the last
+        let input = capture(10, 5, 8e3);
+        let spectrogram = Spectrogram::compute(input.into_iter(), 10 * FFT_SIZE, SAMP_RATE,
+            FilterSettings::new(FFT_SIZE), 3);
+        assert_eq!(spectrogram.columns.len(), 3);
+        assert_eq!(spectrogram.column_duration, 4.0 * FFT_SIZE as f64 / SAMP_RATE);
+        assert_eq!(spectrogram.duration(), 12.0 * FFT_SIZE as f64 / SAMP_RATE);
+
+        // The pulse in the sixth frame shows at full power in the second column only
+        let bin = frequency_bin(8e3, FFT_SIZE, SAMP_RATE);
+        let peaks: Vec<_> = spectrogram.columns.iter().map(|x| x[bin]).collect();
+        assert!((peaks[1] - spectrum::to_db(0.25)).abs() < 0.1, "{:?}", peaks);
+        assert!(peaks[0] < -100.0 && peaks[2] < -100.0, "{:?}", peaks);
+    }
+
+    #[test]
+    fn uses_a_column_per_frame_when_there_are_few() {
+        let settings = FilterSettings::new(FFT_SIZE);
+        let spectrogram = Spectrogram::compute(capture(5, 0, 0.0).into_iter(), 5 * FFT_SIZE,
+            SAMP_RATE, settings, 1200);
+        assert_eq!(spectrogram.columns.len(), 5);
+        assert_eq!(spectrogram.column_duration, FFT_SIZE as f64 / SAMP_RATE);
+
+        let spectrogram = Spectrogram::compute(vec![].into_iter(), 0, SAMP_RATE, settings, 1200);
+        assert!(spectrogram.columns.is_empty());
+    }
+
+    #[test]
+    fn combines_bins_into_rows() {
+        assert_eq!(row_binning(1024, 1024), (1, 1024));
+        assert_eq!(row_binning(1024, 2000), (1, 1024));
+        assert_eq!(row_binning(1024, 300), (4, 256));
+        assert_eq!(row_binning(10, 4), (3, 4));
+        assert_eq!(row_binning(10, 0), (10, 1));
+
+        // Rows run from the highest frequency down, with the leftover bins in the bottom row
+        let column = [0.0, 1.0, 5.0, 2.0, 3.0, 4.0, 9.0, 6.0, 7.0, 8.0];
+        let rows: Vec<_> = (0..4).map(|row| row_peak(&column, row, 3)).collect();
+        assert_eq!(rows, vec![8.0, 9.0, 5.0, 0.0]);
+    }
+}
@@ -0,0 +1,99 @@
+//! A tool for rendering spectrograms of raw data from the HackRF as PNG images
+
+extern crate clap;
+extern crate iq_converter;
+extern crate spectrogram;
+
+use std::path::Path;
+
+use clap::{App, Arg};
+
+use iq_converter::IqConverter;
+use iq_converter::cli::{exit_with_error, parse_arg, read_capture};
+use iq_converter::goertzel::FilterSettings;
+use iq_converter::units::{parse_count, parse_level, parse_rate};
+use iq_converter::window::Window;
+
+use spectrogram::{Spectrogram, Style};
+use spectrogram::colormap::Colormap;
+
+fn main() {
+    let matches = App::new("Spectrogram Renderer")
+        .arg_from_usage("<input> 'The path to the input file.'")
+        .arg_from_usage("-o, --output [output] 'Name of output file. (default: spectrogram.png)'")
+        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set input file sample rate, e.g. 2e6, 2M or \
+            2MS/s (default: 2MS/s)'")
+        .arg_from_usage("-n, --fft_size [fft_size] 'Set the number of samples in each FFT, must be \
+            a power of two (default: 1024)'")
+        .arg_from_usage("--overlap [overlap] 'Set the fraction of each FFT block shared with the \
+            next, from 0 up to 0.99 (default: 0.5)'")
+        .arg_from_usage("-w, --window [window] 'Set the FFT window: rectangular, hann, hamming, \
+            blackman-harris, flat-top or kaiser[:<beta>] (default: blackman-harris)'")
+        .arg(Arg::from_usage("--min_db [min_db] 'Set the level drawn with the lowest colour \
+            (default: 10 dB below the noise floor)'").allow_hyphen_values(true))
+        .arg(Arg::from_usage("--max_db [max_db] 'Set the level drawn with the highest colour \
+            (default: the peak level)'").allow_hyphen_values(true))
+        .arg_from_usage("-c, --colormap [colormap] 'Set the colour map: viridis, inferno, jet or \
+            grey (default: viridis)'")
+        .arg_from_usage("--width [width] 'Set the maximum number of columns of the image, with \
+            each column showing the peak of the spectra it covers (default: 1200)'")
+        .arg_from_usage("--height [height] 'Set the maximum number of rows used for the frequency \
+            bins (default: 1024)'")
+        .get_matches();
+
+    let input_filename = matches.value_of("input").unwrap();
+    let output_filename = matches.value_of("output").unwrap_or("spectrogram.png");
+
+    let samp_rate = parse_arg(&matches, "samp_rate", parse_rate).unwrap_or(2e6);
+
+    let fft_size = parse_arg(&matches, "fft_size", parse_count).unwrap_or(1024);
+    if !fft_size.is_power_of_two() {
+        exit_with_error(&format!("FFT size must be a power of two (got {})", fft_size));
+    }
+
+    let parse_overlap = |x: &str| {
+        x.trim().parse::<f64>().map_err(|_| format!("Invalid overlap `{}`: expected a number", x))
+    };
+    let overlap = parse_arg(&matches, "overlap", parse_overlap).unwrap_or(0.5);
+    if !(overlap >= 0.0 && overlap <= 0.99) {
+        exit_with_error(&format!("Overlap must be between 0 and 0.99 (got {})", overlap));
+    }
+
+    let mut settings = FilterSettings::new(fft_size);
+    settings.hop = ((fft_size as f64 * (1.0 - overlap)).round() as usize).max(1);
+    if let Some(window) = matches.value_of("window") {
+        settings.window = window.parse::<Window>().unwrap_or_else(|e| exit_with_error(&e));
+    }
+
+    let colormap = parse_arg(&matches, "colormap", |x| x.parse::<Colormap>())
+        .unwrap_or(Colormap::default());
+    let width = parse_arg(&matches, "width", parse_count).unwrap_or(1200);
+    let max_rows = parse_arg(&matches, "height", parse_count).unwrap_or(1024);
+
+    let data = read_capture(Path::new(input_filename)).unwrap_or_else(|e| {
+        exit_with_error(&format!("Unable to read {}: {}", input_filename, e))
+    });
+    let samples = data.len() / 2;
+
+    let spectrogram = Spectrogram::compute(IqConverter::new(data), samples, samp_rate, settings,
+        width);
+    if spectrogram.columns.is_empty() {
+        exit_with_error(&format!("{} is shorter than a single FFT block", input_filename));
+    }
+
+    let (auto_min, auto_max) = spectrogram.auto_range();
+    let style = Style {
+        min_db: parse_arg(&matches, "min_db", parse_level).unwrap_or(auto_min),
+        max_db: parse_arg(&matches, "max_db", parse_level).unwrap_or(auto_max),
+        colormap: colormap,
+        max_rows: max_rows,
+    };
+
+    println!("Rendering {} columns from {} to {} dB", spectrogram.columns.len(), style.min_db,
+        style.max_db);
+
+    let image = spectrogram::render(&spectrogram, &style);
+    if let Err(e) = image.save_png(output_filename) {
+        exit_with_error(&format!("Unable to write {}: {}", output_filename, e));
+    }
+}
@@ -0,0 +1,389 @@
+//! Surveys of a capture for unknown collar frequencies.
+//!
+//! The capture is split into overlapping windowed blocks and the power spectrum of each block is
+//! computed. The spectra are averaged over the whole capture (Welch's method) to show the
+//! long-term shape of the band, and the power of each frequency bin is also followed over time to
+//! find pulses. A collar shows up as a narrow group of bins that pulse at a regular interval, while
+//! noise rarely produces pulses at all and wideband interference covers too many bins.
+
+extern crate iq_converter;
+
+use iq_converter::goertzel::FilterSettings;
+use iq_converter::pulse::{Pulse, PulseDetector, DEFAULT_THRESHOLD};
+use iq_converter::spectrum::{bin_frequency, to_db, Spectra};
+
+/// The default number of samples in each FFT block
+pub const DEFAULT_FFT_SIZE: usize = 1024;
+
+/// The default smallest number of pulses needed to report a frequency
+pub const DEFAULT_MIN_PULSES: usize = 3;
+
+/// The default widest group of bins (in Hz) that is treated as a narrowband signal
+pub const DEFAULT_MAX_BANDWIDTH: f64 = 10e3;
+
+/// How far (as a fraction of the pulse interval) an interval may differ from a whole number of
+/// pulse intervals and still count as regular, allowing for missed pulses
+const INTERVAL_TOLERANCE: f64 = 0.1;
+
+/// How long (in seconds) the noise floor of each bin is given to settle before pulses are counted.
+/// Until then a quiet start can make ordinary noise look like pulses.
+const SETTLE_TIME: f64 = 0.2;
+
+/// The fraction of intervals that must be regular for a frequency to count as periodic
+const MIN_REGULARITY: f64 = 0.5;
+
+/// How far (in dB) below the strongest bin of a group a bin may be and still count towards its
+/// bandwidth
+const BANDWIDTH_DB: f32 = 10.0;
+
+/// Settings controlling a survey
+#[derive(Copy, Clone, Debug)]
+pub struct Settings {
+    /// The sample rate of the capture
+    pub samp_rate: f64,
+
+    /// The FFT blocks. The block size must be a power of two.
+    pub fft: FilterSettings,
+
+    /// How far (in dB) above the noise floor of its bin a pulse must reach
+    pub threshold: f32,
+
+    /// The smallest number of pulses needed to report a frequency
+    pub min_pulses: usize,
+
+    /// The widest group of bins (in Hz) that is treated as a narrowband signal. This is always
+    /// allowed to be at least a few bins wide, since the window spreads even a pure tone.
+    pub max_bandwidth: f64,
+}
+
+impl Settings {
+    /// Creates the default settings for a capture sampled at `samp_rate`
+    pub fn new(samp_rate: f64) -> Settings {
+        let mut fft = FilterSettings::new(DEFAULT_FFT_SIZE);
+        fft.hop = DEFAULT_FFT_SIZE / 2;
+
+        Settings {
+            samp_rate: samp_rate,
+            fft: fft,
+            threshold: DEFAULT_THRESHOLD,
+            min_pulses: DEFAULT_MIN_PULSES,
+            max_bandwidth: DEFAULT_MAX_BANDWIDTH,
+        }
+    }
+
+    /// The width of each frequency bin in Hz
+    pub fn bin_width(&self) -> f64 {
+        self.samp_rate / self.fft.block_size as f64
+    }
+
+    /// The number of spectra computed per second
+    pub fn frame_rate(&self) -> f64 {
+        self.fft.output_rate(self.samp_rate)
+    }
+}
+
+/// A frequency that pulsed during the capture
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    /// The estimated frequency of the carrier in Hz, as an offset from the centre frequency
+    pub freq: f64,
+
+    /// The mean level (in dB) of the pulses above the noise floor
+    pub snr: f32,
+
+    /// The noise floor (in dB) of the frequency bin
+    pub noise_floor: f32,
+
+    /// The number of pulses found
+    pub pulses: usize,
+
+    /// The mean length of the pulses in seconds
+    pub width: f64,
+
+    /// The typical time between the start of consecutive pulses in seconds, if there were at least
+    /// two pulses
+    pub interval: Option<f64>,
+
+    /// The fraction of the times between pulses that were a whole number of intervals
+    pub regularity: f64,
+
+    /// The width (in Hz) of the group of bins that the pulses were found in
+    pub bandwidth: f64,
+}
+
+impl Candidate {
+    /// The pulse rate in pulses per minute
+    pub fn rate(&self) -> Option<f64> {
+        self.interval.map(|x| 60.0 / x)
+    }
+
+    /// Whether the pulses arrived at a regular interval, as a collar's do
+    pub fn is_periodic(&self) -> bool {
+        self.interval.is_some() && self.regularity >= MIN_REGULARITY
+    }
+}
+
+/// The results of a survey
+pub struct Survey {
+    /// The average power (in dB) of each frequency bin, ordered from the lowest frequency to the
+    /// highest
+    pub spectrum: Vec<f32>,
+
+    /// The median of the average spectrum in dB, which is usually the noise floor
+    pub noise_floor: f32,
+
+    /// The number of spectra averaged
+    pub frames: usize,
+
+    /// Every narrowband frequency that pulsed at least the minimum number of times, strongest
+    /// first, whether or not the pulses were periodic
+    pub candidates: Vec<Candidate>,
+}
+
+impl Survey {
+    /// The candidates whose pulses were periodic, strongest first
+    pub fn periodic(&self) -> Vec<&Candidate> {
+        self.candidates.iter().filter(|x| x.is_periodic()).collect()
+    }
+}
+
+/// The pulses found in a single frequency bin
+struct Bin {
+    detector: PulseDetector,
+    pulses: Vec<Pulse>,
+}
+
+impl Bin {
+    /// The mean level (in dB) of the pulses above the noise floor
+    fn snr(&self) -> f32 {
+        self.pulses.iter().map(|x| x.snr).sum::<f32>() / self.pulses.len().max(1) as f32
+    }
+}
+
+/// Surveys an I/Q stream for pulsed narrowband signals. Panics if the FFT block size is not a power
+/// of two.
+pub fn survey<I>(input: I, settings: &Settings) -> Survey
+    where I: Iterator<Item=(f32, f32)>
+{
+    let size = settings.fft.block_size;
+    let frame_rate = settings.frame_rate();
+
+    let mut bins: Vec<_> = (0..size).map(|_| {
+        Bin { detector: PulseDetector::new(frame_rate, settings.threshold), pulses: vec![] }
+    }).collect();
+    let mut total = vec![0.0f64; size];
+    let mut frames = 0;
+    let settled = (SETTLE_TIME * frame_rate) as usize;
+
+    for spectrum in Spectra::new(input, settings.fft) {
+        for ((bin, sum), &power) in bins.iter_mut().zip(&mut total).zip(&spectrum) {
+            *sum += power as f64;
+            match bin.detector.process(power.sqrt()) {
+                Some(pulse) if pulse.start >= settled => bin.pulses.push(pulse),
+                _ => {}
+            }
+        }
+        frames += 1;
+    }
+    for bin in &mut bins {
+        match bin.detector.finish() {
+            Some(pulse) if pulse.start >= settled => bin.pulses.push(pulse),
+            _ => {}
+        }
+    }
+
+    let averaged = frames.max(1) as f64;
+    let spectrum: Vec<_> = total.iter().map(|&x| to_db((x / averaged) as f32)).collect();
+    let mut sorted = spectrum.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let noise_floor = sorted.get(sorted.len() / 2).cloned().unwrap_or(0.0);
+
+    let candidates = find_candidates(&bins, &spectrum, settings);
+    Survey {
+        spectrum: spectrum,
+        noise_floor: noise_floor,
+        frames: frames,
+        candidates: candidates,
+    }
+}
+
+/// Groups neighbouring bins with pulses into candidates, starting from the strongest bin
+fn find_candidates(bins: &[Bin], spectrum: &[f32], settings: &Settings) -> Vec<Candidate> {
+    let size = bins.len();
+    let bin_width = settings.bin_width();
+    let max_bins = ((settings.max_bandwidth / bin_width).round() as usize).max(4);
+
+    let mut order: Vec<_> = (0..size).filter(|&i| bins[i].pulses.len() >= settings.min_pulses)
+        .collect();
+    order.sort_by(|&a, &b| bins[b].snr().partial_cmp(&bins[a].snr()).unwrap());
+
+    let mut used = vec![false; size];
+    let mut candidates = vec![];
+    for peak in order {
+        if used[peak] {
+            continue;
+        }
+
+        // Find the extent of the group of bins around the peak that pulsed at a similar level
+        let similar = |i: usize| {
+            bins[i].pulses.len() >= settings.min_pulses &&
+                bins[i].snr() >= bins[peak].snr() - BANDWIDTH_DB
+        };
+        let mut low = peak;
+        while low > 0 && similar(low - 1) {
+            low -= 1;
+        }
+        let mut high = peak;
+        while high + 1 < size && similar(high + 1) {
+            high += 1;
+        }
+
+        // Leakage from a strong pulse can reach well beyond the group, so claim those bins too
+        let reach = max_bins / 2;
+        for flag in &mut used[low.saturating_sub(reach)..(high + reach + 1).min(size)] {
+            *flag = true;
+        }
+
+        let width = high - low + 1;
+        if width > max_bins {
+            continue;
+        }
+
+        let bin = &bins[peak];
+        let frame_rate = settings.frame_rate();
+        let starts: Vec<_> = bin.pulses.iter().map(|x| x.start as f64 / frame_rate).collect();
+        let (interval, regularity) = match periodicity(&starts) {
+            Some((interval, regularity)) => (Some(interval), regularity),
+            None => (None, 0.0),
+        };
+
+        candidates.push(Candidate {
+            freq: refine_frequency(spectrum, peak, settings),
+            snr: bin.snr(),
+            noise_floor: bin.detector.noise_floor_db().unwrap_or(0.0),
+            pulses: bin.pulses.len(),
+            width: bin.pulses.iter().map(|x| (x.end - x.start) as f64).sum::<f64>()
+                / bin.pulses.len() as f64 / frame_rate,
+            interval: interval,
+            regularity: regularity,
+            bandwidth: width as f64 * bin_width,
+        });
+    }
+
+    candidates
+}
+
+/// Estimates the frequency of the peak at `bin` to a fraction of a bin by fitting a parabola to the
+/// average power (in dB) of it and its neighbours
+fn refine_frequency(spectrum: &[f32], bin: usize, settings: &Settings) -> f64 {
+    let centre = bin_frequency(bin, spectrum.len(), settings.samp_rate);
+    if bin == 0 || bin + 1 >= spectrum.len() {
+        return centre;
+    }
+
+    let (a, b, c) = (spectrum[bin - 1] as f64, spectrum[bin] as f64, spectrum[bin + 1] as f64);
+    let curvature = a - 2.0 * b + c;
+    if !(curvature < 0.0) {
+        return centre;
+    }
+    let offset = (0.5 * (a - c) / curvature).max(-0.5).min(0.5);
+    centre + offset * settings.bin_width()
+}
+
+/// Estimates the interval between pulses starting at `starts` (in seconds) as the median time
+/// between consecutive pulses, along with the fraction of times between pulses that are close to a
+/// whole number of intervals
+pub fn periodicity(starts: &[f64]) -> Option<(f64, f64)> {
+    if starts.len() < 2 {
+        return None;
+    }
+
+    let mut intervals: Vec<_> = starts.windows(2).map(|x| x[1] - x[0]).collect();
+    intervals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = intervals[intervals.len() / 2];
+    if !(median > 0.0) {
+        return None;
+    }
+
+    let regular = intervals.iter().filter(|&&x| {
+        let multiple = x / median;
+        multiple.round() >= 1.0 && (multiple - multiple.round()).abs() <= INTERVAL_TOLERANCE
+    }).count();
+
+    // Refine the interval from the regular single intervals
+    let singles: Vec<_> = intervals.iter().cloned()
+        .filter(|&x| (x / median - 1.0).abs() <= INTERVAL_TOLERANCE).collect();
+    let interval = singles.iter().sum::<f64>() / singles.len() as f64;
+
+    Some((interval, regular as f64 / intervals.len() as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate beacon_generator;
+
+    use self::beacon_generator::Generator;
+    use self::beacon_generator::Settings as CaptureSettings;
+    use self::beacon_generator::beacon::Beacon;
+
+    use super::*;
+
+    const SAMP_RATE: f64 = 1e6;
+
+    fn settings() -> Settings {
+        let mut settings = Settings::new(SAMP_RATE);
+        settings.fft = FilterSettings::new(256);
+        settings.fft.hop = 128;
+        settings
+    }
+
+    #[test]
+    fn finds_periodic_beacons() {
+        let mut first = Beacon::new(130.5e3);
+        first.interval = 0.25;
+        first.start = 0.1;
+        let mut second = Beacon::new(-250e3);
+        second.interval = 0.4;
+        second.amplitude = 0.1;
+        second.width = 0.03;
+        let mut capture = CaptureSettings::new(SAMP_RATE, 3.0);
+        capture.noise = 0.02;
+
+        let survey = survey(Generator::new(&[first, second], &capture), &settings());
+        let found = survey.periodic();
+        assert_eq!(found.len(), 2, "{:?}", survey.candidates);
+
+        // Frequencies are resolved to a fraction of the 3.9 kHz bins
+        assert!((found[0].freq - 130.5e3).abs() < 1e3, "found {} Hz", found[0].freq);
+        assert!((found[0].interval.unwrap() - 0.25).abs() < 0.005);
+        assert!((found[0].width - 0.02).abs() < 0.003);
+        assert!(found[0].snr > found[1].snr);
+
+        assert!((found[1].freq + 250e3).abs() < 1e3, "found {} Hz", found[1].freq);
+        assert!((found[1].rate().unwrap() - 150.0).abs() < 3.0);
+    }
+
+    #[test]
+    fn ignores_noise_and_continuous_tones() {
+        let mut tone = Beacon::new(200e3);
+        tone.width = 3.0;
+        tone.interval = 3.0;
+        let mut capture = CaptureSettings::new(SAMP_RATE, 2.0);
+        capture.noise = 0.05;
+
+        let survey = survey(Generator::new(&[tone], &capture), &settings());
+        assert!(survey.periodic().is_empty(), "{:?}", survey.candidates);
+
+        // The tone still stands out in the average spectrum
+        let bin = iq_converter::spectrum::frequency_bin(200e3, 256, SAMP_RATE);
+        assert!(survey.spectrum[bin] > survey.noise_floor + 30.0);
+    }
+
+    #[test]
+    fn measures_periodicity_with_missed_pulses() {
+        let (interval, regularity) = periodicity(&[0.0, 1.0, 2.01, 4.0, 5.0, 6.5]).unwrap();
+        assert!((interval - 1.0).abs() < 0.01);
+        assert!((regularity - 0.8).abs() < 1e-9);
+        assert_eq!(periodicity(&[1.0]), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! A tool for surveying a capture for pulsed collar frequencies
+
+extern crate clap;
+extern crate iq_converter;
+extern crate survey;
+
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::Path;
+
+use clap::App;
+
+use iq_converter::IqConverter;
+use iq_converter::cli::{exit_with_error, parse_arg, read_capture};
+use iq_converter::spectrum::bin_frequency;
+use iq_converter::units::{format_frequency, parse_count, parse_frequency, parse_level};
+use iq_converter::units::parse_rate;
+use iq_converter::window::Window;
+
+use survey::{survey, Candidate, Settings, Survey, DEFAULT_FFT_SIZE};
+
+fn main() {
+    let matches = App::new("Band Survey")
+        .arg_from_usage("<input> 'The path to the capture'")
+        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set input file sample rate, e.g. 2e6, 2M or \
+            2MS/s (default: 2MS/s)'")
+        .arg_from_usage("-n, --fft_size [fft_size] 'Set the number of samples in each FFT, must be \
+            a power of two (default: 1024)'")
+        .arg_from_usage("--overlap [overlap] 'Set the fraction of each FFT block shared with the \
+            next, from 0 up to 0.99 (default: 0.5)'")
+        .arg_from_usage("-w, --window [window] 'Set the FFT window: rectangular, hann, hamming, \
+            blackman-harris, flat-top or kaiser[:<beta>] (default: blackman-harris)'")
+        .arg_from_usage("--pulse_threshold [threshold] 'Detect pulses where a frequency bin is at \
+            least this many dB above its noise floor (default: 10)'")
+        .arg_from_usage("--min_pulses [min_pulses] 'Set the smallest number of pulses needed to \
+            report a frequency (default: 3)'")
+        .arg_from_usage("--max_bandwidth [bandwidth] 'Set the widest signal treated as \
+            narrowband, e.g. 10k (default: 10 kHz)'")
+        .arg_from_usage("-a, --all 'Also list frequencies whose pulses were not periodic'")
+        .arg_from_usage("--csv [csv] 'Name of a CSV file to write the candidate frequencies to'")
+        .arg_from_usage("--spectrum [spectrum] 'Name of a CSV file to write the average spectrum \
+            to'")
+        .get_matches();
+
+    let input = matches.value_of("input").unwrap();
+
+    let mut settings = Settings::new(parse_arg(&matches, "samp_rate", parse_rate).unwrap_or(2e6));
+
+    let fft_size = parse_arg(&matches, "fft_size", parse_count).unwrap_or(DEFAULT_FFT_SIZE);
+    if !fft_size.is_power_of_two() {
+        exit_with_error(&format!("FFT size must be a power of two (got {})", fft_size));
+    }
+    let parse_overlap = |x: &str| {
+        x.trim().parse::<f64>().map_err(|_| format!("Invalid overlap `{}`: expected a number", x))
+    };
+    let overlap = parse_arg(&matches, "overlap", parse_overlap).unwrap_or(0.5);
+    if !(overlap >= 0.0 && overlap <= 0.99) {
+        exit_with_error(&format!("Overlap must be between 0 and 0.99 (got {})", overlap));
+    }
+    settings.fft.block_size = fft_size;
+    settings.fft.hop = ((fft_size as f64 * (1.0 - overlap)).round() as usize).max(1);
+    if let Some(window) = matches.value_of("window") {
+        settings.fft.window = window.parse::<Window>().unwrap_or_else(|e| exit_with_error(&e));
+    }
+
+    if let Some(threshold) = parse_arg(&matches, "pulse_threshold", parse_level) {
+        settings.threshold = threshold;
+    }
+    if let Some(min_pulses) = parse_arg(&matches, "min_pulses", parse_count) {
+        settings.min_pulses = min_pulses;
+    }
+    if let Some(bandwidth) = parse_arg(&matches, "max_bandwidth", parse_frequency) {
+        settings.max_bandwidth = bandwidth;
+    }
+
+    let data = read_capture(Path::new(input))
+        .unwrap_or_else(|e| exit_with_error(&format!("Unable to read {}: {}", input, e)));
+    let duration = (data.len() / 2) as f64 / settings.samp_rate;
+
+    let result = survey(IqConverter::new(data), &settings);
+    if result.frames == 0 {
+        exit_with_error(&format!("{} is shorter than a single FFT block", input));
+    }
+
+    println!("Surveyed {:.1} s from {} to {} in {} bins, average noise floor {:.1} dB", duration,
+        format_frequency(-settings.samp_rate / 2.0), format_frequency(settings.samp_rate / 2.0),
+        format_frequency(settings.bin_width()), result.noise_floor);
+
+    let candidates: Vec<_> = match matches.is_present("all") {
+        true => result.candidates.iter().collect(),
+        false => result.periodic(),
+    };
+    print_candidates(&candidates);
+
+    if let Some(path) = matches.value_of("csv") {
+        if let Err(e) = write_candidates(Path::new(path), &candidates) {
+            exit_with_error(&format!("Unable to write {}: {}", path, e));
+        }
+    }
+    if let Some(path) = matches.value_of("spectrum") {
+        if let Err(e) = write_spectrum(Path::new(path), &result, &settings) {
+            exit_with_error(&format!("Unable to write {}: {}", path, e));
+        }
+    }
+}
+
+fn print_candidates(candidates: &[&Candidate]) {
+    if candidates.is_empty() {
+        println!("No pulsed frequencies found");
+        return;
+    }
+
+    println!("{:>12}  {:>8}  {:>6}  {:>8}  {:>9}  {:>8}  {:>10}", "Frequency", "SNR", "Pulses",
+        "Width", "Interval", "Rate", "Regularity");
+    for x in candidates {
+        let interval = x.interval.map_or("-".into(), |x| format!("{:.3} s", x));
+        let rate = x.rate().map_or("-".into(), |x| format!("{:.1} ppm", x));
+        println!("{:>12}  {:>5.1} dB  {:>6}  {:>5.1} ms  {:>9}  {:>8}  {:>9.0}%",
+            format_frequency(x.freq.round()), x.snr, x.pulses, x.width * 1000.0, interval, rate,
+            x.regularity * 100.0);
+    }
+    println!("Pass a frequency to signal_to_wav as e.g. --filter {}, or enter it as viscope's \
+        filter frequency", candidates[0].freq.round());
+}
+
+/// Writes the candidate frequencies to a CSV file
+fn write_candidates(path: &Path, candidates: &[&Candidate]) -> io::Result<()> {
+    let mut output = BufWriter::new(try!(File::create(path)));
+    try!(writeln!(output, "frequency,snr,noise_floor,pulses,width,interval,rate,regularity,\
+        bandwidth"));
+
+    for x in candidates {
+        try!(writeln!(output, "{},{},{},{},{},{},{},{},{}", x.freq.round(), x.snr, x.noise_floor,
+            x.pulses, x.width, x.interval.map_or(String::new(), |x| x.to_string()),
+            x.rate().map_or(String::new(), |x| x.to_string()), x.regularity, x.bandwidth));
+    }
+
+    output.flush()
+}
+
+/// Writes the frequency and average power of each bin to a CSV file
+fn write_spectrum(path: &Path, survey: &Survey, settings: &Settings) -> io::Result<()> {
+    let mut output = BufWriter::new(try!(File::create(path)));
+    try!(writeln!(output, "frequency,power"));
+
+    let size = survey.spectrum.len();
+    for (bin, power) in survey.spectrum.iter().enumerate() {
+        try!(writeln!(output, "{},{}", bin_frequency(bin, size, settings.samp_rate), power));
+    }
+
+    output.flush()
+}
@@ -0,0 +1,28 @@
+#version 140
+
+uniform sampler2D spectrogram;
+uniform float filled;
+uniform float low;
+uniform float high;
+
+in vec2 vTexCoord;
+
+out vec4 f_color;
+
+void main() {
+    // Times outside the capture, or not computed yet, are left blank
+    if (vTexCoord.y < 0.0 || vTexCoord.y >= filled) {
+        f_color = vec4(1.0, 1.0, 1.0, 1.0);
+        return;
+    }
+
+    float level = clamp((texture(spectrogram, vTexCoord).r - low) / (high - low), 0.0, 1.0);
+
+    // Dark blue for the weakest powers, through red, to yellow for the strongest
+    vec3 cold = vec3(0.0, 0.0, 0.3);
+    vec3 warm = vec3(0.85, 0.1, 0.1);
+    vec3 hot = vec3(1.0, 1.0, 0.0);
+    vec3 color = level < 0.5 ? mix(cold, warm, level * 2.0) : mix(warm, hot, level * 2.0 - 1.0);
+
+    f_color = vec4(color, 1.0);
+}
\ No newline at end of file
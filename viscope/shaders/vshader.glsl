@@ -3,6 +3,8 @@
 uniform float dt;
 uniform float x_scale;
 uniform float y_scale;
+uniform float y_offset;
+uniform vec3 color;
 
 in vec2 position;
 
@@ -10,9 +12,9 @@ out vec3 vColor;
 
 void main() {
     float x = (position.x - dt) * x_scale - 1.0;
-    float y = position.y * y_scale;
+    float y = position.y * y_scale + y_offset;
 
     gl_Position = vec4(x, y, 0.0, 1.0);
 
-    vColor = vec3(0.0, 0.0, 0.0);
+    vColor = color;
 }
\ No newline at end of file
@@ -0,0 +1,15 @@
+#version 140
+
+uniform float start;
+uniform float span;
+
+in vec2 position;
+
+out vec2 vTexCoord;
+
+void main() {
+    // The corners of the panel are at 0 and 1, with time increasing down the panel
+    gl_Position = vec4(position.x * 2.0 - 1.0, 1.0 - position.y * 2.0, 0.0, 1.0);
+
+    vTexCoord = vec2(position.x, start + position.y * span);
+}
\ No newline at end of file
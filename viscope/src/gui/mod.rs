@@ -1,19 +1,241 @@
+use std::f32;
+use std::iter::repeat;
+
 use imgui::*;
 
-use scope_app::{PlaybackState, SignalType};
+use iq_converter::goertzel::{FilterSettings, TrackingSettings, MAX_TRACK_STEPS};
+use iq_converter::units::{format_duration, format_frequency, parse_frequency};
+use iq_converter::window::{Window, DEFAULT_KAISER_BETA};
+
+use scope_app::{Channel, PlaybackState, SignalType, CHANNELS, SPECTRUM_HEIGHT};
+use scope_app::{MIN_X_ZOOM, MAX_X_ZOOM};
+use scope_app::cursors::Readout;
+use scope_app::grid::{AmplitudeScale, AxisLabel};
+use scope_app::loader::CaptureSettings;
+use scope_app::spectrum::{SpectrumSettings, MAX_AVERAGES, MAX_FFT_SIZE};
+use scope_app::trigger::{Condition, Sweep, TriggerSettings};
+use scope_app::waterfall::DEFAULT_DYNAMIC_RANGE;
+
+/// The colour of text drawn over the scope
+const LABEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// The smallest FFT size that can be selected for the spectrum
+const MIN_FFT_SIZE: usize = 256;
+
+/// The size (in bytes) of the buffer edited by the filter frequency input
+const FREQ_TEXT_SIZE: usize = 32;
 
 pub struct Gui {
     pub playback: PlaybackState,
     pub signal_type: SignalType,
-    filter_freq: f32,
+    pub capture: CaptureSettings,
+    pub tracked_freq: Option<f32>,
+    pub length: f32,
+
+    /// The fraction of the signal being loaded that has been processed, while a load is running
+    pub progress: Option<f32>,
+
+    /// Set when the user asks to cancel the running load
+    pub cancel: bool,
+
+    /// The reason the last load failed, until the user dismisses it
+    pub error: Option<String>,
+
+    /// The frequency of the Goertzel filter, which can also be set by clicking on the spectrum
+    pub filter_freq: f32,
+
+    /// Whether the spectrum panel is shown
+    pub show_spectrum: bool,
+
+    /// The power (in dB) at the bottom and top of the spectrum panel, while it is shown
+    pub spectrum_range: Option<(f32, f32)>,
+
+    /// Whether the waterfall panel is shown
+    pub show_waterfall: bool,
+
+    /// The fraction of the waterfall that has been computed, while it is shown
+    pub waterfall_progress: Option<f32>,
+
+    /// Set to 1 or -1 when the user asks to move to the next or previous trigger
+    pub trigger_step: i32,
+
+    /// The number of triggers found in the signal, while triggering is enabled
+    pub trigger_count: Option<usize>,
+
+    /// The measurements from the cursors, while they are shown
+    pub cursor_readout: Option<Readout>,
+
+    /// The labels of the scope's grid lines
+    pub axis_labels: Vec<AxisLabel>,
+    samp_rate: f32,
+    display_rate: f32,
+
+    /// The text of the filter frequency input, with the frequency it was last written for and the
+    /// reason it could not be parsed
+    filter_freq_text: String,
+    filter_freq_text_value: f32,
+    filter_freq_error: Option<String>,
+    window_index: i32,
+    kaiser_beta: f32,
+    block_size: i32,
+    hop: i32,
+    track: bool,
+    track_band: f32,
+    track_step: f32,
+    spectrum_window_index: i32,
+    spectrum_size_index: i32,
+    spectrum_averages: i32,
+    waterfall_range: f32,
+    trigger: bool,
+    trigger_channel_index: i32,
+    trigger_condition_index: i32,
+    trigger_level: f32,
+    trigger_width: f32,
+    trigger_sweep_index: i32,
+    trigger_position: f32,
+    cursors: bool,
+    cursor_channel_index: i32,
+    amplitude_scale_index: i32,
 }
 
 impl Gui {
-    pub fn init() -> Gui {
+    pub fn init(capture: CaptureSettings, block_size: usize) -> Gui {
         Gui {
             playback: PlaybackState::default(),
             signal_type: SignalType::Raw,
+            capture: capture,
+            tracked_freq: None,
+            length: 0.0,
+            progress: None,
+            cancel: false,
+            error: None,
             filter_freq: 130000.0,
+            show_spectrum: false,
+            spectrum_range: None,
+            show_waterfall: false,
+            waterfall_progress: None,
+            trigger_step: 0,
+            trigger_count: None,
+            cursor_readout: None,
+            axis_labels: vec![],
+            samp_rate: capture.samp_rate as f32,
+            display_rate: capture.display_rate as f32,
+            filter_freq_text: String::new(),
+            filter_freq_text_value: f32::NAN,
+            filter_freq_error: None,
+            window_index: 3,
+            kaiser_beta: DEFAULT_KAISER_BETA,
+            block_size: block_size as i32,
+            hop: block_size as i32,
+            track: false,
+            track_band: 500.0,
+            track_step: 50.0,
+            spectrum_window_index: 3,
+            spectrum_size_index: 2,
+            spectrum_averages: 4,
+            waterfall_range: DEFAULT_DYNAMIC_RANGE,
+            trigger: false,
+            trigger_channel_index: 2,
+            trigger_condition_index: 0,
+            trigger_level: 0.5,
+            trigger_width: 1.0,
+            trigger_sweep_index: 0,
+            trigger_position: 0.1,
+            cursors: false,
+            cursor_channel_index: 2,
+            amplitude_scale_index: 0,
+        }
+    }
+
+    /// Gets the filter settings currently selected in the GUI
+    fn filter_settings(&self) -> FilterSettings {
+        FilterSettings {
+            window: selected_window(self.window_index, self.kaiser_beta),
+            block_size: self.block_size as usize,
+            hop: self.hop as usize,
+        }
+    }
+
+    /// Gets the capture settings currently entered in the GUI
+    fn capture_settings(&self) -> CaptureSettings {
+        CaptureSettings {
+            samp_rate: self.samp_rate as f64,
+            display_rate: self.display_rate as f64,
+        }
+    }
+
+    /// Gets the spectrum settings currently selected in the GUI, if the spectrum is shown
+    pub fn spectrum_settings(&self) -> Option<SpectrumSettings> {
+        match self.show_spectrum {
+            true => Some(SpectrumSettings {
+                window: selected_window(self.spectrum_window_index, self.kaiser_beta),
+                size: MIN_FFT_SIZE << self.spectrum_size_index,
+                averages: self.spectrum_averages as usize,
+            }),
+            false => None,
+        }
+    }
+
+    /// Gets the range (in dB) of powers shown in the waterfall, if the waterfall is shown
+    pub fn waterfall_range(&self) -> Option<f32> {
+        match self.show_waterfall {
+            true => Some(self.waterfall_range),
+            false => None,
+        }
+    }
+
+    /// Gets the trigger settings currently selected in the GUI, if triggering is enabled
+    pub fn trigger_settings(&self) -> Option<TriggerSettings> {
+        // The pulse width is entered in milliseconds
+        let width = self.trigger_width / 1000.0;
+        let condition = match self.trigger_condition_index {
+            1 => Condition::Falling,
+            2 => Condition::WiderThan(width),
+            3 => Condition::NarrowerThan(width),
+            _ => Condition::Rising,
+        };
+        let sweep = match self.trigger_sweep_index {
+            1 => Sweep::Normal,
+            2 => Sweep::Single,
+            _ => Sweep::Auto,
+        };
+
+        match self.trigger {
+            true => Some(TriggerSettings {
+                channel: CHANNELS[self.trigger_channel_index as usize],
+                condition: condition,
+                level: self.trigger_level,
+                sweep: sweep,
+                position: self.trigger_position,
+            }),
+            false => None,
+        }
+    }
+
+    /// Gets how the amplitude axis is labelled
+    pub fn amplitude_scale(&self) -> AmplitudeScale {
+        match self.amplitude_scale_index {
+            1 => AmplitudeScale::Dbfs,
+            _ => AmplitudeScale::Linear,
+        }
+    }
+
+    /// Gets the channel measured by the cursors, if they are shown
+    pub fn cursor_channel(&self) -> Option<Channel> {
+        match self.cursors {
+            true => Some(CHANNELS[self.cursor_channel_index as usize]),
+            false => None,
+        }
+    }
+
+    /// Gets the drift tracking settings currently selected in the GUI
+    fn tracking_settings(&self) -> Option<TrackingSettings> {
+        match self.track {
+            true => Some(TrackingSettings {
+                band: self.track_band,
+                step: self.track_step,
+            }),
+            false => None,
         }
     }
 
@@ -21,37 +243,340 @@ impl Gui {
         ui.window(im_str!("Playback"))
             .movable(true)
             .size((500.0, 100.0), ImGuiSetCond_FirstUseEver)
-            .build(|| draw_window(self, ui))
+            .build(|| draw_window(self, ui));
+
+        draw_scope_axes(ui, &self.axis_labels);
+
+        if let Some(range) = self.spectrum_range {
+            draw_spectrum_axes(ui, self.capture.samp_rate, range);
+        }
+
+        if let Some(readout) = self.cursor_readout {
+            ui.window(im_str!("Cursors"))
+                .always_auto_resize(true)
+                .build(|| draw_readout(ui, readout));
+        }
+
+        if let Some(error) = self.error.clone() {
+            let mut opened = true;
+            ui.window(im_str!("Error"))
+                .opened(&mut opened)
+                .always_auto_resize(true)
+                .build(|| ui.text(im_str!("{}", error)));
+            if !opened {
+                self.error = None;
+            }
+        }
     }
 }
 
 fn draw_window(gui: &mut Gui, ui: &Ui) {
     ui.slider_float(im_str!("Playback Speed"), &mut gui.playback.speed, 0.0, 2.0).build();
     ui.slider_float(im_str!("Playback Offset"), &mut gui.playback.offset, 0.0, 1.0).build();
+    ui.text(im_str!("Time: {:.3} s of {:.3} s", gui.playback.offset * gui.length, gui.length));
 
     ui.separator();
 
     ui.slider_float(im_str!("Vertical Zoom"), &mut gui.playback.y_zoom, -10.0, 10.0).build();
-    ui.slider_float(im_str!("Horizontal Zoom"), &mut gui.playback.x_zoom, -10.0, 20.0).build();
+    ui.slider_float(im_str!("Horizontal Zoom"), &mut gui.playback.x_zoom, MIN_X_ZOOM, MAX_X_ZOOM)
+        .build();
+    ui.combo(im_str!("Amplitude axis"), &mut gui.amplitude_scale_index, &[im_str!("Linear"),
+        im_str!("dBFS")], -1);
 
     ui.separator();
 
+    // The filtered signal only has a magnitude trace, so the other channels only apply to the raw
+    // signal
+    for &channel in CHANNELS.iter() {
+        let trace = &mut gui.playback.traces[channel as usize];
+        ui.checkbox(im_str!("{}", channel.name()), &mut trace.visible);
+        if trace.visible {
+            ui.same_line(100.0);
+            ui.slider_float(im_str!("Offset##{}", channel.name()), &mut trace.offset, -1.0, 1.0)
+                .build();
+        }
+    }
+
+    ui.separator();
+
+    draw_trigger_controls(gui, ui);
+
+    ui.separator();
+
+    ui.checkbox(im_str!("Cursors"), &mut gui.cursors);
+    if gui.cursors {
+        let channels = channel_names();
+        ui.combo(im_str!("Cursor channel"), &mut gui.cursor_channel_index, &channels, -1);
+        ui.text(im_str!("Drag the cursors in the scope to measure the signal"));
+    }
+
+    ui.separator();
+
+    if let Some(progress) = gui.progress {
+        const BAR_WIDTH: usize = 30;
+        let filled = ((progress * BAR_WIDTH as f32) as usize).min(BAR_WIDTH);
+        ui.text(im_str!("Loading [{}{}] {:.0}%", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled),
+            progress * 100.0));
+        ui.same_line(0.0);
+        if ui.small_button(im_str!("Cancel")) {
+            gui.cancel = true;
+        }
+    }
+
+    // Changes to the capture settings only take effect when the signal is next loaded
+    ui.input_float(im_str!("Sample rate (Hz)"), &mut gui.samp_rate).build();
+    ui.input_float(im_str!("Display rate (Hz)"), &mut gui.display_rate).build();
+    if gui.samp_rate < 1.0 {
+        gui.samp_rate = 1.0;
+    }
+    if gui.display_rate < 1.0 {
+        gui.display_rate = 1.0;
+    }
+    ui.text(im_str!("Raw signal resampled to {}",
+        format_frequency(gui.display_rate.min(gui.samp_rate) as f64)));
+
     if ui.small_button(im_str!("Raw signal")) {
         gui.signal_type = SignalType::Raw;
+        gui.capture = gui.capture_settings();
     }
 
     ui.same_line(100.0);
 
     if ui.small_button(im_str!("Filtered signal")) {
-        gui.signal_type = SignalType::Filtered(gui.filter_freq as i64)
+        gui.signal_type = SignalType::Filtered(gui.filter_freq as i64, gui.filter_settings(),
+            gui.tracking_settings());
+        gui.capture = gui.capture_settings();
+    }
+
+    // The filter can only be placed within the band captured by the HackRF
+    let nyquist = gui.samp_rate / 2.0;
+    gui.filter_freq = gui.filter_freq.max(-nyquist).min(nyquist);
+
+    // Rewrite the text when the frequency is changed elsewhere, e.g. by clicking on the spectrum
+    if gui.filter_freq != gui.filter_freq_text_value {
+        gui.filter_freq_text = text_buffer(&format_frequency(gui.filter_freq as f64),
+            FREQ_TEXT_SIZE);
+        gui.filter_freq_text_value = gui.filter_freq;
+        gui.filter_freq_error = None;
+    }
+    if ui.input_text(im_str!("Filter freq"), &mut gui.filter_freq_text).build() {
+        match parse_frequency(buffer_text(&gui.filter_freq_text)) {
+            Ok(freq) => {
+                gui.filter_freq = (freq as f32).max(-nyquist).min(nyquist);
+                gui.filter_freq_text_value = gui.filter_freq;
+                gui.filter_freq_error = None;
+            }
+            Err(e) => gui.filter_freq_error = Some(e),
+        }
+    }
+    match gui.filter_freq_error {
+        Some(ref error) => ui.text(im_str!("{}", error)),
+        None => ui.text(im_str!("Filter at {}", format_frequency(gui.filter_freq as f64))),
+    }
+
+    ui.combo(im_str!("Window"), &mut gui.window_index, &window_names(), -1);
+
+    if gui.window_index == 5 || (gui.show_spectrum && gui.spectrum_window_index == 5) {
+        ui.input_float(im_str!("Kaiser beta"), &mut gui.kaiser_beta).build();
+        if gui.kaiser_beta < 0.0 {
+            gui.kaiser_beta = 0.0;
+        }
+    }
+
+    ui.input_int(im_str!("Block size"), &mut gui.block_size).build();
+    ui.input_int(im_str!("Hop"), &mut gui.hop).build();
+
+    if gui.block_size < 1 {
+        gui.block_size = 1;
+    }
+    if gui.hop < 1 {
+        gui.hop = 1;
+    }
+
+    ui.checkbox(im_str!("Track drift"), &mut gui.track);
+    if gui.track {
+        ui.input_float(im_str!("Track band (Hz)"), &mut gui.track_band).build();
+        ui.input_float(im_str!("Track step (Hz)"), &mut gui.track_step).build();
+        if gui.track_band < 0.0 {
+            gui.track_band = 0.0;
+        }
+        // Keep the number of frequencies searched within what the tracker allows
+        let min_step = (gui.track_band / MAX_TRACK_STEPS as f32).max(1.0);
+        if gui.track_step < min_step {
+            gui.track_step = min_step;
+        }
+        ui.text(im_str!("Searching +/- {} in steps of {}", format_frequency(gui.track_band as f64),
+            format_frequency(gui.track_step as f64)));
+    }
+
+    if let Some(freq) = gui.tracked_freq {
+        ui.text(im_str!("Tracked freq: {}", format_frequency(freq as f64)));
+    }
+
+    ui.separator();
+
+    ui.checkbox(im_str!("Show spectrum"), &mut gui.show_spectrum);
+    if gui.show_spectrum {
+        ui.combo(im_str!("Spectrum window"), &mut gui.spectrum_window_index, &window_names(),
+            -1);
+
+        let mut sizes = vec![];
+        let mut size = MIN_FFT_SIZE;
+        while size <= MAX_FFT_SIZE {
+            sizes.push(ImStr::from(size.to_string()));
+            size *= 2;
+        }
+        ui.combo(im_str!("FFT size"), &mut gui.spectrum_size_index, &sizes, -1);
+
+        ui.input_int(im_str!("Averages"), &mut gui.spectrum_averages).build();
+        gui.spectrum_averages = gui.spectrum_averages.max(1).min(MAX_AVERAGES as i32);
+
+        ui.text(im_str!("Click on a peak in the spectrum to filter at its frequency"));
+    }
+
+    ui.checkbox(im_str!("Show waterfall"), &mut gui.show_waterfall);
+    if gui.show_waterfall {
+        ui.slider_float(im_str!("Waterfall range (dB)"), &mut gui.waterfall_range, 10.0, 120.0)
+            .build();
+
+        match gui.waterfall_progress {
+            Some(progress) if progress < 1.0 => {
+                ui.text(im_str!("Computing waterfall: {:.0}%", progress * 100.0));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw_trigger_controls(gui: &mut Gui, ui: &Ui) {
+    ui.checkbox(im_str!("Trigger"), &mut gui.trigger);
+    if !gui.trigger {
+        return;
+    }
+
+    let channels = channel_names();
+    ui.combo(im_str!("Trigger channel"), &mut gui.trigger_channel_index, &channels, -1);
+
+    ui.combo(im_str!("Condition"), &mut gui.trigger_condition_index, &[im_str!("Rising edge"),
+        im_str!("Falling edge"), im_str!("Pulse wider than"), im_str!("Pulse narrower than")], -1);
+    ui.input_float(im_str!("Trigger level"), &mut gui.trigger_level).build();
+    if gui.trigger_condition_index >= 2 {
+        ui.input_float(im_str!("Pulse width (ms)"), &mut gui.trigger_width).build();
+        if gui.trigger_width < 0.0 {
+            gui.trigger_width = 0.0;
+        }
+    }
+
+    ui.combo(im_str!("Sweep"), &mut gui.trigger_sweep_index, &[im_str!("Auto"),
+        im_str!("Normal"), im_str!("Single")], -1);
+    ui.slider_float(im_str!("Trigger position"), &mut gui.trigger_position, 0.0, 1.0).build();
+
+    if ui.small_button(im_str!("Previous trigger")) {
+        gui.trigger_step = -1;
     }
+    ui.same_line(0.0);
+    if ui.small_button(im_str!("Next trigger")) {
+        gui.trigger_step = 1;
+    }
+
+    if let Some(count) = gui.trigger_count {
+        ui.same_line(0.0);
+        ui.text(im_str!("{} triggers", count));
+    }
+}
 
-    ui.input_float(im_str!("Filter freq"), &mut gui.filter_freq).build();
+fn draw_readout(ui: &Ui, readout: Readout) {
+    let value = |x: Option<f32>| x.map_or("-".into(), |x| format!("{:.4}", x));
 
-    if gui.filter_freq < -1e6 {
-        gui.filter_freq = -1e6;
+    ui.text(im_str!("Measuring {}", readout.channel.name()));
+    for i in 0..2 {
+        ui.text(im_str!("t{}: {}  value: {}", i + 1, format_duration(readout.times[i]),
+            value(readout.values[i])));
     }
-    else if gui.filter_freq > 1e6 {
-        gui.filter_freq = 1e6;
+
+    let delta_t = readout.delta_t();
+    ui.text(im_str!("dt: {}", format_duration(delta_t)));
+    match delta_t == 0.0 {
+        true => ui.text(im_str!("1/dt: -")),
+        false => ui.text(im_str!("1/dt: {}", format_frequency(1.0 / delta_t.abs()))),
+    }
+
+    ui.separator();
+
+    for i in 0..2 {
+        ui.text(im_str!("V{}: {:.4}", i + 1, readout.levels[i]));
     }
-}
\ No newline at end of file
+    ui.text(im_str!("dV: {:.4}", readout.delta_v()));
+}
+
+fn channel_names() -> Vec<ImStr<'static>> {
+    CHANNELS.iter().map(|x| ImStr::from(x.name().to_string())).collect()
+}
+
+fn window_names() -> [ImStr<'static>; 6] {
+    [im_str!("Rectangular"), im_str!("Hann"), im_str!("Hamming"), im_str!("Blackman-Harris"),
+        im_str!("Flat-top"), im_str!("Kaiser")]
+}
+
+/// Gets the window selected from the list given by `window_names`
+fn selected_window(index: i32, kaiser_beta: f32) -> Window {
+    match index {
+        0 => Window::Rectangular,
+        1 => Window::Hann,
+        2 => Window::Hamming,
+        4 => Window::FlatTop,
+        5 => Window::Kaiser(kaiser_beta),
+        _ => Window::BlackmanHarris,
+    }
+}
+
+/// Labels the scope's grid lines, which are positioned as fractions of the window's size
+fn draw_scope_axes(ui: &Ui, labels: &[AxisLabel]) {
+    let (width, height) = ui.imgui().display_size();
+    for (i, label) in labels.iter().enumerate() {
+        let pos = (label.pos.0 * width, label.pos.1 * height);
+        draw_label(ui, &format!("axis{}", i), pos, &label.text);
+    }
+}
+
+/// Labels the frequency and power axes of the spectrum panel at the bottom of the window
+fn draw_spectrum_axes(ui: &Ui, samp_rate: f64, range: (f32, f32)) {
+    let (width, height) = ui.imgui().display_size();
+    let top = height * (1.0 - SPECTRUM_HEIGHT);
+
+    for i in 0..5 {
+        let freq = samp_rate * (i as f64 / 4.0 - 0.5);
+        let x = (width * i as f32 / 4.0 - 30.0).max(0.0).min(width - 70.0);
+        draw_label(ui, &format!("freq{}", i), (x, height - 25.0), &format_frequency(freq));
+    }
+    draw_label(ui, "power_top", (0.0, top), &format!("{:.0} dB", range.1));
+    draw_label(ui, "power_bottom", (0.0, height - 45.0), &format!("{:.0} dB", range.0));
+}
+
+/// Draws text at a fixed position (in points from the top left of the window) over the scope
+fn draw_label(ui: &Ui, id: &str, pos: (f32, f32), text: &str) {
+    ui.window(im_str!("##{}", id))
+        .position(pos, ImGuiSetCond_Always)
+        .title_bar(false)
+        .resizable(false)
+        .movable(false)
+        .scroll_bar(false)
+        .always_auto_resize(true)
+        .save_settings(false)
+        .inputs(false)
+        .bg_alpha(0.0)
+        .build(|| ui.text_colored(LABEL_COLOR, im_str!("{}", text)));
+}
+
+/// Creates the buffer edited by a text input, holding `text` padded with nuls to `size` bytes
+fn text_buffer(text: &str, size: usize) -> String {
+    let mut buffer = text.to_owned();
+    buffer.extend(repeat('\0').take(size.saturating_sub(text.len())));
+    buffer.truncate(size);
+    buffer
+}
+
+/// Gets the text entered in a text input's buffer
+fn buffer_text(buffer: &str) -> &str {
+    buffer.split('\0').next().unwrap_or("")
+}
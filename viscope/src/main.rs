@@ -1,5 +1,6 @@
 #[macro_use] extern crate glium;
 #[macro_use] extern crate imgui;
+extern crate clap;
 
 extern crate iq_converter;
 extern crate sample;
@@ -9,11 +10,16 @@ mod scope_app;
 mod gui;
 
 use std::error::Error;
-use std::env;
+use std::path::Path;
 use std::time::{Instant, Duration};
 
+use clap::App;
+
+use iq_converter::units::{parse_count, parse_rate};
+
 use window::Window;
 use scope_app::{SignalType, ScopeApp, loader};
+use scope_app::loader::{CaptureSettings, LoadJob};
 use gui::Gui;
 
 fn main() {
@@ -23,33 +29,119 @@ fn main() {
 }
 
 fn run() -> Result<(), Box<Error>> {
+    let matches = App::new("ViScope")
+        .arg_from_usage("[input] 'The path to the input file (default: signal.bin)'")
+        .arg_from_usage("-s, --samp_rate [samp_rate] 'Set input file sample rate, e.g. 2e6, 2M or \
+            2MS/s (default: 2MS/s)'")
+        .arg_from_usage("--display_rate [display_rate] 'Set the rate the raw signal is resampled \
+            to for display, e.g. 100k (default: 100kS/s)'")
+        .arg_from_usage("-n, --block_size [block_size] 'Set the number of samples in each filter \
+            block (default: 1000)'")
+        .get_matches();
+
+    let source = matches.value_of("input").unwrap_or("signal.bin").to_string();
+
+    let mut capture = CaptureSettings::default();
+    if let Some(samp_rate) = matches.value_of("samp_rate") {
+        capture.samp_rate = try!(parse_rate(samp_rate));
+    }
+    if let Some(display_rate) = matches.value_of("display_rate") {
+        capture.display_rate = try!(parse_rate(display_rate));
+    }
+    let block_size = match matches.value_of("block_size") {
+        Some(block_size) => try!(parse_count(block_size)),
+        None => 1000,
+    };
+    if block_size == 0 {
+        return Err("Block size must be at least 1".into());
+    }
+
     let mut window = try!(Window::init("ViScope"));
 
-    let source = env::args().nth(1).unwrap_or("signal.bin".into());
     println!("Loading: {}", source);
 
-    let mut gui = Gui::init();
+    let mut gui = Gui::init(capture, block_size);
     let mut app = ScopeApp::new(window.get_display());
 
-    app.load(window.get_display(), loader::raw_data(&source));
     let mut signal_type = SignalType::Raw;
+    let mut job = Some(start_load(&source, signal_type, capture));
+
+    // The settings of the signal currently displayed, which are restored if a load is cancelled
+    let mut loaded = (signal_type, capture);
 
     let mut prev_time = Instant::now();
     loop {
-        if gui.signal_type != signal_type {
-            match gui.signal_type {
-                SignalType::Raw => app.load(window.get_display(), loader::raw_data(&source)),
-                SignalType::Filtered(freq) => {
-                    app.load(window.get_display(),  loader::filtered_data(&source, freq as f32));
+        if gui.signal_type != signal_type || gui.capture != capture {
+            // Replacing the job cancels any load that is still running
+            job = Some(start_load(&source, gui.signal_type, gui.capture));
+            signal_type = gui.signal_type;
+            capture = gui.capture;
+        }
+
+        if let Some(result) = job.as_ref().and_then(|job| job.poll()) {
+            job = None;
+            match result {
+                Ok(data) => {
+                    app.load(window.get_display(), data, &mut gui.playback);
+                    loaded = (signal_type, capture);
+                }
+                Err(e) => {
+                    gui.error = Some(e);
+                    gui.cancel = true;
                 }
             }
-            signal_type = gui.signal_type;
         }
 
+        if gui.cancel {
+            job = None;
+            gui.cancel = false;
+            gui.signal_type = loaded.0;
+            gui.capture = loaded.1;
+            signal_type = loaded.0;
+            capture = loaded.1;
+        }
+        gui.progress = job.as_ref().map(|job| job.progress());
+
         let dt = total_seconds(prev_time.elapsed()) as f32;
         prev_time = Instant::now();
 
+        app.set_trigger(gui.trigger_settings());
+        if gui.trigger_step != 0 {
+            app.step_trigger(&mut gui.playback, gui.trigger_step > 0);
+            gui.trigger_step = 0;
+        }
+        gui.trigger_count = app.trigger_count();
+
+        app.set_cursors(window.get_display(), gui.cursor_channel());
+        let size = window.get_display().get_framebuffer_dimensions();
+        if let Some(freq) = app.handle_mouse(&window.mouse, size, &mut gui.playback) {
+            gui.filter_freq = freq as f32;
+        }
+        gui.cursor_readout = app.cursor_readout();
+
         app.update(dt, &mut gui.playback);
+
+        app.set_amplitude_scale(gui.amplitude_scale());
+        gui.axis_labels = app.axis_labels(size);
+        gui.tracked_freq = app.tracked_freq();
+        gui.length = app.length();
+
+        let (spectrum, filter_freq) = (gui.spectrum_settings(), gui.filter_freq);
+        if let Err(e) = app.update_spectrum(Path::new(&source), loaded.1, spectrum, filter_freq) {
+            gui.error = Some(e);
+            gui.show_spectrum = false;
+        }
+        gui.spectrum_range = app.spectrum_range();
+
+        let waterfall_range = gui.waterfall_range();
+        if let Err(e) = app.update_waterfall(window.get_display(), Path::new(&source), loaded.1,
+            waterfall_range)
+        {
+            gui.error = Some(e);
+            gui.show_waterfall = false;
+        }
+        gui.waterfall_progress = app.waterfall_progress();
+
         try!(window.render(|ui| gui.draw(ui), |frame| app.draw(frame)));
 
         if !window.update_events() {
@@ -60,6 +152,19 @@ fn run() -> Result<(), Box<Error>> {
     Ok(())
 }
 
+/// Starts loading the signal selected in the GUI on a worker thread
+fn start_load(source: &str, signal_type: SignalType, capture: CaptureSettings) -> LoadJob {
+    let source = source.to_string();
+    LoadJob::spawn(move |progress| {
+        match signal_type {
+            SignalType::Raw => loader::raw_data(&source, capture, progress),
+            SignalType::Filtered(freq, settings, tracking) => {
+                loader::filtered_data(&source, capture, freq as f32, settings, tracking, progress)
+            }
+        }
+    })
+}
+
 /// Computes the total number of seconds from a duration
 pub fn total_seconds(duration: Duration) -> f64 {
     duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
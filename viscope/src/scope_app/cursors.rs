@@ -0,0 +1,218 @@
+use std::rc::Rc;
+
+use glium::{index, Surface, Display, Frame, Rect, DrawParameters};
+use glium::{VertexBuffer, Program};
+
+use super::Channel;
+use super::renderer::Vertex;
+
+/// How close (in pixels) the mouse must be to a cursor to pick it up
+const GRAB_PIXELS: f32 = 6.0;
+
+/// How close (in pixels) an amplitude cursor must be dragged to the trace to snap to its value
+const SNAP_PIXELS: f32 = 8.0;
+
+const TIME_COLOR: [f32; 3] = [0.9, 0.5, 0.0];
+const LEVEL_COLOR: [f32; 3] = [0.6, 0.0, 0.8];
+
+/// Maps between pixels in the scope view and the time and amplitude of a trace
+#[derive(Copy, Clone)]
+pub struct ViewMapping {
+    /// The time (in seconds) at the left of the view
+    pub start: f64,
+
+    /// The time (in seconds) across the view
+    pub span: f64,
+    pub y_scale: f32,
+    pub y_offset: f32,
+
+    /// The view's viewport, with the size of the window it is in
+    pub rect: Rect,
+    pub window_size: (u32, u32),
+}
+
+impl ViewMapping {
+    /// Whether `pos` (in pixels from the top left of the window) is inside the view
+    pub fn contains(&self, pos: (f32, f32)) -> bool {
+        let (left, top) = (self.rect.left as f32, self.top());
+        pos.0 >= left && pos.0 < left + self.rect.width as f32 &&
+            pos.1 >= top && pos.1 < top + self.rect.height as f32
+    }
+
+    pub fn time_at(&self, x: f32) -> f64 {
+        self.start + (x - self.rect.left as f32) as f64 / self.rect.width as f64 * self.span
+    }
+
+    pub fn x_of(&self, time: f64) -> f32 {
+        self.rect.left as f32 + ((time - self.start) / self.span) as f32 * self.rect.width as f32
+    }
+
+    pub fn level_at(&self, y: f32) -> f32 {
+        let ndc = 1.0 - 2.0 * (y - self.top()) / self.rect.height as f32;
+        (ndc - self.y_offset) / self.y_scale
+    }
+
+    pub fn y_of(&self, level: f32) -> f32 {
+        let ndc = level * self.y_scale + self.y_offset;
+        self.top() + (1.0 - ndc) / 2.0 * self.rect.height as f32
+    }
+
+    /// The distance (in pixels) from the top of the window to the top of the view
+    fn top(&self) -> f32 {
+        (self.window_size.1 - self.rect.bottom - self.rect.height) as f32
+    }
+}
+
+/// The cursor being dragged
+#[derive(Copy, Clone, PartialEq)]
+enum Handle {
+    Time(usize),
+    Level(usize),
+}
+
+/// The positions of the cursors and the signal under them
+#[derive(Copy, Clone)]
+pub struct Readout {
+    pub channel: Channel,
+    pub times: [f64; 2],
+
+    /// The value of the channel at each time cursor, if it is within the signal
+    pub values: [Option<f32>; 2],
+    pub levels: [f32; 2],
+}
+
+impl Readout {
+    pub fn delta_t(&self) -> f64 {
+        self.times[1] - self.times[0]
+    }
+
+    pub fn delta_v(&self) -> f32 {
+        self.levels[1] - self.levels[0]
+    }
+}
+
+/// Two time cursors and two amplitude cursors, measuring one channel of the signal
+pub struct Cursors {
+    shaders: Rc<Program>,
+    lines: VertexBuffer<Vertex>,
+
+    pub channel: Channel,
+    times: [f64; 2],
+    levels: [f32; 2],
+    dragging: Option<Handle>,
+}
+
+impl Cursors {
+    /// Creates cursors at a third and two thirds of the way across and up the view
+    pub fn new(display: &Display, shaders: Rc<Program>, channel: Channel, view: &ViewMapping)
+        -> Cursors
+    {
+        let level = |ndc: f32| (ndc - view.y_offset) / view.y_scale;
+
+        Cursors {
+            shaders: shaders,
+            lines: VertexBuffer::empty_dynamic(display, 8).unwrap(),
+            channel: channel,
+            times: [view.start + view.span / 3.0, view.start + 2.0 * view.span / 3.0],
+            levels: [level(-1.0 / 3.0), level(1.0 / 3.0)],
+            dragging: None,
+        }
+    }
+
+    /// Picks up the cursor nearest to a click at `pos`, returning false if there is none close
+    /// enough
+    pub fn grab(&mut self, pos: (f32, f32), view: &ViewMapping) -> bool {
+        let mut best = None;
+        let mut best_distance = GRAB_PIXELS;
+        for i in 0..2 {
+            let distance = (view.x_of(self.times[i]) - pos.0).abs();
+            if distance <= best_distance {
+                best = Some(Handle::Time(i));
+                best_distance = distance;
+            }
+
+            let distance = (view.y_of(self.levels[i]) - pos.1).abs();
+            if distance <= best_distance {
+                best = Some(Handle::Level(i));
+                best_distance = distance;
+            }
+        }
+
+        self.dragging = best;
+        best.is_some()
+    }
+
+    /// Moves the cursor being dragged to `pos`, snapping it to the nearest of the channel's
+    /// `samples`. Returns false if no cursor is being dragged.
+    pub fn drag(&mut self, pos: (f32, f32), view: &ViewMapping, samples: &[f32], samp_rate: f32)
+        -> bool
+    {
+        let samp_rate = samp_rate as f64;
+        let nearest = (view.time_at(pos.0) * samp_rate).round().max(0.0) as usize;
+
+        match self.dragging {
+            Some(Handle::Time(i)) => {
+                self.times[i] = nearest as f64 / samp_rate;
+            }
+            Some(Handle::Level(i)) => {
+                // Snap to the sample under the mouse when dragged close to the trace
+                self.levels[i] = match samples.get(nearest) {
+                    Some(&value) if (view.y_of(value) - pos.1).abs() <= SNAP_PIXELS => value,
+                    _ => view.level_at(pos.1),
+                };
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Drops the cursor being dragged
+    pub fn release(&mut self) {
+        self.dragging = None;
+    }
+
+    pub fn readout(&self, samples: &[f32], samp_rate: f32) -> Readout {
+        let value = |time: f64| {
+            samples.get((time * samp_rate as f64).round().max(0.0) as usize).cloned()
+        };
+
+        Readout {
+            channel: self.channel,
+            times: self.times,
+            values: [value(self.times[0]), value(self.times[1])],
+            levels: self.levels,
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, view: &ViewMapping) {
+        let params = DrawParameters {
+            viewport: Some(view.rect),
+            .. Default::default()
+        };
+
+        // Vertices are relative to the start of the view, so that they keep their precision far
+        // into long signals
+        let x = |i: usize| (self.times[i] - view.start) as f32;
+        let span = view.span as f32;
+        self.lines.write(&[
+            Vertex::new(x(0), -1.0), Vertex::new(x(0), 1.0),
+            Vertex::new(x(1), -1.0), Vertex::new(x(1), 1.0),
+            Vertex::new(0.0, self.levels[0]), Vertex::new(span, self.levels[0]),
+            Vertex::new(0.0, self.levels[1]), Vertex::new(span, self.levels[1]),
+        ]);
+
+        let lines = index::NoIndices(index::PrimitiveType::LinesList);
+        let uniforms = |y_scale: f32, y_offset: f32, color: [f32; 3]| uniform! {
+            dt: 0.0_f32,
+            x_scale: 2.0 / span,
+            y_scale: y_scale,
+            y_offset: y_offset,
+            color: color,
+        };
+
+        frame.draw(self.lines.slice(0..4).unwrap(), &lines, &self.shaders,
+            &uniforms(1.0, 0.0, TIME_COLOR), &params).unwrap();
+        frame.draw(self.lines.slice(4..8).unwrap(), &lines, &self.shaders,
+            &uniforms(view.y_scale, view.y_offset, LEVEL_COLOR), &params).unwrap();
+    }
+}
@@ -0,0 +1,309 @@
+use std::rc::Rc;
+
+use glium::{index, Surface, Display, Frame, DrawParameters};
+use glium::{VertexBuffer, Program};
+
+use iq_converter::units::format_duration;
+
+use super::cursors::ViewMapping;
+use super::renderer::Vertex;
+
+/// The closest (in pixels) that grid lines are drawn across and up the scope
+const MIN_TIME_TICK_PIXELS: f32 = 100.0;
+const MIN_LEVEL_TICK_PIXELS: f32 = 40.0;
+
+/// The levels (in dB relative to a full scale sample) that can be marked on a dBFS axis
+const DBFS_TICKS: [f32; 13] = [3.0, 0.0, -3.0, -6.0, -10.0, -20.0, -30.0, -40.0, -50.0, -60.0,
+    -70.0, -80.0, -100.0];
+
+/// The most grid lines drawn in each direction
+const MAX_LINES: usize = 64;
+
+const GRID_COLOR: [f32; 3] = [0.85, 0.85, 0.85];
+
+/// How the amplitude axis is labelled
+#[derive(Copy, Clone, PartialEq)]
+pub enum AmplitudeScale {
+    /// The amplitude, where a full scale sample is 1
+    Linear,
+
+    /// The amplitude in dB relative to a full scale sample
+    Dbfs,
+}
+
+/// Text to draw over the scope, positioned as a fraction of the window's size from its top left
+pub struct AxisLabel {
+    pub pos: (f32, f32),
+    pub text: String,
+}
+
+/// The positions of the grid lines across the visible part of the signal
+pub struct Axes {
+    /// The time (in seconds) of each vertical line, which are `time_step` apart
+    times: Vec<f64>,
+    time_step: f64,
+
+    /// The amplitude of each horizontal line, with its label
+    levels: Vec<(f32, String)>,
+    scale: AmplitudeScale,
+
+    /// Whether the amplitude lines are labelled
+    label_levels: bool,
+}
+
+impl Axes {
+    /// Chooses grid lines for `view`, spaced at 1, 2 or 5 times a power of ten in time and, on a
+    /// linear scale, in amplitude. The amplitude lines are only labelled if `label_levels` is set.
+    pub fn new(view: &ViewMapping, scale: AmplitudeScale, label_levels: bool) -> Axes {
+        let max_ticks = (view.rect.width as f32 / MIN_TIME_TICK_PIXELS) as usize;
+        let time_step = nice_step(view.span, max_ticks);
+        let times = ticks(view.start, view.start + view.span, time_step).iter()
+            .map(|&i| i as f64 * time_step)
+            .collect();
+
+        let levels = match scale {
+            AmplitudeScale::Linear => {
+                let max_ticks = (view.rect.height as f32 / MIN_LEVEL_TICK_PIXELS) as usize;
+                let top = 1.0 / view.y_scale as f64;
+                let step = nice_step(2.0 * top, max_ticks);
+
+                // Enough decimal places to tell the lines apart
+                let decimals = (-step.log10().floor()).max(0.0) as usize;
+                ticks(-top, top, step).iter().map(|&i| {
+                    let level = i as f64 * step;
+                    (level as f32, format!("{:.*}", decimals, level))
+                }).collect()
+            }
+            AmplitudeScale::Dbfs => dbfs_levels(view),
+        };
+
+        Axes {
+            times: times,
+            time_step: time_step,
+            levels: levels,
+            scale: scale,
+            label_levels: label_levels,
+        }
+    }
+
+    /// Gets the labels for the grid lines of `view`. The first time is absolute and the rest are
+    /// measured from it, so that they stay short far into long signals.
+    pub fn labels(&self, view: &ViewMapping) -> Vec<AxisLabel> {
+        let size = (view.window_size.0 as f32, view.window_size.1 as f32);
+        let label = |x: f32, y: f32, text: String| AxisLabel {
+            pos: (x / size.0, y / size.1),
+            text: text,
+        };
+
+        let mut labels = vec![];
+        let bottom = view.y_of(-1.0 / view.y_scale);
+        for (i, &time) in self.times.iter().enumerate() {
+            let text = match i {
+                0 => format_duration(time),
+                _ => format!("+{}", format_duration(i as f64 * self.time_step)),
+            };
+            labels.push(label(view.x_of(time) + 2.0, bottom - 20.0, text));
+        }
+
+        if !self.label_levels {
+            return labels;
+        }
+
+        let left = view.rect.left as f32;
+        for &(level, ref text) in &self.levels {
+            labels.push(label(left + 2.0, view.y_of(level) - 18.0, text.clone()));
+        }
+
+        let title = match self.scale {
+            AmplitudeScale::Linear => "Amplitude",
+            AmplitudeScale::Dbfs => "dBFS",
+        };
+        labels.push(label(left + 2.0, view.y_of(1.0 / view.y_scale), title.into()));
+
+        labels
+    }
+}
+
+/// Gets the dBFS levels that are visible in `view` and far enough apart to label, with the same
+/// levels mirrored below zero for signals that go negative
+fn dbfs_levels(view: &ViewMapping) -> Vec<(f32, String)> {
+    let mut levels = vec![];
+    let mut last = None;
+    for &db in DBFS_TICKS.iter() {
+        let level = (10.0_f32).powf(db / 20.0);
+        if level * view.y_scale > 1.0 {
+            continue;
+        }
+
+        // Stop before the labels of the mirrored levels would overlap around zero
+        let y = view.y_of(level);
+        if view.y_of(0.0) - y < MIN_LEVEL_TICK_PIXELS / 2.0 {
+            break;
+        }
+        if let Some(last) = last {
+            if y - last < MIN_LEVEL_TICK_PIXELS {
+                continue;
+            }
+        }
+        last = Some(y);
+
+        let text = format!("{} dBFS", db);
+        levels.push((level, text.clone()));
+        levels.push((-level, text));
+    }
+    levels
+}
+
+/// Gets a step of 1, 2 or 5 times a power of ten that splits `span` into at most `max_ticks`
+/// parts
+fn nice_step(span: f64, max_ticks: usize) -> f64 {
+    let raw = span / max_ticks.max(1) as f64;
+    let power = (10.0_f64).powf(raw.log10().floor());
+    for &multiple in [1.0, 2.0, 5.0].iter() {
+        if multiple * power >= raw {
+            return multiple * power;
+        }
+    }
+    10.0 * power
+}
+
+/// Gets the multiples of `step` from `start` to `end`
+fn ticks(start: f64, end: f64, step: f64) -> Vec<i64> {
+    if !(step > 0.0) {
+        return vec![];
+    }
+
+    let first = (start / step).ceil() as i64;
+    let last = (end / step).floor() as i64;
+    (first..last + 1).take(MAX_LINES).collect()
+}
+
+/// Draws the grid lines behind the traces
+pub struct Grid {
+    shaders: Rc<Program>,
+    lines: VertexBuffer<Vertex>,
+}
+
+impl Grid {
+    pub fn new(display: &Display, shaders: Rc<Program>) -> Grid {
+        Grid {
+            shaders: shaders,
+            lines: VertexBuffer::empty_dynamic(display, 4 * MAX_LINES).unwrap(),
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, view: &ViewMapping, axes: &Axes) {
+        let params = DrawParameters {
+            viewport: Some(view.rect),
+            .. Default::default()
+        };
+        let lines = index::NoIndices(index::PrimitiveType::LinesList);
+        let uniforms = |x_scale: f32, y_scale: f32| uniform! {
+            dt: 0.0_f32,
+            x_scale: x_scale,
+            y_scale: y_scale,
+            y_offset: 0.0_f32,
+            color: GRID_COLOR,
+        };
+
+        // Times are relative to the start of the view, so that they keep their precision far into
+        // long signals
+        let span = view.span as f32;
+        let mut vertices = vec![];
+        for &time in &axes.times {
+            let x = (time - view.start) as f32;
+            vertices.push(Vertex::new(x, -1.0));
+            vertices.push(Vertex::new(x, 1.0));
+        }
+        let vertical = vertices.len();
+        for &(level, _) in &axes.levels {
+            vertices.push(Vertex::new(0.0, level));
+            vertices.push(Vertex::new(span, level));
+        }
+        if vertices.is_empty() {
+            return;
+        }
+        vertices.truncate(self.lines.len());
+        self.lines.slice(0..vertices.len()).unwrap().write(&vertices);
+
+        if vertical > 0 {
+            frame.draw(self.lines.slice(0..vertical).unwrap(), &lines, &self.shaders,
+                &uniforms(2.0 / span, 1.0), &params).unwrap();
+        }
+        if vertices.len() > vertical {
+            frame.draw(self.lines.slice(vertical..vertices.len()).unwrap(), &lines, &self.shaders,
+                &uniforms(2.0 / span, view.y_scale), &params).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glium::Rect;
+
+    use super::*;
+
+    fn view(start: f64, span: f64, y_scale: f32) -> ViewMapping {
+        ViewMapping {
+            start: start,
+            span: span,
+            y_scale: y_scale,
+            y_offset: 0.0,
+            rect: Rect { left: 0, bottom: 0, width: 1000, height: 400 },
+            window_size: (1000, 400),
+        }
+    }
+
+    #[test]
+    fn steps_at_1_2_or_5_times_a_power_of_ten() {
+        assert_eq!(nice_step(1.0, 10), 0.1);
+        assert_eq!(nice_step(1.0, 8), 0.2);
+        assert_eq!(nice_step(1.0, 4), 0.5);
+        assert_eq!(nice_step(1.0, 3), 0.5);
+        assert_eq!(nice_step(0.9, 1), 1.0);
+        assert_eq!(nice_step(7.0, 1), 10.0);
+        assert_eq!(nice_step(3e-5, 2), 2e-5);
+
+        // No room for any ticks still gives a step wider than the span
+        assert_eq!(nice_step(3.0, 0), 5.0);
+    }
+
+    #[test]
+    fn ticks_are_the_multiples_of_the_step_in_range() {
+        assert_eq!(ticks(0.15, 0.75, 0.2), vec![1, 2, 3]);
+        assert_eq!(ticks(-1.0, 1.0, 0.5), vec![-2, -1, 0, 1, 2]);
+        assert!(ticks(0.1, 0.15, 0.2).is_empty());
+        assert!(ticks(0.0, 1.0, 0.0).is_empty());
+        assert_eq!(ticks(0.0, 1.0, 1e-6), (0..MAX_LINES as i64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn labels_dbfs_levels_that_fit() {
+        let levels = dbfs_levels(&view(0.0, 1.0, 1.0));
+        let db: Vec<_> = levels.iter().filter(|x| x.0 > 0.0).map(|x| x.1.as_str()).collect();
+        assert_eq!(db, vec!["0 dBFS", "-3 dBFS", "-6 dBFS", "-20 dBFS"]);
+
+        // Each level is mirrored below zero, and levels above full scale are left out
+        for pair in levels.chunks(2) {
+            assert_eq!(pair[0].0, -pair[1].0);
+            assert!(pair[0].0 <= 1.0);
+        }
+
+        // Zooming in shows the quieter levels
+        let levels = dbfs_levels(&view(0.0, 1.0, 100.0));
+        assert_eq!(levels[0].1, "-40 dBFS");
+    }
+
+    #[test]
+    fn only_labels_levels_when_asked() {
+        let view = view(2.0, 1.0, 1.0);
+        let labelled = Axes::new(&view, AmplitudeScale::Linear, true);
+        let unlabelled = Axes::new(&view, AmplitudeScale::Linear, false);
+
+        // Ten time labels from 2 s, then the levels and the axis title
+        let labels = labelled.labels(&view);
+        assert_eq!(labels.len(), 11 + labelled.levels.len() + 1);
+        assert_eq!(labels[1].text, "+100 ms");
+        assert_eq!(unlabelled.labels(&view).len(), 11);
+    }
+}
@@ -1,56 +1,343 @@
 use std::f32;
 
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{self, BufReader, Bytes, Read};
+use std::path::{Path, PathBuf};
 
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 use std::vec;
 
 use iq_converter::IqConverter;
+use iq_converter::goertzel::{FilterSettings, Magnitudes, TrackingMagnitudes, TrackingSettings};
 use sample::rate::Converter;
-use dsp_filters::GoertzelFilter;
 use dsp_filters::edge_filter::EdgeFilter;
-use dsp_filters::window;
 
-use super::ScopeSource;
+use super::{Channel, ScopeSource};
 
-pub fn raw_data<P: AsRef<Path>>(file_path: P) -> ScopeSource<vec::IntoIter<f32>> {
-    let mut input_file = File::open(file_path).unwrap();
-    let mut data = vec![];
-    input_file.read_to_end(&mut data).unwrap();
+/// The default sample rate of the captures loaded by the scope
+pub const DEFAULT_SAMP_RATE: f64 = 2e6;
 
-    let raw_stream = IqConverter::new(data).map(|x| [x.0]);
-    let down_sampled_stream = Converter::from_hz_to_hz(raw_stream, 2e6, 1e5);
-    let data: Vec<_> = down_sampled_stream.map(|x| x[0]).collect();
+/// The default rate that raw captures are resampled to for display
+pub const DEFAULT_DISPLAY_RATE: f64 = 1e5;
 
-    ScopeSource {
-        samp_rate: 1e5,
-        data: data.into_iter(),
+/// The number of samples processed between progress updates and checks for cancellation
+const PROGRESS_INTERVAL: usize = 1 << 16;
+
+/// A signal loaded from a capture
+pub type Source = ScopeSource<vec::IntoIter<f32>>;
+
+/// The timing of a capture loaded by the scope
+#[derive(Copy, Clone, PartialEq)]
+pub struct CaptureSettings {
+    /// The sample rate of the capture
+    pub samp_rate: f64,
+
+    /// The rate that the raw signal is resampled to for display. Captures sampled slower than this
+    /// are displayed at their own rate.
+    pub display_rate: f64,
+}
+
+impl Default for CaptureSettings {
+    fn default() -> CaptureSettings {
+        CaptureSettings {
+            samp_rate: DEFAULT_SAMP_RATE,
+            display_rate: DEFAULT_DISPLAY_RATE,
+        }
     }
 }
 
-pub fn filtered_data<P: AsRef<Path>>(file_path: P, freq: f32) -> ScopeSource<vec::IntoIter<f32>> {
+/// How far a job has got, shared between the thread doing the work and the GUI
+pub struct Progress {
+    done: AtomicUsize,
+    total: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    fn new() -> Progress {
+        Progress {
+            done: AtomicUsize::new(0),
+            total: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Gets the fraction of the capture processed so far
+    pub fn fraction(&self) -> f32 {
+        let total = self.total.load(Ordering::Relaxed);
+        match total {
+            0 => 0.0,
+            _ => self.done.load(Ordering::Relaxed) as f32 / total as f32,
+        }
+    }
+
+    /// Asks the load to stop as soon as possible
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// A load, or other work on a capture, running on a worker thread. Dropping the job cancels it.
+pub struct LoadJob<T = Source> {
+    progress: Arc<Progress>,
+    result: Receiver<Result<T, String>>,
+}
+
+impl<T: Send + 'static> LoadJob<T> {
+    pub fn spawn<F>(work: F) -> LoadJob<T>
+        where F: FnOnce(&Progress) -> Result<T, String> + Send + 'static
+    {
+        let progress = Arc::new(Progress::new());
+        let (sender, receiver) = mpsc::channel();
+
+        let worker_progress = progress.clone();
+        thread::spawn(move || {
+            // The receiver is gone if the job was cancelled, in which case the result is unwanted
+            let _ = sender.send(work(&worker_progress));
+        });
+
+        LoadJob {
+            progress: progress,
+            result: receiver,
+        }
+    }
+
+    /// Gets the fraction of the capture processed so far
+    pub fn progress(&self) -> f32 {
+        self.progress.fraction()
+    }
+
+    /// Gets the result, or the reason the job failed, once the job has finished. The result is
+    /// only returned once.
+    pub fn poll(&self) -> Option<Result<T, String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("The worker stopped unexpectedly".into())),
+        }
+    }
+}
+
+impl<T> Drop for LoadJob<T> {
+    fn drop(&mut self) {
+        self.progress.cancel();
+    }
+}
+
+/// Passes on the samples of a capture, counting them in `progress` and stopping early if the load
+/// is cancelled
+struct Tracked<'a, I> {
+    input: I,
+    count: usize,
+    progress: &'a Progress,
+}
+
+impl<'a, I: Iterator> Tracked<'a, I> {
+    fn new(input: I, total: usize, progress: &'a Progress) -> Tracked<'a, I> {
+        progress.total.store(total, Ordering::Relaxed);
+        Tracked {
+            input: input,
+            count: 0,
+            progress: progress,
+        }
+    }
+}
+
+impl<'a, I: Iterator> Iterator for Tracked<'a, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.count % PROGRESS_INTERVAL == 0 {
+            if self.progress.is_cancelled() {
+                return None;
+            }
+            self.progress.done.store(self.count, Ordering::Relaxed);
+        }
+        self.count += 1;
+        self.input.next()
+    }
+}
+
+/// The bytes of a capture, read from the file as they are needed. A read error ends the iteration
+/// and is kept to be checked once the bytes have been consumed.
+pub struct CaptureBytes {
+    path: PathBuf,
+    bytes: Bytes<BufReader<File>>,
+    len: u64,
+    error: Option<io::Error>,
+}
+
+impl CaptureBytes {
+    pub fn open(path: &Path) -> Result<CaptureBytes, String> {
+        let file = try!(File::open(path)
+            .map_err(|e| format!("Unable to open {}: {}", path.display(), e)));
+        let len = try!(file.metadata()
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))).len();
+
+        Ok(CaptureBytes {
+            path: path.to_path_buf(),
+            bytes: BufReader::new(file).bytes(),
+            len: len,
+            error: None,
+        })
+    }
+
+    /// The number of I/Q samples in the capture, from the size of the file when it was opened
+    pub fn samples(&self) -> usize {
+        (self.len / 2) as usize
+    }
+
+    /// Returns the error that stopped the iteration, if there was one
+    pub fn take_error(&mut self) -> Result<(), String> {
+        match self.error.take() {
+            Some(e) => Err(format!("Unable to read {}: {}", self.path.display(), e)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Iterator for CaptureBytes {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        match self.bytes.next() {
+            Some(Ok(byte)) => Some(byte),
+            Some(Err(e)) => {
+                self.error = Some(e);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+pub fn raw_data<P: AsRef<Path>>(file_path: P, capture: CaptureSettings, progress: &Progress)
+    -> Result<Source, String>
+{
+    let mut data = try!(CaptureBytes::open(file_path.as_ref()));
+    let total = data.samples();
+
+    let display_rate = capture.display_rate.min(capture.samp_rate);
+
+    let raw_stream = Tracked::new(IqConverter::new(&mut data), total, progress)
+        .map(|x| [x.0, x.1]);
+    let down_sampled_stream = Converter::from_hz_to_hz(raw_stream, capture.samp_rate,
+        display_rate);
+
+    let (mut i, mut q, mut magnitude, mut phase) = (vec![], vec![], vec![], vec![]);
+    for x in down_sampled_stream {
+        i.push(x[0]);
+        q.push(x[1]);
+        magnitude.push((x[0] * x[0] + x[1] * x[1]).sqrt());
+        // Scaled so that the phase fits in the same range as the I/Q samples
+        phase.push(x[1].atan2(x[0]) / f32::consts::PI);
+    }
+    if progress.is_cancelled() {
+        return Err("Loading cancelled".into());
+    }
+    try!(data.take_error());
+
+    Ok(ScopeSource {
+        samp_rate: display_rate as f32,
+        channels: vec![
+            (Channel::I, i.into_iter()),
+            (Channel::Q, q.into_iter()),
+            (Channel::Magnitude, magnitude.into_iter()),
+            (Channel::Phase, phase.into_iter()),
+        ],
+        tracked_freq: None,
+    })
+}
+
+pub fn filtered_data<P: AsRef<Path>>(file_path: P, capture: CaptureSettings, freq: f32,
+    settings: FilterSettings, tracking: Option<TrackingSettings>, progress: &Progress)
+    -> Result<Source, String>
+{
     let mut edge_filter = EdgeFilter::new(20);
 
-    let mut input_file = File::open(file_path).unwrap();
-    let mut data = vec![];
-    input_file.read_to_end(&mut data).unwrap();
+    let mut data = try!(CaptureBytes::open(file_path.as_ref()));
+    let total = data.samples();
+
+    let input = Tracked::new(IqConverter::new(&mut data), total, progress);
+    let samp_rate = capture.samp_rate as f32;
+    let (magnitudes, tracked_freq): (Vec<_>, _) = match tracking {
+        Some(tracking) => {
+            let (track, magnitudes) = TrackingMagnitudes::new(input, samp_rate, freq,
+                settings, tracking).unzip();
+            (magnitudes, Some(track))
+        }
+        None => (Magnitudes::new(input, samp_rate, freq, settings).collect(), None),
+    };
+    if progress.is_cancelled() {
+        return Err("Loading cancelled".into());
+    }
+    try!(data.take_error());
 
-    let input = IqConverter::new(data).collect::<Vec<_>>();
     let mut buffer = vec![];
+    for magnitude in magnitudes {
+        edge_filter.input(magnitude);
+        buffer.push(edge_filter.output())
+    }
+
+    Ok(ScopeSource {
+        samp_rate: settings.output_rate(capture.samp_rate) as f32,
+        channels: vec![(Channel::Magnitude, buffer.into_iter())],
+        tracked_freq: tracked_freq,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use scope_app::test_util::TempCapture;
+
+    use super::*;
+
+    /// The bytes of a capture of `samples` I/Q samples
+    fn ramp(samples: usize) -> Vec<u8> {
+        (0..2 * samples).map(|x| (x % 64) as u8).collect()
+    }
 
-    let window_size = 1000;
-    let mut filter = GoertzelFilter::new(2e6 as f32, freq, window_size, window::blackman_harris);
-    for chunk in input.chunks(window_size) {
-        for &sample in chunk {
-            filter.input(sample.1, sample.0);
+    #[test]
+    fn streams_captures_with_progress() {
+        let capture = TempCapture::new("stream", &ramp(100000));
+        let settings = CaptureSettings { samp_rate: 1e5, display_rate: 1e5 };
+
+        let progress = Progress::new();
+        let source = raw_data(&capture.0, settings, &progress).unwrap();
+        assert_eq!(progress.total.load(Ordering::Relaxed), 100000);
+        assert!(progress.fraction() > 0.5);
+        for (_, channel) in source.channels {
+            assert_eq!(channel.len(), 100000);
         }
-        edge_filter.input(filter.output());
-        buffer.push(edge_filter.output())
     }
 
-    ScopeSource {
-        samp_rate: 2e6 / window_size as f32,
-        data: buffer.into_iter(),
+    #[test]
+    fn stops_when_cancelled() {
+        let capture = TempCapture::new("cancel", &ramp(1000));
+        let progress = Progress::new();
+        progress.cancel();
+        assert_eq!(raw_data(&capture.0, CaptureSettings::default(), &progress).err(),
+            Some("Loading cancelled".into()));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn reports_missing_captures() {
+        let path = env::temp_dir().join(format!("viscope_missing_{}.bin", process::id()));
+        let error = raw_data(&path, CaptureSettings::default(), &Progress::new()).err().unwrap();
+        assert!(error.starts_with("Unable to open"), "{}", error);
+    }
+}
@@ -0,0 +1,155 @@
+use std::f32;
+
+/// The number of buckets (or samples) combined into each bucket of the next level of detail
+const FACTOR: usize = 4;
+
+/// A signal together with the minimum and maximum of progressively larger buckets of its samples,
+/// so that any part of it can be drawn with a bounded number of points
+pub struct Pyramid {
+    samples: Vec<f32>,
+
+    /// The (min, max) of each bucket, where the buckets of level `i` span `FACTOR^(i + 1)` samples
+    levels: Vec<Vec<(f32, f32)>>,
+}
+
+impl Pyramid {
+    pub fn new(samples: Vec<f32>) -> Pyramid {
+        let mut levels = vec![];
+
+        let mut current: Vec<_> = samples.chunks(FACTOR).map(|x| {
+            x.iter().fold((f32::INFINITY, f32::NEG_INFINITY),
+                |acc, &x| (acc.0.min(x), acc.1.max(x)))
+        }).collect();
+        while current.len() > 1 {
+            let next = current.chunks(FACTOR).map(|x| {
+                x.iter().fold((f32::INFINITY, f32::NEG_INFINITY),
+                    |acc, &x| (acc.0.min(x.0), acc.1.max(x.1)))
+            }).collect();
+            levels.push(current);
+            current = next;
+        }
+        levels.push(current);
+
+        Pyramid {
+            samples: samples,
+            levels: levels,
+        }
+    }
+
+    /// The number of samples in the signal
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The samples of the signal
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    /// Gets the points to draw for the samples from `start` to `end`, as (sample index, value)
+    /// pairs. Uses the finest level of detail that needs no more than `max_points` buckets, with
+    /// the minimum and maximum of each bucket drawn at its centre. At most `2 * max_points + 2`
+    /// points are returned.
+    pub fn points(&self, start: usize, end: usize, max_points: usize) -> Vec<(f64, f32)> {
+        let end = end.min(self.samples.len());
+        let start = start.min(end);
+        let max_points = max_points.max(1);
+
+        if end - start <= max_points {
+            return (start..end).map(|i| (i as f64, self.samples[i])).collect();
+        }
+
+        let mut size = FACTOR;
+        for level in &self.levels {
+            // Include the partly visible buckets at either end
+            let first = start / size;
+            let last = ((end + size - 1) / size).min(level.len());
+
+            if last - first <= max_points + 1 {
+                let mut points = Vec::with_capacity(2 * (last - first));
+                for (i, &(min, max)) in level[first..last].iter().enumerate() {
+                    let centre = ((first + i) * size) as f64 + size as f64 / 2.0;
+                    points.push((centre, min));
+                    points.push((centre, max));
+                }
+                return points;
+            }
+
+            size *= FACTOR;
+        }
+
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pyramid whose samples are their own indices, so that each bucket's (min, max) is the
+    /// range of indices it covers
+    fn ramp(len: usize) -> Pyramid {
+        Pyramid::new((0..len).map(|x| x as f32).collect())
+    }
+
+    /// Splits points into (centre, min, max) buckets
+    fn split_buckets(points: &[(f64, f32)]) -> Vec<(f64, f32, f32)> {
+        points.chunks(2).map(|x| {
+            assert_eq!(x[0].0, x[1].0);
+            (x[0].0, x[0].1, x[1].1)
+        }).collect()
+    }
+
+    #[test]
+    fn draws_samples_when_there_are_few_enough() {
+        let pyramid = ramp(100);
+        assert_eq!(pyramid.points(10, 14, 4), vec![(10.0, 10.0), (11.0, 11.0), (12.0, 12.0),
+            (13.0, 13.0)]);
+        assert_eq!(pyramid.points(98, 200, 4), vec![(98.0, 98.0), (99.0, 99.0)]);
+        assert!(pyramid.points(200, 300, 4).is_empty());
+    }
+
+    #[test]
+    fn buckets_cover_the_visible_samples() {
+        // 800 samples don't fit in 50 points, or in the 200 buckets of 4, but do in buckets of 16
+        let pyramid = ramp(1000);
+        let buckets = split_buckets(&pyramid.points(100, 900, 50));
+        assert_eq!(buckets.len(), 900 / 16 + 1 - 100 / 16);
+
+        for (i, &(centre, min, max)) in buckets.iter().enumerate() {
+            let first = (100 / 16 + i) * 16;
+            assert_eq!((centre, min, max), (first as f64 + 8.0, first as f32, first as f32 + 15.0));
+        }
+
+        // The partly visible buckets at either end are included
+        assert!(buckets[0].1 <= 100.0 && buckets[buckets.len() - 1].2 >= 899.0);
+    }
+
+    #[test]
+    fn keeps_partial_buckets_at_the_end_of_the_signal() {
+        // 1001 samples leave a bucket of one sample at the finest level, which is merged into the
+        // last bucket of 256
+        let pyramid = ramp(1001);
+        let buckets = split_buckets(&pyramid.points(0, 1001, 10));
+        assert_eq!(buckets, vec![(128.0, 0.0, 255.0), (384.0, 256.0, 511.0), (640.0, 512.0, 767.0),
+            (896.0, 768.0, 1000.0)]);
+
+        // The end is clamped to the signal, leaving 11 samples that fit in two buckets of 16, the
+        // second of which only has 9 samples
+        let buckets = split_buckets(&pyramid.points(990, 2000, 2));
+        assert_eq!(buckets, vec![(984.0, 976.0, 991.0), (1000.0, 992.0, 1000.0)]);
+    }
+
+    #[test]
+    fn returns_at_most_twice_max_points_plus_two() {
+        let pyramid = Pyramid::new((0..5000).map(|x| ((x * 37) % 101) as f32).collect());
+        for &max_points in &[1, 2, 3, 7, 16, 50, 333] {
+            for &(start, end) in &[(0, 5000), (1, 4999), (3, 1500), (777, 4096), (4000, 4500)] {
+                let points = pyramid.points(start, end, max_points);
+                assert!(!points.is_empty());
+                assert!(points.len() <= 2 * max_points + 2, "{} points for {} from {} to {}",
+                    points.len(), max_points, start, end);
+            }
+        }
+    }
+}
@@ -1,58 +1,654 @@
+pub mod cursors;
+pub mod grid;
 pub mod loader;
+pub mod spectrum;
+pub mod trigger;
+pub mod waterfall;
+mod lod;
 mod renderer;
 
-use glium::{Display, Frame};
+#[cfg(test)]
+mod test_util;
 
-use scope_app::renderer::Renderer;
+use std::cmp::Ordering;
+use std::f32;
+use std::path::Path;
+use std::rc::Rc;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+use glium::{Display, Frame, Program, Rect, Surface};
+
+use iq_converter::goertzel::{FilterSettings, TrackingSettings};
+
+use scope_app::cursors::{Cursors, Readout, ViewMapping};
+use scope_app::grid::{AmplitudeScale, AxisLabel, Axes, Grid};
+use scope_app::loader::CaptureSettings;
+use scope_app::renderer::{line_program, Renderer};
+use scope_app::spectrum::{SpectrumSettings, SpectrumView};
+use scope_app::trigger::{find_triggers, Sweep, TriggerSettings};
+use scope_app::waterfall::WaterfallView;
+use window::Mouse;
+
+/// The fraction of the window's height taken by the spectrum panel, when it is shown
+pub const SPECTRUM_HEIGHT: f32 = 0.35;
+
+/// The fraction of the window's width taken by the waterfall panel, when it is shown
+pub const WATERFALL_WIDTH: f32 = 0.35;
+
+/// The range of the horizontal zoom, which is a power of 1.5
+pub const MIN_X_ZOOM: f32 = -10.0;
+pub const MAX_X_ZOOM: f32 = 20.0;
+
+/// The change in horizontal zoom for each step of the mouse wheel
+const WHEEL_ZOOM: f32 = 0.5;
+
+/// The narrowest range (in pixels) that can be selected to zoom into, so that a click with the
+/// right button doesn't zoom in
+const MIN_ZOOM_PIXELS: f32 = 4.0;
+
+#[derive(Copy, Clone, PartialEq)]
 pub enum SignalType {
     Raw,
-    Filtered(i64),
+    Filtered(i64, FilterSettings, Option<TrackingSettings>),
+}
+
+/// The quantities that can be displayed as traces
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Channel {
+    I,
+    Q,
+    Magnitude,
+    Phase,
+}
+
+/// Every channel, in the order they are listed in the GUI
+pub const CHANNELS: [Channel; 4] = [Channel::I, Channel::Q, Channel::Magnitude, Channel::Phase];
+
+impl Channel {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Channel::I => "I",
+            Channel::Q => "Q",
+            Channel::Magnitude => "|IQ|",
+            Channel::Phase => "Phase",
+        }
+    }
+
+    /// The colour the channel's trace is drawn in
+    pub fn color(&self) -> [f32; 3] {
+        match *self {
+            Channel::I => [0.0, 0.0, 0.0],
+            Channel::Q => [0.0, 0.4, 0.9],
+            Channel::Magnitude => [0.85, 0.1, 0.1],
+            Channel::Phase => [0.1, 0.6, 0.1],
+        }
+    }
 }
 
-#[derive(Copy, Clone, Default)]
+/// How the trace of a channel is displayed
+#[derive(Copy, Clone)]
+pub struct TraceSettings {
+    pub visible: bool,
+
+    /// The vertical offset of the trace, as a fraction of the height of the window
+    pub offset: f32,
+}
+
+#[derive(Copy, Clone)]
 pub struct PlaybackState {
     pub speed: f32,
     pub offset: f32,
     pub x_zoom: f32,
     pub y_zoom: f32,
+
+    /// The display settings of each channel, indexed by `Channel`
+    pub traces: [TraceSettings; 4],
+}
+
+impl Default for PlaybackState {
+    fn default() -> PlaybackState {
+        // Show the same traces as before channels could be selected: I for the raw signal and the
+        // magnitude for the filtered signal
+        let trace = |visible| TraceSettings { visible: visible, offset: 0.0 };
+
+        PlaybackState {
+            speed: 0.0,
+            offset: 0.0,
+            x_zoom: 0.0,
+            y_zoom: 0.0,
+            traces: [trace(true), trace(false), trace(true), trace(false)],
+        }
+    }
+}
+
+/// What the mouse is dragging in the scope
+#[derive(Copy, Clone)]
+enum Drag {
+    Cursor,
+
+    /// Panning the view, holding the given time (in seconds) under the mouse
+    Pan(f64),
+
+    /// Selecting a range to zoom into, from the given time (in seconds)
+    Zoom(f64),
+}
+
+/// Gets the horizontal zoom at which the scope spans `span` seconds
+fn zoom_for_span(span: f64) -> f32 {
+    let zoom = ((2.0 / span).ln() / (1.5_f64).ln()) as f32;
+    zoom.max(MIN_X_ZOOM).min(MAX_X_ZOOM)
 }
 
 pub struct ScopeSource<I: Iterator<Item=f32>> {
     pub samp_rate: f32,
-    pub data: I,
+
+    /// The samples of each channel in the signal
+    pub channels: Vec<(Channel, I)>,
+
+    /// The frequency the filter followed for each sample, when tracking a drifting tone
+    pub tracked_freq: Option<Vec<f32>>,
 }
 
 pub struct ScopeApp {
     renderer: Renderer,
+    grid: Grid,
+    amplitude_scale: AmplitudeScale,
+    spectrum: SpectrumView,
+    show_spectrum: bool,
+    waterfall: WaterfallView,
+
+    /// The range (in dB) of powers shown in the waterfall, or `None` if it is hidden
+    waterfall_range: Option<f32>,
     playback: PlaybackState,
+    tracked_freq: Option<Vec<f32>>,
+    trigger: Option<TriggerSettings>,
+
+    /// The times (in seconds) at which the trigger condition is met in the loaded signal
+    triggers: Vec<f64>,
+
+    /// The time of the trigger the view is locked on, if any
+    triggered: Option<f64>,
+
+    /// Whether the view has been panned or zoomed away from the trigger, which frees it until
+    /// playback resumes, a trigger is stepped to or the trigger settings change
+    trigger_released: bool,
+    cursors: Option<Cursors>,
+
+    /// Whether the left and right mouse buttons were down in the last frame
+    mouse_down: (bool, bool),
+    mouse_x: f32,
+    drag: Option<Drag>,
+
+    /// The program for drawing lines, shared by the panels and the cursors
+    shaders: Rc<Program>,
 }
 
 impl ScopeApp {
     pub fn new(display: &Display) -> ScopeApp {
+        let shaders = Rc::new(line_program(display));
+
         ScopeApp {
-            renderer: Renderer::init(&display, 0.0),
+            renderer: Renderer::init(display, shaders.clone(), 0.0),
+            grid: Grid::new(display, shaders.clone()),
+            amplitude_scale: AmplitudeScale::Linear,
+            spectrum: SpectrumView::new(display, shaders.clone()),
+            show_spectrum: false,
+            waterfall: WaterfallView::new(display, shaders.clone()),
+            waterfall_range: None,
             playback: PlaybackState::default(),
+            tracked_freq: None,
+            trigger: None,
+            triggers: vec![],
+            triggered: None,
+            trigger_released: false,
+            cursors: None,
+            mouse_down: (false, false),
+            mouse_x: 0.0,
+            drag: None,
+            shaders: shaders,
         }
     }
 
-    pub fn load<I: Iterator<Item=f32>>(&mut self, display: &Display, source: ScopeSource<I>) {
+    pub fn load<I: Iterator<Item=f32>>(&mut self, display: &Display, mut source: ScopeSource<I>,
+        playback: &mut PlaybackState)
+    {
+        self.tracked_freq = source.tracked_freq.take();
         self.renderer.reload(display, source);
+        self.find_triggers();
+
+        // Start the new signal from the beginning if the offset isn't a position within it
+        if !(playback.offset >= 0.0 && playback.offset <= 1.0) {
+            playback.offset = 0.0;
+        }
+    }
+
+    /// Sets the trigger that the view locks on to, or frees the view if `settings` is `None`
+    pub fn set_trigger(&mut self, settings: Option<TriggerSettings>) {
+        // Only the channel, condition and level change where the triggers are
+        let source = |x: Option<TriggerSettings>| x.map(|x| (x.channel, x.condition, x.level));
+        let changed = source(settings) != source(self.trigger);
+
+        if settings != self.trigger {
+            self.trigger_released = false;
+        }
+        self.trigger = settings;
+        if changed {
+            self.find_triggers();
+        }
+    }
+
+    /// Gets the number of triggers in the loaded signal, if triggering is enabled
+    pub fn trigger_count(&self) -> Option<usize> {
+        self.trigger.map(|_| self.triggers.len())
+    }
+
+    /// Moves playback to the next trigger after the one currently shown, or the previous one
+    /// before it if `forward` is false
+    pub fn step_trigger(&mut self, playback: &mut PlaybackState, forward: bool) {
+        let length = self.length() as f64;
+        if self.trigger.is_none() || !(length > 0.0) {
+            return;
+        }
+
+        let current = self.triggered.unwrap_or(playback.offset as f64 * length);
+        let tolerance = self.trigger_tolerance();
+        let next = match forward {
+            true => self.triggers.iter().cloned().find(|&t| t > current + tolerance),
+            false => self.triggers.iter().cloned().rev().find(|&t| t < current - tolerance),
+        };
+
+        if let Some(time) = next {
+            playback.offset = (time / length) as f32;
+            self.trigger_released = false;
+        }
+    }
+
+    fn find_triggers(&mut self) {
+        self.triggers = match self.trigger {
+            Some(settings) => {
+                let samp_rate = self.renderer.get_samp_rate();
+                self.renderer.samples(settings.channel).map(|samples| {
+                    find_triggers(samples, samp_rate, settings.condition, settings.level).iter()
+                        .map(|&i| i as f64 / samp_rate as f64)
+                        .collect()
+                }).unwrap_or(vec![])
+            }
+            None => vec![],
+        };
+        self.triggered = None;
+    }
+
+    /// Gets how far (in seconds) a trigger can be after the playback position and still count as
+    /// reached, allowing for a sample's error and the rounding of the playback offset
+    fn trigger_tolerance(&self) -> f64 {
+        let sample = 1.0 / self.renderer.get_samp_rate() as f64;
+        sample.max(self.length() as f64 * f32::EPSILON as f64)
+    }
+
+    /// Gets the number of triggers at or before `time` seconds into the signal
+    fn triggers_until(&self, time: f64) -> usize {
+        // The triggers are in order, so the count is where a trigger just after `time` would go
+        self.triggers.binary_search_by(|&t| match t <= time {
+            true => Ordering::Less,
+            false => Ordering::Greater,
+        }).unwrap_or_else(|i| i)
+    }
+
+    /// Gets the time (in seconds) of the trigger to show at `time` seconds into the signal
+    fn trigger_at(&self, time: f64) -> Option<f64> {
+        let settings = match self.trigger {
+            Some(settings) => settings,
+            None => return None,
+        };
+
+        let count = self.triggers_until(time + self.trigger_tolerance());
+        let latest = match count {
+            0 => None,
+            _ => Some(self.triggers[count - 1]),
+        };
+
+        match settings.sweep {
+            Sweep::Auto => latest.and_then(|t| match time - t <= self.view_span() {
+                true => Some(t),
+                false => None,
+            }),
+            Sweep::Normal | Sweep::Single => latest,
+        }
+    }
+
+    /// Gets the time (in seconds) spanned by the scope view
+    fn view_span(&self) -> f64 {
+        2.0 / (1.5_f64).powf(self.playback.x_zoom as f64)
+    }
+
+    /// Gets the time (in seconds) at the left of the scope view, which places the trigger being
+    /// shown at the trigger position, or otherwise starts at the playback position
+    fn view_start(&self) -> f64 {
+        match (self.trigger, self.triggered) {
+            (Some(settings), Some(time)) => time - settings.position as f64 * self.view_span(),
+            _ => self.playback.offset as f64 * self.length() as f64,
+        }
+    }
+
+    /// Gets the tracked filter frequency at the current playback offset, if the loaded signal was
+    /// filtered with drift tracking enabled
+    pub fn tracked_freq(&self) -> Option<f32> {
+        self.tracked_freq.as_ref().and_then(|track| {
+            let index = (self.playback.offset * track.len() as f32) as usize;
+            track.get(index.min(track.len().saturating_sub(1))).cloned()
+        })
+    }
+
+    /// Gets the length of the loaded signal in seconds
+    pub fn length(&self) -> f32 {
+        self.renderer.get_length()
+    }
+
+    /// Updates the spectrum of the capture at `path` around the middle of the scope view, or hides
+    /// the spectrum panel if `settings` is `None`
+    pub fn update_spectrum(&mut self, path: &Path, capture: CaptureSettings,
+        settings: Option<SpectrumSettings>, filter_freq: f32) -> Result<(), String>
+    {
+        self.show_spectrum = settings.is_some();
+        match settings {
+            Some(settings) => {
+                let time = self.view_start() + self.view_span() / 2.0;
+                self.spectrum.update(path, capture, time, settings, filter_freq)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Adds newly computed rows to the waterfall of the capture at `path`, starting to compute it
+    /// if the capture has changed. The waterfall panel shows powers down to `dynamic_range` dB
+    /// below the strongest, and is hidden if `dynamic_range` is `None`.
+    pub fn update_waterfall(&mut self, display: &Display, path: &Path, capture: CaptureSettings,
+        dynamic_range: Option<f32>) -> Result<(), String>
+    {
+        self.waterfall_range = dynamic_range;
+        if dynamic_range.is_none() {
+            return Ok(());
+        }
+
+        try!(self.waterfall.load(display, path, capture));
+        self.waterfall.update()
+    }
+
+    /// Gets the fraction of the waterfall computed so far, if it is shown
+    pub fn waterfall_progress(&self) -> Option<f32> {
+        self.waterfall_range.map(|_| self.waterfall.progress())
+    }
+
+    /// Gets the power (in dB) at the bottom and top of the spectrum panel, if it is shown
+    pub fn spectrum_range(&self) -> Option<(f32, f32)> {
+        match self.show_spectrum {
+            true => Some(self.spectrum.range()),
+            false => None,
+        }
+    }
+
+    /// Gets the frequency of the spectrum peak nearest to a click at `pos` (in pixels from the top
+    /// left of a window of `size` pixels), if the click was in the spectrum panel
+    fn spectrum_click(&self, pos: (f32, f32), size: (u32, u32)) -> Option<f64> {
+        let (width, height) = (size.0 as f32, size.1 as f32);
+        if !self.show_spectrum || pos.1 < height * (1.0 - SPECTRUM_HEIGHT) || !(width > 0.0) {
+            return None;
+        }
+
+        let freq = (pos.0 / width - 0.5) as f64 * self.spectrum.samp_rate();
+        self.spectrum.peak_near(freq)
+    }
+
+    /// Gets the viewport of the spectrum panel in a window of `size` pixels
+    fn spectrum_rect(&self, size: (u32, u32)) -> Rect {
+        let height = match self.show_spectrum {
+            true => (size.1 as f32 * SPECTRUM_HEIGHT) as u32,
+            false => 0,
+        };
+        Rect { left: 0, bottom: 0, width: size.0, height: height }
+    }
+
+    /// Gets the viewport of the scope in a window of `size` pixels, which is above the spectrum
+    /// panel and shares its width with the waterfall
+    fn scope_rect(&self, size: (u32, u32)) -> Rect {
+        let bottom = self.spectrum_rect(size).height;
+        let width = match self.waterfall_range {
+            Some(_) => (size.0 as f32 * (1.0 - WATERFALL_WIDTH)) as u32,
+            None => size.0,
+        };
+        Rect { left: 0, bottom: bottom, width: width, height: size.1 - bottom }
+    }
+
+    /// Gets the mapping between pixels in the scope and the time and amplitude of a channel's
+    /// trace, in a window of `size` pixels
+    fn scope_view(&self, size: (u32, u32), channel: Channel) -> ViewMapping {
+        ViewMapping {
+            start: self.view_start(),
+            span: self.view_span(),
+            y_scale: (1.5_f32).powf(self.playback.y_zoom),
+            y_offset: self.playback.traces[channel as usize].offset,
+            rect: self.scope_rect(size),
+            window_size: size,
+        }
+    }
+
+    /// Gets the mapping between pixels in the scope and the time and amplitude of a trace without
+    /// any offset, which the axes are drawn for
+    fn axis_view(&self, size: (u32, u32)) -> ViewMapping {
+        let mut view = self.scope_view(size, Channel::Magnitude);
+        view.y_offset = 0.0;
+        view
+    }
+
+    /// Gets the grid lines of `view`. The amplitude lines are only labelled while none of the
+    /// shown traces are offset, since the labels wouldn't match the traces that are.
+    fn axes(&self, view: &ViewMapping) -> Axes {
+        let offset = CHANNELS.iter().any(|&channel| {
+            let trace = self.playback.traces[channel as usize];
+            trace.visible && trace.offset != 0.0 && self.renderer.samples(channel).is_some()
+        });
+        Axes::new(view, self.amplitude_scale, !offset)
+    }
+
+    pub fn set_amplitude_scale(&mut self, scale: AmplitudeScale) {
+        self.amplitude_scale = scale;
+    }
+
+    /// Gets the labels of the scope's grid lines in a window of `size` pixels
+    pub fn axis_labels(&self, size: (u32, u32)) -> Vec<AxisLabel> {
+        let view = self.axis_view(size);
+        self.axes(&view).labels(&view)
+    }
+
+    /// Shows measurement cursors on `channel`, or hides them if `channel` is `None`. New cursors
+    /// are placed within the current view.
+    pub fn set_cursors(&mut self, display: &Display, channel: Option<Channel>) {
+        match (channel, self.cursors.as_mut()) {
+            (Some(channel), Some(cursors)) => {
+                cursors.channel = channel;
+                return;
+            }
+            _ => {}
+        }
+
+        let size = display.get_framebuffer_dimensions();
+        self.cursors = channel.map(|channel| {
+            Cursors::new(display, self.shaders.clone(), channel, &self.scope_view(size, channel))
+        });
+    }
+
+    /// Gets the cursor positions and the signal under them, if the cursors are shown
+    pub fn cursor_readout(&self) -> Option<Readout> {
+        self.cursors.as_ref().map(|cursors| {
+            let samples = self.renderer.samples(cursors.channel).unwrap_or(&[]);
+            cursors.readout(samples, self.renderer.get_samp_rate())
+        })
+    }
+
+    /// Handles the mouse over the scope, in a window of `size` pixels: dragging with the left
+    /// button moves a cursor or pans the view, dragging with the right button selects a time
+    /// range to zoom into, and the wheel zooms around the mouse. The mouse is left to the GUI
+    /// while it is over it. Returns the frequency to tune the filter to if the spectrum was
+    /// clicked.
+    pub fn handle_mouse(&mut self, mouse: &Mouse, size: (u32, u32), playback: &mut PlaybackState)
+        -> Option<f64>
+    {
+        let (left, right) = (mouse.pressed.0, mouse.pressed.1);
+        let channel = self.cursors.as_ref().map_or(Channel::Magnitude, |x| x.channel);
+        let view = self.scope_view(size, channel);
+        let over_scope = !mouse.in_gui && view.contains(mouse.pos);
+
+        // Clicking on the spectrum tunes the filter, but dragging into it from the scope doesn't
+        let mut tuned = None;
+        if self.drag.is_none() && left && !self.mouse_down.0 && !mouse.in_gui {
+            tuned = self.spectrum_click(mouse.pos, size);
+        }
+
+        if self.drag.is_none() && over_scope {
+            if left && !self.mouse_down.0 {
+                let grabbed = match self.cursors {
+                    Some(ref mut cursors) => cursors.grab(mouse.pos, &view),
+                    None => false,
+                };
+                self.drag = match grabbed {
+                    true => Some(Drag::Cursor),
+                    false => Some(Drag::Pan(view.time_at(mouse.pos.0))),
+                };
+            }
+            else if right && !self.mouse_down.1 {
+                self.drag = Some(Drag::Zoom(view.time_at(mouse.pos.0)));
+            }
+        }
+        self.mouse_down = (left, right);
+        self.mouse_x = mouse.pos.0;
+
+        // The fraction of the way across the view that the mouse is
+        let fraction = (mouse.pos.0 - view.rect.left as f32) as f64 / view.rect.width as f64;
+
+        match self.drag {
+            Some(Drag::Cursor) if left => {
+                let samp_rate = self.renderer.get_samp_rate();
+                if let Some(ref mut cursors) = self.cursors {
+                    let samples = self.renderer.samples(cursors.channel).unwrap_or(&[]);
+                    cursors.drag(mouse.pos, &view, samples, samp_rate);
+                }
+            }
+            Some(Drag::Pan(time)) if left => {
+                // Keep the time that was grabbed under the mouse
+                self.set_view_start(playback, time - fraction * view.span);
+            }
+            Some(Drag::Zoom(_)) if right => {}
+            Some(Drag::Zoom(start)) => {
+                self.drag = None;
+                if (view.x_of(start) - mouse.pos.0).abs() >= MIN_ZOOM_PIXELS {
+                    let end = view.time_at(mouse.pos.0);
+                    playback.x_zoom = zoom_for_span((end - start).abs());
+                    self.set_view_start(playback, start.min(end));
+                }
+            }
+            Some(_) => {
+                self.drag = None;
+                if let Some(ref mut cursors) = self.cursors {
+                    cursors.release();
+                }
+            }
+            None if over_scope && mouse.wheel != 0.0 => {
+                // Keep the time under the mouse fixed while zooming
+                let time = view.time_at(mouse.pos.0);
+                playback.x_zoom = (playback.x_zoom + mouse.wheel * WHEEL_ZOOM)
+                    .max(MIN_X_ZOOM).min(MAX_X_ZOOM);
+                let span = 2.0 / (1.5_f64).powf(playback.x_zoom as f64);
+                self.set_view_start(playback, time - fraction * span);
+            }
+            None => {}
+        }
+
+        tuned
+    }
+
+    /// Moves playback so that the view starts at `start` seconds, within the signal
+    fn set_view_start(&mut self, playback: &mut PlaybackState, start: f64) {
+        let length = self.length() as f64;
+        if length > 0.0 {
+            playback.offset = (start / length).max(0.0).min(1.0) as f32;
+
+            // The view is no longer placed by the trigger, so free it to stay where it was moved
+            self.triggered = None;
+            self.trigger_released = self.trigger.is_some();
+        }
     }
 
     pub fn draw(&self, frame: &mut Frame) {
-        self.renderer.draw(self.playback, frame);
+        let size = frame.get_dimensions();
+
+        if self.show_spectrum {
+            self.spectrum.draw(frame, self.spectrum_rect(size));
+        }
+
+        // The waterfall sits beside the trace, sharing its time span
+        let scope = self.scope_rect(size);
+        if let Some(dynamic_range) = self.waterfall_range {
+            let time = self.playback.offset as f64 * self.length() as f64;
+            let left = scope.width;
+            let rect = Rect { left: left, bottom: scope.bottom, width: size.0 - left,
+                height: scope.height };
+            self.waterfall.draw(self.view_start(), self.view_span(), time, dynamic_range, frame,
+                rect);
+        }
+
+        let view = self.axis_view(size);
+        self.grid.draw(frame, &view, &self.axes(&view));
+
+        self.renderer.draw(self.playback, self.view_start(), frame, scope);
+        if let Some(settings) = self.trigger {
+            self.renderer.draw_trigger(self.playback, settings, frame, scope);
+        }
+        if let Some(ref cursors) = self.cursors {
+            cursors.draw(frame, &self.scope_view(size, cursors.channel));
+        }
+        if let Some(Drag::Zoom(start)) = self.drag {
+            let view = self.scope_view(size, Channel::Magnitude);
+            let from = (view.x_of(start) - scope.left as f32) / scope.width as f32;
+            let to = (self.mouse_x - scope.left as f32) / scope.width as f32;
+            self.renderer.draw_range(from, to, frame, scope);
+        }
     }
 
     pub fn update(&mut self, dt: f32, playback: &mut PlaybackState) {
-        playback.offset += dt * playback.speed / self.renderer.get_length();
+        let length = self.renderer.get_length();
+        let start = playback.offset as f64 * length as f64;
+
+        // Nothing plays until a signal has been loaded
+        if length > 0.0 {
+            playback.offset += dt * playback.speed / length;
+        }
 
         if playback.offset > 1.0 {
             playback.offset = 1.0;
             playback.speed = 0.0;
         }
 
+        // A single sweep stops at the first trigger played through
+        let end = playback.offset as f64 * length as f64;
+        if let Some(settings) = self.trigger {
+            let next = self.triggers.get(self.triggers_until(start)).cloned()
+                .and_then(|t| match t <= end {
+                    true => Some(t),
+                    false => None,
+                });
+            if let (Sweep::Single, Some(time)) = (settings.sweep, next) {
+                playback.offset = (time / length as f64) as f32;
+                playback.speed = 0.0;
+            }
+        }
+
+        if playback.speed != 0.0 {
+            self.trigger_released = false;
+        }
+
         self.playback = *playback;
+        self.triggered = match self.trigger_released {
+            true => None,
+            false => self.trigger_at(playback.offset as f64 * length as f64),
+        };
     }
 }
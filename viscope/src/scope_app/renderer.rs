@@ -1,12 +1,22 @@
 use std::f32;
+use std::rc::Rc;
 
-use glium::{index, Surface, Display, Frame};
+use glium::{index, Surface, Display, Frame, Rect, DrawParameters};
 use glium::{VertexBuffer, Program};
 
-use super::{ScopeSource, PlaybackState};
+use super::{Channel, ScopeSource, PlaybackState};
+use super::lod::Pyramid;
+use super::trigger::TriggerSettings;
+
+/// The most buckets drawn across the window for each trace. Several per pixel column keeps the
+/// min/max envelope looking the same as drawing every sample.
+const MAX_POINTS: usize = 4096;
+
+const TRIGGER_COLOR: [f32; 3] = [0.6, 0.6, 0.6];
+const RANGE_COLOR: [f32; 3] = [0.3, 0.3, 0.3];
 
 #[derive(Copy, Clone)]
-struct Vertex {
+pub struct Vertex {
     pub position: [f32; 2],
 }
 
@@ -20,54 +30,174 @@ impl Vertex {
 
 implement_vertex!(Vertex, position);
 
-pub struct Renderer {
-    shaders: Program,
+/// Compiles the program that draws traces and lines in a single colour, which is shared by the
+/// scope's panels
+pub fn line_program(display: &Display) -> Program {
+    program!(display,
+        140 => {
+            vertex: include_str!("../../shaders/vshader.glsl"),
+            fragment: include_str!("../../shaders/fshader.glsl"),
+        }
+    ).unwrap()
+}
+
+/// A single channel's trace. Only the visible part of the signal is uploaded, at a level of detail
+/// suited to the zoom, so the vertex buffer is the same size however long the signal is.
+struct Trace {
+    channel: Channel,
+    pyramid: Pyramid,
     vertex_buffer: VertexBuffer<Vertex>,
+}
+
+pub struct Renderer {
+    shaders: Rc<Program>,
+    traces: Vec<Trace>,
+    trigger_marker: VertexBuffer<Vertex>,
+    range_marker: VertexBuffer<Vertex>,
     samp_rate: f32,
     length: f32,
 }
 
 impl Renderer {
-    pub fn init(display: &Display, samp_rate: f32) -> Renderer {
-        let shaders = program!(display,
-            140 => {
-                vertex: include_str!("../../shaders/vshader.glsl"),
-                fragment: include_str!("../../shaders/fshader.glsl"),
-            }
-        ).unwrap();
-
+    pub fn init(display: &Display, shaders: Rc<Program>, samp_rate: f32) -> Renderer {
         Renderer {
             shaders: shaders,
-            vertex_buffer: VertexBuffer::new(display, &vec![]).unwrap(),
+            traces: vec![],
+            trigger_marker: VertexBuffer::empty_dynamic(display, 4).unwrap(),
+            range_marker: VertexBuffer::empty_dynamic(display, 4).unwrap(),
             samp_rate: samp_rate,
             length: 0.0,
         }
     }
 
-    pub fn draw(&self, state: PlaybackState, frame: &mut Frame) {
-        let uniforms = uniform! {
-            dt: state.offset * self.length,
-            x_scale: (1.5_f32).powf(state.x_zoom),
-            y_scale: (1.5_f32).powf(state.y_zoom),
+    /// Draws the traces from `view_start` seconds into the signal
+    pub fn draw(&self, state: PlaybackState, view_start: f64, frame: &mut Frame, viewport: Rect) {
+        let params = DrawParameters {
+            viewport: Some(viewport),
+            .. Default::default()
         };
         let index_buffer = index::NoIndices(index::PrimitiveType::LineStrip);
 
-        frame.draw(&self.vertex_buffer, &index_buffer, &self.shaders, &uniforms,
-            &Default::default()).unwrap();
+        // The window spans 2 / x_scale seconds
+        let x_scale = (1.5_f32).powf(state.x_zoom);
+        let view_end = view_start + 2.0 / x_scale as f64;
+
+        for trace in &self.traces {
+            let settings = state.traces[trace.channel as usize];
+            if !settings.visible {
+                continue;
+            }
+
+            let samp_rate = self.samp_rate as f64;
+            let start = (view_start * samp_rate).floor().max(0.0) as usize;
+            let end = (view_end * samp_rate).ceil() as usize + 1;
+
+            // Vertices are positioned relative to the first sample drawn, so that they keep their
+            // precision far into long signals
+            let origin = start as f64 / samp_rate;
+            let mut vertices: Vec<_> = trace.pyramid.points(start, end, MAX_POINTS).iter()
+                .map(|&(i, y)| Vertex::new((i / samp_rate - origin) as f32, y))
+                .collect();
+            if vertices.is_empty() {
+                continue;
+            }
+            vertices.truncate(trace.vertex_buffer.len());
+
+            let slice = trace.vertex_buffer.slice(0..vertices.len()).unwrap();
+            slice.write(&vertices);
+
+            let uniforms = uniform! {
+                dt: (view_start - origin) as f32,
+                x_scale: x_scale,
+                y_scale: (1.5_f32).powf(state.y_zoom),
+                y_offset: settings.offset,
+                color: trace.channel.color(),
+            };
+
+            frame.draw(slice, &index_buffer, &self.shaders, &uniforms, &params).unwrap();
+        }
+    }
+
+    /// Draws the trigger level across the view, and a line at the trigger position
+    pub fn draw_trigger(&self, state: PlaybackState, settings: TriggerSettings, frame: &mut Frame,
+        viewport: Rect)
+    {
+        let params = DrawParameters {
+            viewport: Some(viewport),
+            .. Default::default()
+        };
+
+        let x = 2.0 * settings.position;
+        self.trigger_marker.write(&[Vertex::new(0.0, settings.level),
+            Vertex::new(2.0, settings.level), Vertex::new(x, -1.0), Vertex::new(x, 1.0)]);
+
+        // The level is scaled like the trigger channel's trace, while the position line spans the
+        // whole height
+        let uniforms = |y_scale: f32, y_offset: f32| uniform! {
+            dt: 0.0_f32,
+            x_scale: 1.0_f32,
+            y_scale: y_scale,
+            y_offset: y_offset,
+            color: TRIGGER_COLOR,
+        };
+        let trace = state.traces[settings.channel as usize];
+        let lines = index::NoIndices(index::PrimitiveType::LinesList);
+        frame.draw(self.trigger_marker.slice(0..2).unwrap(), &lines, &self.shaders,
+            &uniforms((1.5_f32).powf(state.y_zoom), trace.offset), &params).unwrap();
+        frame.draw(self.trigger_marker.slice(2..4).unwrap(), &lines, &self.shaders,
+            &uniforms(1.0, 0.0), &params).unwrap();
+    }
+
+    /// Outlines the range being selected to zoom into, between fractions `from` and `to` of the
+    /// width of the view
+    pub fn draw_range(&self, from: f32, to: f32, frame: &mut Frame, viewport: Rect) {
+        let params = DrawParameters {
+            viewport: Some(viewport),
+            .. Default::default()
+        };
+
+        let (from, to) = (2.0 * from, 2.0 * to);
+        self.range_marker.write(&[Vertex::new(from, -0.99), Vertex::new(to, -0.99),
+            Vertex::new(to, 0.99), Vertex::new(from, 0.99)]);
+
+        let uniforms = uniform! {
+            dt: 0.0_f32,
+            x_scale: 1.0_f32,
+            y_scale: 1.0_f32,
+            y_offset: 0.0_f32,
+            color: RANGE_COLOR,
+        };
+        frame.draw(&self.range_marker, &index::NoIndices(index::PrimitiveType::LineLoop),
+            &self.shaders, &uniforms, &params).unwrap();
     }
 
     pub fn reload<I: Iterator<Item=f32>>(&mut self, display: &Display, source: ScopeSource<I>) {
         self.samp_rate = source.samp_rate;
+        self.length = 0.0;
+        self.traces.clear();
 
-        let vertices: Vec<_> = source.data.enumerate()
-            .map(|(x, y)| Vertex::new(x as f32 / self.samp_rate, y))
-            .collect();
+        for (channel, data) in source.channels {
+            let pyramid = Pyramid::new(data.collect());
 
-        self.length = vertices.len() as f32 / self.samp_rate;
-        self.vertex_buffer = VertexBuffer::new(display, &vertices).unwrap();
+            self.length = self.length.max(pyramid.len() as f32 / self.samp_rate);
+            self.traces.push(Trace {
+                channel: channel,
+                pyramid: pyramid,
+                vertex_buffer: VertexBuffer::empty_dynamic(display, 2 * MAX_POINTS + 2).unwrap(),
+            });
+        }
     }
 
     pub fn get_length(&self) -> f32 {
         self.length
     }
+
+    pub fn get_samp_rate(&self) -> f32 {
+        self.samp_rate
+    }
+
+    /// Gets the samples of a channel, if it is in the loaded signal
+    pub fn samples(&self, channel: Channel) -> Option<&[f32]> {
+        self.traces.iter().find(|x| x.channel == channel).map(|x| x.pyramid.samples())
+    }
 }
@@ -0,0 +1,305 @@
+use std::f32;
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use glium::{index, Surface, Display, Frame, Rect, DrawParameters};
+use glium::{VertexBuffer, Program};
+
+use iq_converter::IqConverter;
+use iq_converter::goertzel::FilterSettings;
+use iq_converter::spectrum::{bin_frequency, frequency_bin, to_db, Spectra};
+use iq_converter::window::Window;
+
+use super::loader::{CaptureSettings, LoadJob};
+use super::renderer::Vertex;
+
+/// The largest FFT size that can be selected
+pub const MAX_FFT_SIZE: usize = 16384;
+
+/// The most FFTs that can be averaged, which bounds how much of the capture is read for each
+/// spectrum
+pub const MAX_AVERAGES: usize = 64;
+
+/// The number of bins either side of a click that are searched for a peak
+const PEAK_SEARCH_BINS: usize = 8;
+
+/// The space (in dB) left above the highest peak
+const HEADROOM: f32 = 3.0;
+
+const TRACE_COLOR: [f32; 3] = [0.0, 0.0, 0.0];
+const MARKER_COLOR: [f32; 3] = [0.85, 0.1, 0.1];
+
+/// Settings for the spectrum around the scope view
+#[derive(Copy, Clone, PartialEq)]
+pub struct SpectrumSettings {
+    pub window: Window,
+
+    /// The number of samples in each FFT, which must be a power of two
+    pub size: usize,
+
+    /// The number of consecutive FFTs averaged together, up to `MAX_AVERAGES`
+    pub averages: usize,
+}
+
+/// Reads up to `count` I/Q samples of a capture centred on the sample `centre`, moving them back
+/// from the end of the capture if needed
+fn read_samples(path: &Path, centre: usize, count: usize) -> Result<Vec<(f32, f32)>, String> {
+    let mut data = vec![];
+    try!(File::open(path).and_then(|mut file| {
+        let total = (try!(file.metadata()).len() / 2) as usize;
+        let start = centre.saturating_sub(count / 2).min(total.saturating_sub(count));
+
+        try!(file.seek(SeekFrom::Start(2 * start as u64)));
+        file.take(2 * count as u64).read_to_end(&mut data)
+    }).map_err(|e| format!("Unable to read {}: {}", path.display(), e)));
+
+    Ok(IqConverter::new(data).collect())
+}
+
+/// Computes the average power spectrum (in dB) of the part of a capture centred on `time` seconds.
+/// The spectrum is empty if the capture is shorter than a single FFT.
+pub fn spectrum_at(path: &Path, capture: CaptureSettings, time: f64, settings: SpectrumSettings)
+    -> Result<Vec<f32>, String>
+{
+    let centre = (time * capture.samp_rate).max(0.0) as usize;
+    let averages = settings.averages.max(1).min(MAX_AVERAGES);
+    let samples = try!(read_samples(path, centre, settings.size * averages));
+
+    let fft = FilterSettings {
+        window: settings.window,
+        block_size: settings.size,
+        hop: settings.size,
+    };
+
+    let mut total = vec![0.0; settings.size];
+    let mut count = 0;
+    for spectrum in Spectra::new(samples, fft) {
+        for (sum, power) in total.iter_mut().zip(spectrum) {
+            *sum += power;
+        }
+        count += 1;
+    }
+
+    match count {
+        0 => Ok(vec![]),
+        _ => Ok(total.iter().map(|&x| to_db(x / count as f32)).collect()),
+    }
+}
+
+/// The part of a capture, and the settings, that a spectrum is computed for
+#[derive(Clone, PartialEq)]
+struct Request {
+    path: PathBuf,
+    block: usize,
+    settings: SpectrumSettings,
+    capture: CaptureSettings,
+}
+
+/// A spectrum being computed on a worker thread
+struct Job {
+    request: Request,
+    work: LoadJob<Vec<f32>>,
+}
+
+impl Job {
+    fn spawn(request: Request, time: f64) -> Job {
+        let (path, capture, settings) = (request.path.clone(), request.capture, request.settings);
+        Job {
+            request: request,
+            work: LoadJob::spawn(move |_| spectrum_at(&path, capture, time, settings)),
+        }
+    }
+}
+
+/// A panel showing the spectrum of the capture around the scope view. The spectrum is computed on
+/// a worker thread, one at a time, so the panel may lag behind during playback.
+pub struct SpectrumView {
+    shaders: Rc<Program>,
+    vertex_buffer: VertexBuffer<Vertex>,
+    marker: VertexBuffer<Vertex>,
+
+    power: Vec<f32>,
+    range: (f32, f32),
+    samp_rate: f64,
+    filter_freq: f32,
+
+    /// The part of the capture, and the settings, that the spectrum shown was computed for
+    computed: Option<Request>,
+
+    /// The spectrum being computed, if any
+    job: Option<Job>,
+}
+
+impl SpectrumView {
+    pub fn new(display: &Display, shaders: Rc<Program>) -> SpectrumView {
+        SpectrumView {
+            shaders: shaders,
+            vertex_buffer: VertexBuffer::empty_dynamic(display, MAX_FFT_SIZE).unwrap(),
+            marker: VertexBuffer::empty_dynamic(display, 2).unwrap(),
+            power: vec![],
+            range: (0.0, 0.0),
+            samp_rate: 0.0,
+            filter_freq: 0.0,
+            computed: None,
+            job: None,
+        }
+    }
+
+    /// Updates the spectrum for the time `time` (in seconds), showing any spectrum that has
+    /// finished computing and starting another once the time has moved into another FFT block or
+    /// the settings have changed
+    pub fn update(&mut self, path: &Path, capture: CaptureSettings, time: f64,
+        settings: SpectrumSettings, filter_freq: f32) -> Result<(), String>
+    {
+        self.filter_freq = filter_freq;
+
+        let finished = match self.job {
+            Some(ref job) => job.work.poll().map(|result| (job.request.clone(), result)),
+            None => None,
+        };
+        if let Some((request, result)) = finished {
+            self.job = None;
+            self.computed = Some(request.clone());
+            self.show(try!(result), request.capture.samp_rate);
+        }
+
+        let request = Request {
+            path: path.to_path_buf(),
+            block: (time * capture.samp_rate).max(0.0) as usize / settings.size,
+            settings: settings,
+            capture: capture,
+        };
+        if self.job.is_none() && self.computed.as_ref() != Some(&request) {
+            self.job = Some(Job::spawn(request, time));
+        }
+
+        Ok(())
+    }
+
+    /// Shows a newly computed spectrum of a capture sampled at `samp_rate`
+    fn show(&mut self, power: Vec<f32>, samp_rate: f64) {
+        self.power = power;
+        self.samp_rate = samp_rate;
+        if self.power.is_empty() {
+            return;
+        }
+
+        let low = self.power.iter().cloned().fold(f32::INFINITY, f32::min);
+        let high = self.power.iter().cloned().fold(f32::NEG_INFINITY, f32::max) + HEADROOM;
+        self.range = (low, high);
+
+        let size = self.power.len();
+        let vertices: Vec<_> = self.power.iter().enumerate().map(|(bin, &power)| {
+            // Bins are centred on their frequency, so that bin `size / 2` is at the centre
+            let x = 2.0 * bin as f32 / size as f32;
+            Vertex::new(x, 2.0 * (power - low) / (high - low) - 1.0)
+        }).collect();
+        self.vertex_buffer.slice(0..size).unwrap().write(&vertices);
+    }
+
+    /// Gets the sample rate of the capture the spectrum was computed from
+    pub fn samp_rate(&self) -> f64 {
+        self.samp_rate
+    }
+
+    /// Gets the power (in dB) at the bottom and top of the panel
+    pub fn range(&self) -> (f32, f32) {
+        self.range
+    }
+
+    /// Gets the frequency of the strongest bin near `freq`
+    pub fn peak_near(&self, freq: f64) -> Option<f64> {
+        if self.power.is_empty() {
+            return None;
+        }
+
+        let size = self.power.len();
+        let bin = frequency_bin(freq, size, self.samp_rate);
+        let first = bin.saturating_sub(PEAK_SEARCH_BINS);
+        let last = (bin + PEAK_SEARCH_BINS + 1).min(size);
+
+        let peak = (first..last).fold(bin, |best, x| {
+            if self.power[x] > self.power[best] { x } else { best }
+        });
+        Some(bin_frequency(peak, size, self.samp_rate))
+    }
+
+    pub fn draw(&self, frame: &mut Frame, viewport: Rect) {
+        if self.power.is_empty() {
+            return;
+        }
+
+        let params = DrawParameters {
+            viewport: Some(viewport),
+            .. Default::default()
+        };
+        let index_buffer = index::NoIndices(index::PrimitiveType::LineStrip);
+        let uniforms = |color: [f32; 3]| uniform! {
+            dt: 0.0_f32,
+            x_scale: 1.0_f32,
+            y_scale: 1.0_f32,
+            y_offset: 0.0_f32,
+            color: color,
+        };
+
+        let trace = self.vertex_buffer.slice(0..self.power.len()).unwrap();
+        frame.draw(trace, &index_buffer, &self.shaders, &uniforms(TRACE_COLOR), &params).unwrap();
+
+        let x = 2.0 * (self.filter_freq / self.samp_rate as f32 + 0.5);
+        self.marker.write(&[Vertex::new(x, -1.0), Vertex::new(x, 1.0)]);
+        frame.draw(&self.marker, &index_buffer, &self.shaders, &uniforms(MARKER_COLOR), &params)
+            .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use scope_app::test_util::TempCapture;
+
+    use super::*;
+
+    const SAMP_RATE: f64 = 1e6;
+
+    /// Writes a capture of a tone at `freq` that lasts `samples` samples
+    fn write_tone(name: &str, freq: f64, samples: usize) -> TempCapture {
+        let step = 2.0 * PI * (freq / SAMP_RATE) as f32;
+        let to_cs8 = |x: f32| (x * 100.0).round() as i8 as u8;
+        let data: Vec<_> = (0..samples).flat_map(|i| {
+            let phase = step * i as f32;
+            vec![to_cs8(phase.cos()), to_cs8(phase.sin())]
+        }).collect();
+        TempCapture::new(name, &data)
+    }
+
+    fn settings(averages: usize) -> SpectrumSettings {
+        SpectrumSettings { window: Window::Hann, size: 256, averages: averages }
+    }
+
+    #[test]
+    fn peaks_at_the_tone() {
+        let freq = bin_frequency(160, 256, SAMP_RATE);
+        let tone = write_tone("spectrum_tone", freq, 256 * 8);
+        let capture = CaptureSettings { samp_rate: SAMP_RATE, display_rate: SAMP_RATE };
+        let power = spectrum_at(&tone.0, capture, 0.001, settings(4)).unwrap();
+        let peak = (0..power.len()).fold(0, |best, x| {
+            if power[x] > power[best] { x } else { best }
+        });
+        assert_eq!(peak, 160);
+    }
+
+    #[test]
+    fn caps_the_number_of_averages() {
+        let tone = write_tone("spectrum_averages", 10e3, 256 * 2 * MAX_AVERAGES);
+        let capture = CaptureSettings { samp_rate: SAMP_RATE, display_rate: SAMP_RATE };
+        let time = 256.0 * MAX_AVERAGES as f64 / SAMP_RATE;
+        let capped = spectrum_at(&tone.0, capture, time, settings(MAX_AVERAGES));
+        let requested = spectrum_at(&tone.0, capture, time, settings(100000));
+
+        assert_eq!(capped.unwrap(), requested.unwrap());
+    }
+}
@@ -0,0 +1,26 @@
+//! Helpers shared by the tests of the scope's modules
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+
+/// A capture written to a temporary file, which is removed when dropped
+pub struct TempCapture(pub PathBuf);
+
+impl TempCapture {
+    /// Writes the interleaved I/Q bytes of a capture. `name` must be unique across the tests,
+    /// since they run at the same time.
+    pub fn new(name: &str, data: &[u8]) -> TempCapture {
+        let path = env::temp_dir().join(format!("viscope_{}_{}.bin", name, process::id()));
+        File::create(&path).unwrap().write_all(data).unwrap();
+        TempCapture(path)
+    }
+}
+
+impl Drop for TempCapture {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,119 @@
+use super::Channel;
+
+/// The event in the signal that the scope triggers on
+#[derive(Copy, Clone, PartialEq)]
+pub enum Condition {
+    /// The signal rising through the trigger level
+    Rising,
+
+    /// The signal falling through the trigger level
+    Falling,
+
+    /// A pulse above the trigger level lasting longer than the given number of seconds
+    WiderThan(f32),
+
+    /// A pulse above the trigger level lasting less than the given number of seconds
+    NarrowerThan(f32),
+}
+
+/// How the view follows the triggers as the signal plays
+#[derive(Copy, Clone, PartialEq)]
+pub enum Sweep {
+    /// Shows the latest trigger, or the signal at the playback position if there has been no
+    /// trigger within the width of the view
+    Auto,
+
+    /// Holds the latest trigger until the next one
+    Normal,
+
+    /// Stops playback at the next trigger
+    Single,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct TriggerSettings {
+    /// The channel the trigger condition is tested on
+    pub channel: Channel,
+    pub condition: Condition,
+    pub level: f32,
+    pub sweep: Sweep,
+
+    /// Where triggers are placed in the view, as a fraction of its width from the left
+    pub position: f32,
+}
+
+/// Finds the samples at which `condition` is met. Pulses trigger on their rising edge, so that the
+/// whole pulse is shown after the trigger position.
+pub fn find_triggers(samples: &[f32], samp_rate: f32, condition: Condition, level: f32)
+    -> Vec<usize>
+{
+    let mut triggers = vec![];
+
+    // The start of the pulse currently above the level, if its rising edge was seen
+    let mut rise = None;
+
+    for i in 1..samples.len() {
+        let (prev, x) = (samples[i - 1], samples[i]);
+
+        if prev < level && x >= level {
+            rise = Some(i);
+            if condition == Condition::Rising {
+                triggers.push(i);
+            }
+        }
+        else if prev >= level && x < level {
+            if condition == Condition::Falling {
+                triggers.push(i);
+            }
+
+            if let Some(start) = rise.take() {
+                let width = (i - start) as f32 / samp_rate;
+                match condition {
+                    Condition::WiderThan(min) if width > min => triggers.push(start),
+                    Condition::NarrowerThan(max) if width < max => triggers.push(start),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    triggers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two pulses at 1 S/s: samples 2 to 4 and 7 to 8 are above a level of 0.5
+    const PULSES: [f32; 10] = [0.0, 0.2, 0.8, 1.0, 0.6, 0.1, 0.0, 0.9, 0.7, 0.3];
+
+    #[test]
+    fn triggers_on_edges() {
+        assert_eq!(find_triggers(&PULSES, 1.0, Condition::Rising, 0.5), vec![2, 7]);
+        assert_eq!(find_triggers(&PULSES, 1.0, Condition::Falling, 0.5), vec![5, 9]);
+
+        // Reaching the level counts as crossing it, but staying on it doesn't trigger again
+        let flat = [0.0, 0.5, 0.5, 0.0];
+        assert_eq!(find_triggers(&flat, 1.0, Condition::Rising, 0.5), vec![1]);
+        assert_eq!(find_triggers(&flat, 1.0, Condition::Falling, 0.5), vec![3]);
+    }
+
+    #[test]
+    fn triggers_on_the_rising_edge_of_pulses_by_width() {
+        assert_eq!(find_triggers(&PULSES, 1.0, Condition::WiderThan(2.5), 0.5), vec![2]);
+        assert_eq!(find_triggers(&PULSES, 1.0, Condition::NarrowerThan(2.5), 0.5), vec![7]);
+
+        // Widths are in seconds, so they scale with the sample rate
+        assert_eq!(find_triggers(&PULSES, 10.0, Condition::WiderThan(0.25), 0.5), vec![2]);
+        assert!(find_triggers(&PULSES, 1.0, Condition::WiderThan(3.0), 0.5).is_empty());
+    }
+
+    #[test]
+    fn ignores_pulses_without_both_edges() {
+        // The signal starts above the level and ends before the second pulse falls
+        let samples = [0.9, 0.8, 0.1, 0.7, 0.8];
+        assert_eq!(find_triggers(&samples, 1.0, Condition::Falling, 0.5), vec![2]);
+        assert!(find_triggers(&samples, 1.0, Condition::WiderThan(0.0), 0.5).is_empty());
+        assert!(find_triggers(&samples, 1.0, Condition::NarrowerThan(10.0), 0.5).is_empty());
+    }
+}
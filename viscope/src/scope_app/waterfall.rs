@@ -0,0 +1,265 @@
+use std::f32;
+
+use std::path::Path;
+use std::rc::Rc;
+
+use std::sync::mpsc::{self, Receiver};
+
+use glium::{index, Surface, Display, Frame, Rect, DrawParameters};
+use glium::{VertexBuffer, Program};
+use glium::texture::{MipmapsOption, Texture2d, UncompressedFloatFormat};
+use glium::uniforms::MagnifySamplerFilter;
+
+use iq_converter::IqConverter;
+use iq_converter::goertzel::FilterSettings;
+use iq_converter::spectrum::{to_db, Spectra};
+
+use super::loader::{CaptureBytes, CaptureSettings, LoadJob};
+use super::renderer::Vertex;
+
+/// The number of frequency bins across the waterfall
+pub const WATERFALL_SIZE: usize = 512;
+
+/// The default range (in dB) of powers shown below the strongest
+pub const DEFAULT_DYNAMIC_RANGE: f32 = 60.0;
+
+/// The most rows in the waterfall, which limits its time resolution for long captures
+const MAX_ROWS: usize = 4096;
+
+/// The number of rows computed before they are sent to be drawn
+const ROW_BATCH: usize = 16;
+
+const LINE_COLOR: [f32; 3] = [0.0, 0.9, 0.9];
+
+/// A spectrogram being computed on a worker thread. Dropping the job stops the thread.
+struct Job {
+    work: LoadJob<()>,
+    rows: Receiver<Vec<Vec<f32>>>,
+}
+
+impl Job {
+    /// Computes rows of `WATERFALL_SIZE` bins in dB, each the peak power of `ffts_per_row`
+    /// consecutive FFTs so that pulses shorter than a row still show up. The rows are sent in
+    /// batches as they are computed, and a read error stops the job.
+    fn spawn(mut data: CaptureBytes, ffts_per_row: usize) -> Job {
+        let (sender, receiver) = mpsc::channel();
+
+        let work = LoadJob::spawn(move |progress| {
+            let settings = FilterSettings::new(WATERFALL_SIZE);
+
+            let mut batch = vec![];
+            let mut row = vec![0.0; WATERFALL_SIZE];
+            let mut count = 0;
+            for spectrum in Spectra::new(IqConverter::new(&mut data), settings) {
+                if progress.is_cancelled() {
+                    return Ok(());
+                }
+
+                for (peak, power) in row.iter_mut().zip(spectrum) {
+                    *peak = f32::max(*peak, power);
+                }
+                count += 1;
+
+                if count == ffts_per_row {
+                    batch.push(row.iter().map(|&x| to_db(x)).collect());
+                    row = vec![0.0; WATERFALL_SIZE];
+                    count = 0;
+                }
+                if batch.len() == ROW_BATCH {
+                    if sender.send(batch).is_err() {
+                        return Ok(());
+                    }
+                    batch = vec![];
+                }
+            }
+
+            try!(data.take_error());
+            if count > 0 {
+                batch.push(row.iter().map(|&x| to_db(x)).collect());
+            }
+            let _ = sender.send(batch);
+            Ok(())
+        });
+
+        Job {
+            work: work,
+            rows: receiver,
+        }
+    }
+}
+
+/// A panel showing the spectrogram of the capture over the same time as the scope trace
+pub struct WaterfallView {
+    shaders: Program,
+    line_shaders: Rc<Program>,
+    quad: VertexBuffer<Vertex>,
+    line: VertexBuffer<Vertex>,
+
+    texture: Option<Texture2d>,
+    job: Option<Job>,
+
+    /// The settings of the capture the waterfall is computed from
+    capture: Option<CaptureSettings>,
+
+    /// The number of rows computed so far, out of the height of the texture
+    rows: usize,
+    capacity: usize,
+
+    /// The time (in seconds) covered by each row
+    row_time: f64,
+
+    /// The strongest power (in dB) computed so far
+    peak: f32,
+}
+
+impl WaterfallView {
+    pub fn new(display: &Display, line_shaders: Rc<Program>) -> WaterfallView {
+        let shaders = program!(display,
+            140 => {
+                vertex: include_str!("../../shaders/vwaterfall.glsl"),
+                fragment: include_str!("../../shaders/fwaterfall.glsl"),
+            }
+        ).unwrap();
+
+        let corners = [Vertex::new(0.0, 0.0), Vertex::new(1.0, 0.0), Vertex::new(0.0, 1.0),
+            Vertex::new(1.0, 1.0)];
+        let line = [Vertex::new(0.0, 0.0), Vertex::new(2.0, 0.0)];
+
+        WaterfallView {
+            shaders: shaders,
+            line_shaders: line_shaders,
+            quad: VertexBuffer::new(display, &corners).unwrap(),
+            line: VertexBuffer::new(display, &line).unwrap(),
+            texture: None,
+            job: None,
+            capture: None,
+            rows: 0,
+            capacity: 0,
+            row_time: 0.0,
+            peak: f32::NEG_INFINITY,
+        }
+    }
+
+    /// Starts computing the waterfall of the capture at `path`, unless it has already been
+    /// started for the same capture settings
+    pub fn load(&mut self, display: &Display, path: &Path, capture: CaptureSettings)
+        -> Result<(), String>
+    {
+        if self.capture == Some(capture) {
+            return Ok(());
+        }
+
+        let data = try!(CaptureBytes::open(path));
+        let samples = data.samples();
+
+        let ffts = (samples / WATERFALL_SIZE).max(1);
+        let ffts_per_row = (ffts + MAX_ROWS - 1) / MAX_ROWS;
+        let capacity = (ffts + ffts_per_row - 1) / ffts_per_row;
+
+        let texture = try!(Texture2d::empty_with_format(display, UncompressedFloatFormat::F32,
+            MipmapsOption::NoMipmap, WATERFALL_SIZE as u32, capacity as u32)
+            .map_err(|e| format!("Unable to create the waterfall texture: {:?}", e)));
+
+        self.texture = Some(texture);
+        self.job = Some(Job::spawn(data, ffts_per_row));
+        self.capture = Some(capture);
+        self.rows = 0;
+        self.capacity = capacity;
+        self.row_time = (ffts_per_row * WATERFALL_SIZE) as f64 / capture.samp_rate;
+        self.peak = f32::NEG_INFINITY;
+
+        Ok(())
+    }
+
+    /// Adds any rows that have been computed since the last update to the texture, or returns the
+    /// error that stopped the computation
+    pub fn update(&mut self) -> Result<(), String> {
+        // Check for the end of the job first, so that every row sent before it is added below
+        let finished = self.job.as_ref().and_then(|job| job.work.poll());
+        if let Some(Err(e)) = finished {
+            // Forget the capture so that showing the waterfall again retries it
+            self.job = None;
+            self.capture = None;
+            return Err(e);
+        }
+
+        if let (Some(texture), Some(job)) = (self.texture.as_ref(), self.job.as_ref()) {
+            for batch in job.rows.try_iter() {
+                let height = batch.len().min(self.capacity - self.rows);
+                if height == 0 {
+                    continue;
+                }
+
+                for row in &batch[..height] {
+                    self.peak = row.iter().cloned().fold(self.peak, f32::max);
+                }
+
+                let rect = Rect {
+                    left: 0,
+                    bottom: self.rows as u32,
+                    width: WATERFALL_SIZE as u32,
+                    height: height as u32,
+                };
+                texture.write(rect, batch[..height].to_vec());
+                self.rows += height;
+            }
+        }
+
+        if finished.is_some() {
+            self.job = None;
+        }
+        Ok(())
+    }
+
+    /// Gets the fraction of the waterfall computed so far
+    pub fn progress(&self) -> f32 {
+        match self.capacity {
+            0 => 0.0,
+            _ => self.rows as f32 / self.capacity as f32,
+        }
+    }
+
+    /// Draws the waterfall over the `span` seconds from `start`, with a line at the playback
+    /// position `time` (in seconds), showing powers from `dynamic_range` dB below the strongest
+    pub fn draw(&self, start: f64, span: f64, time: f64, dynamic_range: f32, frame: &mut Frame,
+        viewport: Rect)
+    {
+        let texture = match self.texture {
+            Some(ref texture) if self.rows > 0 => texture,
+            _ => return,
+        };
+
+        let params = DrawParameters {
+            viewport: Some(viewport),
+            .. Default::default()
+        };
+
+        // The panel spans the same time as the scope trace, in texture coordinates
+        let height = self.capacity as f64 * self.row_time;
+        let uniforms = uniform! {
+            spectrogram: texture.sampled().magnify_filter(MagnifySamplerFilter::Nearest),
+            start: (start / height) as f32,
+            span: (span / height) as f32,
+            filled: self.progress(),
+            low: self.peak - dynamic_range,
+            high: self.peak,
+        };
+        frame.draw(&self.quad, &index::NoIndices(index::PrimitiveType::TriangleStrip),
+            &self.shaders, &uniforms, &params).unwrap();
+
+        // Time increases down the panel, so the line is hidden when playback is outside the view
+        let position = (time - start) / span;
+        if !(position >= 0.0 && position <= 1.0) {
+            return;
+        }
+        let uniforms = uniform! {
+            dt: 0.0_f32,
+            x_scale: 1.0_f32,
+            y_scale: 1.0_f32,
+            y_offset: (1.0 - 2.0 * position) as f32,
+            color: LINE_COLOR,
+        };
+        frame.draw(&self.line, &index::NoIndices(index::PrimitiveType::LineStrip),
+            &self.line_shaders, &uniforms, &params).unwrap();
+    }
+}
@@ -7,12 +7,17 @@ use glium::glutin::{VirtualKeyCode, ElementState, Event, MouseButton, MouseScrol
 use imgui::{ImGui, Ui};
 use imgui::glium_renderer::Renderer;
 
+/// The number of pixels scrolled by a touchpad that count as one line of a mouse wheel
+const PIXELS_PER_LINE: f32 = 20.0;
+
 /// A structure for managing the mouse state
 #[derive(Copy, Clone, Default)]
 pub struct Mouse {
     pub in_gui: bool,
     pub pos: (f32, f32),
     pub pressed: (bool, bool, bool),
+
+    /// The number of lines scrolled since the last frame
     pub wheel: f32,
 }
 
@@ -95,6 +100,9 @@ impl Window {
 
     /// Reads and handles all events received by the window
     pub fn update_events(&mut self) -> bool {
+        // Wheel movement only applies to the frame it happens in
+        self.mouse.wheel = 0.0;
+
         for event in self.display.poll_events() {
             match event {
                 Event::Closed => return false,
@@ -114,9 +122,11 @@ impl Window {
                 Event::MouseInput(state, MouseButton::Middle) =>
                     self.mouse.pressed.2 = state == ElementState::Pressed,
 
-                Event::MouseWheel(MouseScrollDelta::LineDelta(_, y), TouchPhase::Moved) |
+                Event::MouseWheel(MouseScrollDelta::LineDelta(_, y), TouchPhase::Moved) =>
+                    self.mouse.wheel += y,
+
                 Event::MouseWheel(MouseScrollDelta::PixelDelta(_, y), TouchPhase::Moved) =>
-                    self.mouse.wheel = y,
+                    self.mouse.wheel += y / PIXELS_PER_LINE,
 
                 Event::ReceivedCharacter(c) => self.imgui.add_input_character(c),
 